@@ -2,22 +2,34 @@
 mod test;
 
 use {
+    crate::{
+        error::{ InternalError, InternalResult, IntoInternal, },
+        escape::Escape,
+        filter::Filters,
+        template::Node,
+    },
     std::{
         collections::HashMap,
-        fmt::Debug,
+        fmt::{ self, Debug, },
+        fs::read_to_string,
+        sync::Arc,
     },
     std::path::{ Path, PathBuf, },
+    serde_json::Value,
 };
 
 #[derive(Debug, Default, Clone)]
 pub(crate) struct Variable {
     path: PathBuf,
     value: String,
+    /// Whether the value is markup rendered from a template, which is already
+    /// escaped.
+    markup: bool,
 }
 
 impl Variable {
     fn new<P: AsRef<Path>, V: AsRef<str>>(path: P, value: V) -> Self {
-        Self { path: path.as_ref().into(), value: value.as_ref().to_owned(), }
+        Self { path: path.as_ref().into(), value: value.as_ref().to_owned(), markup: false, }
     }
 
     pub(crate) fn value(&self) -> &String {
@@ -44,23 +56,143 @@ impl Variable {
     }
 }
 
+/// A named argument of a function and the value it takes when none is given.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Parameter {
+    name: String,
+    default: Option<String>,
+}
+
+impl Parameter {
+    pub(crate) fn new(name: String, default: Option<String>) -> Self {
+        Self { name, default, }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn default(&self) -> Option<&String> {
+        self.default.as_ref()
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Function {
-    args: Vec<String>,
-    content: String,
+    params: Vec<Parameter>,
+    content: Arc<[Node]>,
+    /// The functions of the file this one was imported from.
+    module: Arc<Vec<(String, Function)>>,
 }
 
 impl Function {
-    fn new(args: Vec<String>, content: String) -> Self {
-        Self { args, content, }
+    fn new(params: Vec<Parameter>, content: Arc<[Node]>) -> Self {
+        Self { params, content, module: Arc::default(), }
+    }
+
+    pub(crate) fn with_module(self, module: Arc<Vec<(String, Function)>>) -> Self {
+        Self { module, ..self }
+    }
+
+    pub(crate) fn module(&self) -> &[(String, Function)] {
+        &self.module
+    }
+
+    pub(crate) fn content(&self) -> &[Node] {
+        &self.content
+    }
+
+    pub(crate) fn params(&self) -> &Vec<Parameter> {
+        &self.params
+    }
+}
+
+/// The body of a named block and the path of the template defining it.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Block {
+    path: PathBuf,
+    content: Arc<[Node]>,
+}
+
+impl Block {
+    pub(crate) fn new<P: AsRef<Path>>(path: P, content: Arc<[Node]>) -> Self {
+        Self { path: path.as_ref().into(), content, }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn content(&self) -> &[Node] {
+        &self.content
+    }
+}
+
+/// A block being rendered, the definitions of the block from the most derived
+/// template to the base one and which of them is rendered.
+#[derive(Debug, Clone)]
+pub(crate) struct BlockFrame {
+    name: String,
+    blocks: Vec<Block>,
+    level: usize,
+}
+
+impl BlockFrame {
+    pub(crate) fn new(name: String, blocks: Vec<Block>) -> Self {
+        Self { name, blocks, level: 0, }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn block(&self) -> Option<&Block> {
+        self.blocks.get(self.level)
+    }
+
+    /// The frame of the definition the current one overrides.
+    pub(crate) fn parent(&self) -> Option<Self> {
+        self.blocks.get(self.level + 1)?;
+
+        Some(Self { level: self.level + 1, ..self.clone() })
+    }
+}
+
+/// An iteration of a loop with a context variable, written to the variables
+/// below the name of that variable while the iteration is parsed.
+#[derive(Debug, Clone)]
+pub(crate) struct LoopFrame {
+    name: String,
+    index: usize,
+    size: usize,
+    previous: Option<String>,
+    next: Option<String>,
+    keys: Vec<String>,
+    shadowed: Option<NativeFunction>,
+}
+
+impl LoopFrame {
+    pub(crate) fn new(name: String, index: usize, size: usize, previous: Option<String>, next: Option<String>) -> Self {
+        Self { name, index, size, previous, next, keys: Vec::new(), shadowed: None, }
     }
+}
+
+type NativeFn = dyn Fn(&[Option<String>]) -> InternalResult<String> + Send + Sync;
+
+/// A function implemented in Rust, called from a template with its argument
+/// values.
+#[derive(Clone)]
+pub(crate) struct NativeFunction(Arc<NativeFn>);
 
-    pub(crate) fn as_bytes(&self) -> &[u8] {
-        self.content.as_bytes()
+impl NativeFunction {
+    pub(crate) fn call(&self, args: &[Option<String>]) -> InternalResult<String> {
+        (self.0)(args)
     }
+}
 
-    pub(crate) fn args(&self) -> &Vec<String> {
-        &self.args
+impl Debug for NativeFunction {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str("NativeFunction")
     }
 }
 
@@ -68,9 +200,180 @@ impl Function {
 pub struct Context {
     variables: HashMap<String, Vec<Variable>>,
     functions: HashMap<String, Vec<Function>>,
+    native_functions: HashMap<String, NativeFunction>,
+    filters: Filters,
+    blocks: HashMap<String, Vec<Block>>,
+    block_frames: Vec<BlockFrame>,
+    loop_frames: Vec<LoopFrame>,
+    escape: Escape,
+    streaming: bool,
 }
 
 impl Context {
+    /// Create a context from a config file. See
+    /// [add_config_file](Context::add_config_file) for the format.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The [path](Path) to the config file.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Arcana, Context, };
+    ///
+    /// let ctx = Context::from_config_file("./resources/context/source_from_file_1/config.cfg")
+    ///     .unwrap();
+    /// let mut output = Vec::<u8>::new();
+    /// Arcana::compile_with_ctx("{{ name }} is {{ age }}", &mut output, ctx).unwrap();
+    /// assert_eq!("First Test is 42", String::from_utf8(output).unwrap());
+    /// ```
+    ///
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> InternalResult<Self> {
+        let mut context = Self::default();
+        context.add_config_file(path)?;
+
+        Ok(context)
+    }
+
+    /// Add the variables of a config file to context.
+    ///
+    /// Each line holds a `key=value` pair, a line without a `=` sets the key
+    /// to an empty value. Keys and values are trimmed of surrounding
+    /// whitespace. Repeating a key adds another value to the variable, as
+    /// repeated `set` tags do. A `[section]` line prefixes the keys that follow
+    /// it with `section.` and lines starting with `#` or `;` are comments.
+    ///
+    /// Relative path values are resolved against the directory of the config
+    /// file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The [path](Path) to the config file.
+    ///
+    pub fn add_config_file<P: AsRef<Path>>(&mut self, path: P) -> InternalResult<()> {
+        let path = path.as_ref();
+        let content = read_to_string(path)
+            .into_internal(format!("Failed to read config file {path:?}"))?;
+
+        let mut section = String::new();
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']')
+                    .into_internal(format!(
+                        "Unclosed section on line {} of config file {path:?}", line_no + 1
+                    ))?
+                    .trim();
+
+                section = if name.is_empty() {
+                    String::new()
+                }
+                else {
+                    format!("{name}.")
+                };
+
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').unwrap_or((line, ""));
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(InternalError::new(format!(
+                    "Empty key on line {} of config file {path:?}", line_no + 1
+                )));
+            }
+
+            self.add_variable(format!("{section}{key}"), path, value.trim());
+        }
+
+        Ok(())
+    }
+
+    /// Add the values of a JSON document to context under a prefix. Object
+    /// keys are joined to the prefix with dots, i.e. `prefix.author.name`.
+    /// Each element of an array is added as another value of the same
+    /// variable, so that objects within an array share their keys, i.e.
+    /// `prefix.posts.title` holds the title of every post. `null` becomes an
+    /// empty value.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The name the document is stored under, may be empty when
+    ///   the document is an object.
+    /// * `path` - The [path](Path) relative path values are resolved against.
+    /// * `json` - The JSON document.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Arcana, Context, };
+    ///
+    /// let mut ctx = Context::default();
+    /// ctx.merge_json("site", "./", r#"{ "author": { "name": "A" }, "tags": [ "x", "y" ] }"#)
+    ///     .unwrap();
+    /// let input = "{{ site.author.name }}:{% foreach tag in site.tags %}{{ tag }}{% /foreach %}";
+    /// let mut output = Vec::<u8>::new();
+    /// Arcana::compile_with_ctx(input, &mut output, ctx).unwrap();
+    /// assert_eq!("A:xy", String::from_utf8(output).unwrap());
+    /// ```
+    ///
+    pub fn merge_json<K, P, S>(&mut self, prefix: K, path: P, json: S) -> InternalResult<()>
+    where
+        K: AsRef<str>,
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let value = serde_json::from_str::<Value>(json.as_ref())
+            .into_internal(format!("Invalid JSON in {:?}", path.as_ref()))?;
+
+        self.merge_value(prefix, path, &value)
+    }
+
+    pub(crate) fn merge_value<K, P>(&mut self, prefix: K, path: P, value: &Value) -> InternalResult<()>
+    where
+        K: AsRef<str>,
+        P: AsRef<Path>,
+    {
+        if prefix.as_ref().is_empty() && !value.is_object() {
+            return Err(InternalError::new("Only an object can be merged without a prefix"));
+        }
+
+        self.merge_json_value(prefix.as_ref(), path.as_ref(), value);
+
+        Ok(())
+    }
+
+    fn merge_json_value(&mut self, key: &str, path: &Path, value: &Value) {
+        match value {
+            Value::Object(object) => {
+                for (name, value) in object.iter() {
+                    let key = if key.is_empty() {
+                        name.to_owned()
+                    }
+                    else {
+                        format!("{key}.{name}")
+                    };
+
+                    self.merge_json_value(&key, path, value);
+                }
+            },
+            Value::Array(array) => {
+                for value in array.iter() {
+                    self.merge_json_value(key, path, value);
+                }
+            },
+            Value::String(value) => self.add_variable(key, path, value),
+            Value::Null => self.add_variable(key, path, ""),
+            value => self.add_variable(key, path, value.to_string()),
+        }
+    }
+
     fn variables<K: AsRef<str>>(&self, key: K) -> Option<&Vec<Variable>> {
         self.variables.get(key.as_ref())
     }
@@ -83,6 +386,12 @@ impl Context {
         self.variables(key)?.last().map(|l| l.value())
     }
 
+    /// Whether the latest value of a variable is markup rendered from a
+    /// template.
+    pub(crate) fn is_markup<K: AsRef<str>>(&self, key: K) -> bool {
+        self.variables(key).and_then(|variables| variables.last()).is_some_and(|v| v.markup)
+    }
+
     pub(crate) fn path<K: AsRef<str>>(&self, key: K) -> Option<PathBuf> {
         let variables = self.variables(key)?;
         Some(variables.last().unwrap().value_as_path())
@@ -100,6 +409,16 @@ impl Context {
         self.variables_mut(key.as_ref()).unwrap().push(Variable::new(path, value));
     }
 
+    /// Add a variable holding markup rendered from a template, i.e. the
+    /// content of a 'set' tag. Output tags write it as is, its values having
+    /// been escaped as they were rendered.
+    pub(crate) fn add_markup<K: AsRef<str>, P: AsRef<Path>, V: AsRef<str>>(&mut self, key: K, path: P, value: V) {
+        self.add_variable(key.as_ref(), path, value);
+        if let Some(variable) = self.variables_mut(key).and_then(|variables| variables.last_mut()) {
+            variable.markup = true;
+        }
+    }
+
     pub(crate) fn remove_variable<K: AsRef<str>>(&mut self, key: K) -> Option<Vec<Variable>> {
         if self.variables(key.as_ref()).is_some() {
             return self.variables.remove(key.as_ref());
@@ -123,15 +442,235 @@ impl Context {
         None
     }
 
+    /// Overwrite the latest value of a variable instead of masking it, or
+    /// add the value if the variable has none.
+    pub(crate) fn replace_variable<K: AsRef<str>, P: AsRef<Path>, V: AsRef<str>>(&mut self, key: K, path: P, value: V) {
+        self.pop_variable(key.as_ref());
+        self.add_variable(key, path, value);
+    }
+
+    /// Like [replace_variable](Self::replace_variable), for markup rendered
+    /// from a template.
+    pub(crate) fn replace_markup<K: AsRef<str>, P: AsRef<Path>, V: AsRef<str>>(&mut self, key: K, path: P, value: V) {
+        self.pop_variable(key.as_ref());
+        self.add_markup(key, path, value);
+    }
+
+    pub(crate) fn escape(&self) -> Escape {
+        self.escape
+    }
+
+    /// Set how values written by `{{ }}` output tags and the `include` tag are
+    /// escaped, [html](Escape::Html) by default. The raw forms, `{{{ }}}` and `include ... raw`, always write
+    /// values verbatim.
+    pub fn set_escape(&mut self, escape: Escape) {
+        self.escape = escape;
+    }
+
+    pub(crate) fn streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// Write output as soon as it is final instead of once the whole template
+    /// is compiled. Only the content of a template which extends another and
+    /// the bodies of tags which capture their output are kept in memory. An
+    /// `extend` tag must come before any output of the template when
+    /// streaming.
+    pub fn set_streaming(&mut self, streaming: bool) {
+        self.streaming = streaming;
+    }
+
     pub(crate) fn function<K: AsRef<str>>(&self, key: K) -> Option<&Function> {
         self.functions.get(key.as_ref()).and_then(|fns| fns.last())
     }
 
-    pub(crate) fn add_function(&mut self, name: String, args: Vec<String>, content: String) {
+    pub(crate) fn add_function(&mut self, name: String, params: Vec<Parameter>, content: Arc<[Node]>) {
+        self.import_function(name, Function::new(params, content));
+    }
+
+    pub(crate) fn import_function(&mut self, name: String, function: Function) {
         if !self.functions.contains_key(&name) {
             self.functions.insert(name.to_owned(), Vec::new());
         }
 
-        self.functions.get_mut(&name).unwrap().push(Function::new(args, content));
+        self.functions.get_mut(&name).unwrap().push(function);
+    }
+
+    /// The latest definition of every function.
+    pub(crate) fn functions(&self) -> impl Iterator<Item = (&String, &Function)> {
+        self.functions.iter().filter_map(|(name, fns)| fns.last().map(|function| (name, function)))
+    }
+
+    pub(crate) fn clear_functions(&mut self) {
+        self.functions.clear();
+    }
+
+    /// The definitions of a block by the templates extending the current one,
+    /// the most derived first.
+    pub(crate) fn blocks<K: AsRef<str>>(&self, key: K) -> Vec<Block> {
+        self.blocks.get(key.as_ref()).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn add_block(&mut self, name: String, block: Block) {
+        self.blocks.entry(name).or_default().push(block);
+    }
+
+    pub(crate) fn clear_blocks(&mut self) {
+        self.blocks.clear();
+        self.block_frames.clear();
+    }
+
+    pub(crate) fn block_frame(&self) -> Option<&BlockFrame> {
+        self.block_frames.last()
+    }
+
+    pub(crate) fn push_block_frame(&mut self, frame: BlockFrame) {
+        self.block_frames.push(frame);
+    }
+
+    pub(crate) fn pop_block_frame(&mut self) -> Option<BlockFrame> {
+        self.block_frames.pop()
+    }
+
+    /// Write the context of a loop iteration, along with the context of the
+    /// enclosing loop as its parent.
+    pub(crate) fn push_loop_frame<P: AsRef<Path>>(&mut self, path: P, mut frame: LoopFrame) {
+        let mut values = vec![
+            ("index".to_owned(), frame.index.to_string()),
+            ("index1".to_owned(), (frame.index + 1).to_string()),
+            ("revindex".to_owned(), (frame.size - frame.index - 1).to_string()),
+            ("size".to_owned(), frame.size.to_string()),
+            ("isfirst".to_owned(), if frame.index == 0 { "1" } else { "0" }.to_owned()),
+            ("islast".to_owned(), if frame.index + 1 == frame.size { "1" } else { "0" }.to_owned()),
+        ];
+
+        if let Some(previous) = frame.previous.take() {
+            values.push(("previous".to_owned(), previous));
+        }
+
+        if let Some(next) = frame.next.take() {
+            values.push(("next".to_owned(), next));
+        }
+
+        if let Some(parent) = self.loop_frames.last() {
+            for key in parent.keys.iter() {
+                if let Some(value) = self.value(format!("{}.{key}", parent.name)) {
+                    values.push((format!("parent.{key}"), value.to_owned()));
+                }
+            }
+        }
+
+        for (key, value) in values {
+            self.add_variable(format!("{}.{key}", frame.name), &path, value);
+            frame.keys.push(key);
+        }
+
+        let index = frame.index;
+        frame.shadowed = self.native_functions.insert(
+            format!("{}.cycle", frame.name),
+            NativeFunction(Arc::new(move |args: &[Option<String>]| {
+                if args.is_empty() {
+                    return Err(InternalError::new("The 'cycle' function of a loop takes at least 1 argument"));
+                }
+
+                Ok(args[index % args.len()].clone().unwrap_or_default())
+            })),
+        );
+
+        self.loop_frames.push(frame);
+    }
+
+    pub(crate) fn pop_loop_frame(&mut self) -> Option<LoopFrame> {
+        let mut frame = self.loop_frames.pop()?;
+
+        for key in frame.keys.iter() {
+            self.pop_variable(format!("{}.{key}", frame.name));
+        }
+
+        let cycle = format!("{}.cycle", frame.name);
+        match frame.shadowed.take() {
+            Some(shadowed) => self.native_functions.insert(cycle, shadowed),
+            None => self.native_functions.remove(&cycle),
+        };
+
+        Some(frame)
+    }
+
+    pub(crate) fn native_function<K: AsRef<str>>(&self, key: K) -> Option<&NativeFunction> {
+        self.native_functions.get(key.as_ref())
+    }
+
+    /// Register a Rust function callable from templates like any other
+    /// function, i.e. `{{ name(a, b) }}`. It receives the values of the
+    /// arguments, `None` for undefined variables, and its result is written
+    /// like the value of a variable. Functions defined within a template take
+    /// precedence over a native function of the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the function is called by.
+    /// * `function` - The function.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Arcana, Context, InternalError, };
+    ///
+    /// let mut ctx = Context::default();
+    /// ctx.register_native_function("double", |args| {
+    ///     let value = args.first()
+    ///         .and_then(|a| a.as_ref())
+    ///         .and_then(|a| a.parse::<i64>().ok())
+    ///         .ok_or_else(|| InternalError::new("Expected a number"))?;
+    ///     Ok((value * 2).to_string())
+    /// });
+    /// let mut output = Vec::<u8>::new();
+    /// Arcana::compile_with_ctx("{{ double(21) }}", &mut output, ctx).unwrap();
+    /// assert_eq!("42", String::from_utf8(output).unwrap());
+    /// ```
+    ///
+    pub fn register_native_function<K, F>(&mut self, name: K, function: F)
+    where
+        K: AsRef<str>,
+        F: Fn(&[Option<String>]) -> InternalResult<String> + Send + Sync + 'static,
+    {
+        self.native_functions.insert(name.as_ref().to_owned(), NativeFunction(Arc::new(function)));
+    }
+
+    pub(crate) fn filters(&self) -> &Filters {
+        &self.filters
+    }
+
+    /// Register a Rust filter which can be chained after any value like the
+    /// built-in filters, i.e. `{{ name | shout("!") }}`. It receives the
+    /// value, `None` when undefined, and the values of its arguments. A
+    /// registered filter replaces the built-in filter of the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the filter is used by.
+    /// * `filter` - The filter.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Arcana, Context, };
+    ///
+    /// let mut ctx = Context::default();
+    /// ctx.register_filter("shout", |value, args| {
+    ///     let suffix = args.first().cloned().flatten().unwrap_or_default();
+    ///     Ok(value.map(|v| format!("{}{suffix}", v.to_uppercase())))
+    /// });
+    /// let mut output = Vec::<u8>::new();
+    /// Arcana::compile_with_ctx("{{ \"hi\" | shout(\"!\") }}", &mut output, ctx).unwrap();
+    /// assert_eq!("HI!", String::from_utf8(output).unwrap());
+    /// ```
+    ///
+    pub fn register_filter<K, F>(&mut self, name: K, filter: F)
+    where
+        K: AsRef<str>,
+        F: Fn(Option<String>, &[Option<String>]) -> InternalResult<Option<String>> + Send + Sync + 'static,
+    {
+        self.filters.register(name, filter);
     }
 }
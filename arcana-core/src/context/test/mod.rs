@@ -24,3 +24,34 @@ fn parse_2() {
     assert_eq!("4", ctx.value("main.list").unwrap());
     assert_eq!(&"3", ctx.values("main.list").unwrap().get(2).unwrap());
 }
+
+#[test]
+fn source_from_file_3() {
+    let path = "./resources/context/source_from_file_3/config.cfg";
+    let ctx = Context::from_config_file(path).unwrap();
+
+    assert_eq!("Arcana", ctx.value("title").unwrap());
+    assert_eq!(vec!["one", "two"], ctx.values("tag").unwrap());
+    assert_eq!("A Test", ctx.value("author.name").unwrap());
+    assert_eq!("", ctx.value("author.draft").unwrap());
+    assert!(ctx.value("name").is_none());
+    assert_eq!(
+        std::path::PathBuf::from("./resources/context/source_from_file_3/assets/logo.txt"),
+        ctx.path("paths.logo").unwrap()
+    );
+}
+
+#[test]
+fn source_from_file_4() {
+    assert!(Context::from_config_file("./resources/context/missing.cfg").is_err());
+}
+
+#[test]
+fn replace_variable_1() {
+    let mut ctx = Context::default();
+    ctx.replace_variable("x", "./", "a");
+    ctx.add_variable("x", "./", "b");
+    ctx.replace_variable("x", "./", "c");
+
+    assert_eq!(vec![ "a", "c", ], ctx.values("x").unwrap());
+}
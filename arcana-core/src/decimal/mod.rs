@@ -0,0 +1,261 @@
+#[cfg(test)]
+mod test;
+
+use {
+    crate::error::{ InternalError, InternalResult, },
+    std::{ cmp::Ordering, fmt, str::FromStr, },
+};
+
+/// The number of decimal places a division is carried out to when no
+/// precision is given.
+pub(crate) const DIVISION_SCALE: u32 = 18;
+
+/// The largest precision the arithmetic tags accept.
+pub(crate) const MAX_PRECISION: u32 = 28;
+
+/// A fixed-point decimal number, `mantissa * 10^-scale`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+fn overflow() -> InternalError {
+    InternalError::new("Arithmetic operation caused an overflow")
+}
+
+fn division_by_zero() -> InternalError {
+    InternalError::new("Division by zero")
+}
+
+fn pow10(exp: u32) -> InternalResult<i128> {
+    10_i128.checked_pow(exp).ok_or_else(overflow)
+}
+
+/// Divide rounding half away from zero.
+fn div_round(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+
+    if remainder.unsigned_abs() * 2 >= denominator.unsigned_abs() {
+        if (numerator < 0) == (denominator < 0) { quotient + 1 } else { quotient - 1 }
+    }
+    else {
+        quotient
+    }
+}
+
+impl Decimal {
+    fn new(mantissa: i128, scale: u32) -> Self {
+        Self { mantissa, scale, }
+    }
+
+    /// Whether the number was written without decimal places.
+    pub(crate) fn is_whole(&self) -> bool {
+        self.scale == 0
+    }
+
+    fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    fn with_scale(self, scale: u32) -> InternalResult<Self> {
+        if scale <= self.scale {
+            return Ok(self);
+        }
+
+        let mantissa = self.mantissa.checked_mul(pow10(scale - self.scale)?)
+            .ok_or_else(overflow)?;
+
+        Ok(Self::new(mantissa, scale))
+    }
+
+    /// Both numbers at the larger of their scales.
+    fn aligned(self, other: Self) -> InternalResult<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+
+        Ok((self.with_scale(scale)?.mantissa, other.with_scale(scale)?.mantissa, scale))
+    }
+
+    /// Drop trailing zeros after the decimal point.
+    pub(crate) fn normalize(mut self) -> Self {
+        while self.scale > 0 && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+
+        self
+    }
+
+    /// Round half away from zero to exactly `places` decimal places.
+    pub(crate) fn round(self, places: u32) -> InternalResult<Self> {
+        if places >= self.scale {
+            return self.with_scale(places);
+        }
+
+        let mantissa = div_round(self.mantissa, pow10(self.scale - places)?);
+
+        Ok(Self::new(mantissa, places))
+    }
+
+    pub(crate) fn neg(self) -> InternalResult<Self> {
+        Ok(Self::new(self.mantissa.checked_neg().ok_or_else(overflow)?, self.scale))
+    }
+
+    pub(crate) fn add(self, other: Self) -> InternalResult<Self> {
+        let (a, b, scale) = self.aligned(other)?;
+
+        Ok(Self::new(a.checked_add(b).ok_or_else(overflow)?, scale))
+    }
+
+    pub(crate) fn sub(self, other: Self) -> InternalResult<Self> {
+        let (a, b, scale) = self.aligned(other)?;
+
+        Ok(Self::new(a.checked_sub(b).ok_or_else(overflow)?, scale))
+    }
+
+    pub(crate) fn mul(self, other: Self) -> InternalResult<Self> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa).ok_or_else(overflow)?;
+
+        Ok(Self::new(mantissa, self.scale + other.scale))
+    }
+
+    /// Divide, rounding to at most `places` decimal places.
+    pub(crate) fn div(self, other: Self, places: u32) -> InternalResult<Self> {
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+
+        let (a, b, _) = self.aligned(other)?;
+        let numerator = a.checked_mul(pow10(places)?).ok_or_else(overflow)?;
+
+        Ok(Self::new(div_round(numerator, b), places).normalize())
+    }
+
+    /// Divide whole numbers as whole numbers, dropping any remainder, and
+    /// others to at most [`DIVISION_SCALE`] decimal places.
+    pub(crate) fn quotient(self, other: Self) -> InternalResult<Self> {
+        if self.is_whole() && other.is_whole() {
+            self.div_whole(other)
+        }
+        else {
+            self.div(other, DIVISION_SCALE)
+        }
+    }
+
+    /// Divide, dropping any remainder.
+    pub(crate) fn div_whole(self, other: Self) -> InternalResult<Self> {
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+
+        let (a, b, _) = self.aligned(other)?;
+
+        Ok(Self::new(a.checked_div(b).ok_or_else(overflow)?, 0))
+    }
+
+    /// The remainder of a division, taking the sign of this number.
+    pub(crate) fn rem(self, other: Self) -> InternalResult<Self> {
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+
+        let (a, b, scale) = self.aligned(other)?;
+
+        Ok(Self::new(a.checked_rem(b).ok_or_else(overflow)?, scale))
+    }
+
+    /// Raise to a whole exponent, a negative exponent divides to `places`
+    /// decimal places.
+    pub(crate) fn pow(self, exponent: Self, places: u32) -> InternalResult<Self> {
+        let exponent = exponent.normalize();
+        if !exponent.is_whole() {
+            return Err(InternalError::new("Exponent must be a whole number"));
+        }
+
+        let magnitude = u32::try_from(exponent.mantissa.unsigned_abs())
+            .map_err(|_| overflow())?;
+
+        let mantissa = self.mantissa.checked_pow(magnitude).ok_or_else(overflow)?;
+        let scale = self.scale.checked_mul(magnitude).ok_or_else(overflow)?;
+        let power = Self::new(mantissa, scale);
+
+        if exponent.mantissa < 0 {
+            Self::new(1, 0).div(power, places)
+        }
+        else {
+            Ok(power)
+        }
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = InternalError;
+
+    fn from_str(s: &str) -> InternalResult<Self> {
+        let invalid = || InternalError::new(format!("'{s}' is not a number"));
+
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (digits, ""),
+        };
+
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut mantissa = 0_i128;
+        for c in whole.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10).ok_or_else(invalid)?;
+            mantissa = mantissa.checked_mul(10)
+                .and_then(|m| m.checked_add(digit as i128))
+                .ok_or_else(overflow)?;
+        }
+
+        let scale = u32::try_from(fraction.len()).map_err(|_| overflow())?;
+
+        Ok(Self::new(if negative { -mantissa } else { mantissa }, scale))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+
+        if self.mantissa < 0 {
+            fmtr.write_str("-")?;
+        }
+
+        fmtr.write_str(whole)?;
+
+        if !fraction.is_empty() {
+            fmtr.write_str(".")?;
+            fmtr.write_str(fraction)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Decimal {
+    /// None when the numbers cannot be brought to the same scale.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let (a, b, _) = self.aligned(*other).ok()?;
+
+        Some(a.cmp(&b))
+    }
+}
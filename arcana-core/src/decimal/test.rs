@@ -0,0 +1,61 @@
+use super::Decimal;
+
+fn dec(s: &str) -> Decimal {
+    s.parse::<Decimal>().unwrap()
+}
+
+#[test]
+fn from_str_1() {
+    assert_eq!("1.50", dec("1.50").to_string());
+    assert_eq!("-0.05", dec("-.05").to_string());
+    assert_eq!("42", dec("+42").to_string());
+    assert!("".parse::<Decimal>().is_err());
+    assert!("1.2.3".parse::<Decimal>().is_err());
+    assert!("1e5".parse::<Decimal>().is_err());
+}
+
+#[test]
+fn arithmetic_1() {
+    assert_eq!("3.00", dec("1.50").add(dec("1.5")).unwrap().to_string());
+    assert_eq!("-0.25", dec("1").sub(dec("1.25")).unwrap().to_string());
+    assert_eq!("2.250", dec("1.5").mul(dec("1.50")).unwrap().to_string());
+    assert_eq!("1.5", dec("3").rem(dec("-1.5").add(dec("3")).unwrap()).unwrap().add(dec("1.5")).unwrap().normalize().to_string());
+    assert_eq!("-1", dec("-7").rem(dec("2")).unwrap().to_string());
+}
+
+#[test]
+fn div_1() {
+    assert_eq!("3.5", dec("7").div(dec("2"), 18).unwrap().to_string());
+    assert_eq!("0.333333333333333333", dec("1").div(dec("3"), 18).unwrap().to_string());
+    assert_eq!("0.67", dec("2").div(dec("3"), 2).unwrap().to_string());
+    assert_eq!("3", dec("7").div_whole(dec("2")).unwrap().to_string());
+    assert_eq!("Division by zero", dec("1").div(dec("0.0"), 2).unwrap_err().message());
+    assert_eq!("Division by zero", dec("1").div_whole(dec("0")).unwrap_err().message());
+    assert_eq!("Division by zero", dec("1").rem(dec("0")).unwrap_err().message());
+}
+
+#[test]
+fn round_1() {
+    assert_eq!("2.35", dec("2.345").round(2).unwrap().to_string());
+    assert_eq!("-2.35", dec("-2.345").round(2).unwrap().to_string());
+    assert_eq!("2.34", dec("2.3449").round(2).unwrap().to_string());
+    assert_eq!("3", dec("2.5").round(0).unwrap().to_string());
+    assert_eq!("2.500", dec("2.5").round(3).unwrap().to_string());
+}
+
+#[test]
+fn pow_1() {
+    assert_eq!("2.25", dec("1.5").pow(dec("2"), 18).unwrap().to_string());
+    assert_eq!("-8", dec("-2").pow(dec("3"), 18).unwrap().to_string());
+    assert_eq!("0.125", dec("2").pow(dec("-3"), 18).unwrap().to_string());
+    assert_eq!("1", dec("5").pow(dec("0"), 18).unwrap().to_string());
+    assert!(dec("2").pow(dec("0.5"), 18).is_err());
+    assert!(dec("10").pow(dec("40"), 18).is_err());
+}
+
+#[test]
+fn cmp_1() {
+    assert!(dec("1.10") == dec("1.1"));
+    assert!(dec("10") > dec("9.99"));
+    assert!(dec("-1") < dec("0.5"));
+}
@@ -0,0 +1,227 @@
+#[cfg(test)]
+mod test;
+
+use {
+    crate::error::{ InternalError, InternalResult, IntoInternal, },
+    std::{
+        cmp::Ordering,
+        io,
+        iter::Peekable,
+        path::{ Path, PathBuf, },
+        str::FromStr,
+    },
+};
+
+/// The order the entries of a directory are looped through in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Sort {
+    /// By path.
+    #[default]
+    Name,
+    /// By modification time, oldest first.
+    Mtime,
+    /// By size in bytes, smallest first.
+    Size,
+    /// By path, comparing runs of digits by their value so `2` comes before
+    /// `10`.
+    Natural,
+}
+
+impl FromStr for Sort {
+    type Err = InternalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "mtime" => Ok(Self::Mtime),
+            "size" => Ok(Self::Size),
+            "natural" => Ok(Self::Natural),
+            s => Err(InternalError::new(format!(
+                "Unknown sort '{s}', expected one of name, mtime, size or natural"
+            ))),
+        }
+    }
+}
+
+/// Which entries of a directory the `fordir` and `forfile` tags loop through
+/// and in what order.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EntryOptions {
+    sort: Sort,
+    matching: Option<String>,
+    recursive: bool,
+    hidden: bool,
+}
+
+impl EntryOptions {
+    pub(crate) fn set_sort(&mut self, sort: Sort) {
+        self.sort = sort;
+    }
+
+    /// Only keep entries whose name matches a glob pattern.
+    pub(crate) fn set_matching(&mut self, pattern: String) {
+        self.matching = Some(pattern);
+    }
+
+    /// Also read the directories within the directory, and so on.
+    pub(crate) fn set_recursive(&mut self, recursive: bool) {
+        self.recursive = recursive;
+    }
+
+    /// Keep entries whose name starts with a `.`.
+    pub(crate) fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    /// The directories, or files, within dir. Every directory read along the
+    /// way is added to read.
+    pub(crate) fn read(&self, dir: &Path, directories: bool, read: &mut Vec<PathBuf>) -> InternalResult<Vec<PathBuf>> {
+        let mut entries = Vec::new();
+        self.walk(dir, directories, read, &mut entries)?;
+
+        match self.sort {
+            Sort::Name => entries.sort_unstable(),
+            Sort::Natural => entries.sort_unstable_by(|a, b| natural_cmp(
+                &a.to_string_lossy(), &b.to_string_lossy()
+            )),
+            Sort::Mtime => entries = sort_by_key(entries, |path| {
+                path.metadata()?.modified()
+            })?,
+            Sort::Size => entries = sort_by_key(entries, |path| {
+                Ok(path.metadata()?.len())
+            })?,
+        }
+
+        Ok(entries)
+    }
+
+    fn walk(&self, dir: &Path, directories: bool, read: &mut Vec<PathBuf>, entries: &mut Vec<PathBuf>) -> InternalResult<()> {
+        read.push(dir.to_owned());
+
+        let paths = dir.read_dir()
+            .into_internal("Failed to read directory")?
+            .map(|direntry_res| direntry_res.map(|de| de.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()
+            .into_internal("Failed to read paths of directory entries")?;
+
+        for path in paths {
+            let name = path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            if !self.hidden && name.starts_with('.') {
+                continue;
+            }
+
+            let is_match = if directories { path.is_dir() } else { path.is_file() };
+            if is_match && self.matching.as_ref().is_none_or(|pattern| glob_matches(pattern, &name)) {
+                entries.push(path.to_owned());
+            }
+
+            // symbolic links aren't followed so that a link to a parent
+            // doesn't walk forever
+            if self.recursive && path.is_dir() && !path.is_symlink() {
+                self.walk(&path, directories, read, entries)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn sort_by_key<K, F>(entries: Vec<PathBuf>, key: F) -> InternalResult<Vec<PathBuf>>
+where
+    K: Ord,
+    F: Fn(&Path) -> io::Result<K>,
+{
+    let mut keyed = entries.into_iter()
+        .map(|path| key(&path).map(|key| (key, path)))
+        .collect::<io::Result<Vec<(K, PathBuf)>>>()
+        .into_internal("Failed to read metadata of directory entries")?;
+
+    keyed.sort_unstable_by(|(a, a_path), (b, b_path)| a.cmp(b).then_with(|| a_path.cmp(b_path)));
+
+    Ok(keyed.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Whether name matches a glob pattern, where `*` matches any number of
+/// characters and `?` matches exactly one.
+pub(crate) fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+
+    let (mut p, mut n) = (0, 0);
+    // the position of the last '*' and of the name when it was reached
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some('?') => {
+                p += 1;
+                n += 1;
+            },
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                // let the last '*' match one more character
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Compare two strings, comparing runs of digits by their value.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a_chars);
+                let y = take_digits(&mut b_chars);
+
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+
+                a_chars.next();
+                b_chars.next();
+            },
+        }
+    }
+}
+
+/// The next run of digits, without leading zeros.
+fn take_digits<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        if !(digits.is_empty() && c == '0') {
+            digits.push(c);
+        }
+    }
+
+    digits
+}
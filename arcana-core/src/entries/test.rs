@@ -0,0 +1,66 @@
+use {
+    super::{ EntryOptions, glob_matches, natural_cmp, Sort, },
+    std::{ cmp::Ordering, path::{ Path, PathBuf, }, },
+};
+
+#[test]
+fn glob_matches_1() {
+    assert!(glob_matches("*.md", "post.md"));
+    assert!(glob_matches("*.md", ".md"));
+    assert!(!glob_matches("*.md", "post.mdx"));
+    assert!(glob_matches("p?st*", "post.md"));
+    assert!(glob_matches("*a*b", "xaxxab"));
+    assert!(!glob_matches("*a*b", "xaxxa"));
+    assert!(glob_matches("*", ""));
+}
+
+#[test]
+fn natural_cmp_1() {
+    assert_eq!(Ordering::Less, natural_cmp("2.fred", "10.mark"));
+    assert_eq!(Ordering::Greater, natural_cmp("b1", "a2"));
+    assert_eq!(Ordering::Less, natural_cmp("a02", "a3"));
+    assert_eq!(Ordering::Less, natural_cmp("a", "a1"));
+    assert_eq!(Ordering::Equal, natural_cmp("a1", "a1"));
+}
+
+#[test]
+fn sort_1() {
+    assert_eq!(Sort::Mtime, "mtime".parse::<Sort>().unwrap());
+    assert!("date".parse::<Sort>().is_err());
+}
+
+#[test]
+fn read_1() {
+    let dir = Path::new("./resources/entries/read_1");
+    let names = |entries: Vec<PathBuf>| entries.iter()
+        .map(|path| path.strip_prefix(dir).unwrap().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+
+    let mut options = EntryOptions::default();
+    let mut read = Vec::new();
+    assert_eq!(
+        vec!["10.md", "2.md", "a.txt"],
+        names(options.read(dir, false, &mut read).unwrap()),
+    );
+    assert_eq!(vec![dir.to_owned()], read);
+
+    options.set_sort(Sort::Natural);
+    options.set_matching("*.md".to_owned());
+    assert_eq!(vec!["2.md", "10.md"], names(options.read(dir, false, &mut Vec::new()).unwrap()));
+
+    options.set_recursive(true);
+    assert_eq!(
+        vec!["2.md", "10.md", "sub/3.md"],
+        names(options.read(dir, false, &mut Vec::new()).unwrap()),
+    );
+
+    options.set_hidden(true);
+    assert_eq!(
+        vec![".hidden.md", "2.md", "10.md", "sub/3.md"],
+        names(options.read(dir, false, &mut Vec::new()).unwrap()),
+    );
+
+    let mut options = EntryOptions::default();
+    options.set_sort(Sort::Size);
+    assert_eq!(vec!["a.txt", "2.md", "10.md"], names(options.read(dir, false, &mut Vec::new()).unwrap()));
+}
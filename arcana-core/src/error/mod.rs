@@ -36,7 +36,9 @@ pub struct InternalError {
 }
 
 impl InternalError {
-    pub(crate) fn new<S>(msg: S) -> Self
+    /// Create an error with a message, its position in the template is added
+    /// as it is returned through the parser.
+    pub fn new<S>(msg: S) -> Self
     where
         S: AsRef<str>,
     {
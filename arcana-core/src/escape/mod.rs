@@ -0,0 +1,58 @@
+use {
+    crate::error::InternalError,
+    std::str::FromStr,
+};
+
+/// The escaping applied to values written by `{{ }}` output tags and the
+/// `include` tag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Escape {
+    /// Values are written verbatim.
+    None,
+    /// `&`, `<`, `>`, `"` and `'` are replaced with HTML entities.
+    #[default]
+    Html,
+    /// Like [Html](Escape::Html), with tabs and line breaks also replaced by
+    /// character references so they survive attribute value normalization.
+    XmlAttribute,
+}
+
+impl Escape {
+    pub(crate) fn escape<S: AsRef<str>>(&self, value: S) -> String {
+        let value = value.as_ref();
+
+        if let Self::None = self {
+            return value.to_owned();
+        }
+
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match (self, c) {
+                (_, '&') => escaped.push_str("&amp;"),
+                (_, '<') => escaped.push_str("&lt;"),
+                (_, '>') => escaped.push_str("&gt;"),
+                (_, '"') => escaped.push_str("&quot;"),
+                (_, '\'') => escaped.push_str("&#39;"),
+                (Self::XmlAttribute, '\t') => escaped.push_str("&#9;"),
+                (Self::XmlAttribute, '\n') => escaped.push_str("&#10;"),
+                (Self::XmlAttribute, '\r') => escaped.push_str("&#13;"),
+                (_, c) => escaped.push(c),
+            }
+        }
+
+        escaped
+    }
+}
+
+impl FromStr for Escape {
+    type Err = InternalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "html" => Ok(Self::Html),
+            "xml-attribute" => Ok(Self::XmlAttribute),
+            s => Err(InternalError::new(format!("Unknown escape mode '{s}'"))),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use {
+    crate::{
+        error::{ InternalError, InternalResult, IntoInternal, },
+        escape::Escape,
+    },
+    std::{
+        collections::HashMap,
+        fmt::{ self, Debug, Write, },
+        sync::{ Arc, OnceLock, },
+    },
+};
+
+pub(crate) type FilterFn = dyn Fn(Option<String>, &[Option<String>]) -> InternalResult<Option<String>> + Send + Sync;
+
+/// A filter, applied to a value with its argument values.
+#[derive(Clone)]
+pub(crate) struct Filter {
+    filter: Arc<FilterFn>,
+    /// Whether the filter escapes the value, so that output tags do not
+    /// escape it again.
+    escapes: bool,
+}
+
+impl Filter {
+    pub(crate) fn apply(&self, value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+        (self.filter)(value, args)
+    }
+
+    pub(crate) fn escapes(&self) -> bool {
+        self.escapes
+    }
+}
+
+impl Debug for Filter {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str("Filter")
+    }
+}
+
+/// The filters which can be chained after a value, i.e.
+/// `{{ name | upper | truncate(20) }}`. It starts with the built-in filters,
+/// a filter registered later replaces the one of the same name.
+#[derive(Clone, Debug)]
+pub(crate) struct Filters {
+    filters: Arc<HashMap<String, Filter>>,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        static BUILTIN: OnceLock<Filters> = OnceLock::new();
+        BUILTIN.get_or_init(Self::builtin).clone()
+    }
+}
+
+impl Filters {
+    fn builtin() -> Self {
+        let mut filters = Self { filters: Arc::default(), };
+
+        filters.register("default", default);
+        filters.insert("escape", Filter { filter: Arc::new(escape), escapes: true, });
+        filters.register("json", json);
+        filters.register("lower", lower);
+        filters.register("replace", replace);
+        filters.register("trim", trim);
+        filters.register("truncate", truncate);
+        filters.register("upper", upper);
+        filters.register("urlencode", urlencode);
+
+        filters
+    }
+
+    pub(crate) fn register<K, F>(&mut self, name: K, filter: F)
+    where
+        K: AsRef<str>,
+        F: Fn(Option<String>, &[Option<String>]) -> InternalResult<Option<String>> + Send + Sync + 'static,
+    {
+        self.insert(name, Filter { filter: Arc::new(filter), escapes: false, });
+    }
+
+    fn insert<K: AsRef<str>>(&mut self, name: K, filter: Filter) {
+        Arc::make_mut(&mut self.filters).insert(name.as_ref().to_owned(), filter);
+    }
+
+    pub(crate) fn get<S: AsRef<str>>(&self, name: S) -> InternalResult<&Filter> {
+        let name = name.as_ref();
+        self.filters.get(name)
+            .into_internal(format!("Unknown filter '{name}'"))
+    }
+}
+
+fn expect_args(name: &str, args: &[Option<String>], min: usize, max: usize) -> InternalResult<()> {
+    if args.len() < min || args.len() > max {
+        if min == max {
+            return Err(InternalError::new(format!(
+                "Filter '{name}' expects {min} argument(s), got {}", args.len()
+            )));
+        }
+
+        return Err(InternalError::new(format!(
+            "Filter '{name}' expects {min} to {max} argument(s), got {}", args.len()
+        )));
+    }
+
+    Ok(())
+}
+
+fn default(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("default", args, 1, 1)?;
+
+    match value {
+        Some(value) if !value.is_empty() => Ok(Some(value)),
+        _ => Ok(args[0].clone()),
+    }
+}
+
+fn escape(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("escape", args, 0, 1)?;
+
+    let escape = match args.first() {
+        Some(Some(mode)) => mode.parse::<Escape>()?,
+        Some(None) => return Err(InternalError::new("Filter 'escape' was given an empty mode")),
+        None => Escape::Html,
+    };
+
+    Ok(value.map(|v| escape.escape(v)))
+}
+
+fn json(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("json", args, 0, 0)?;
+
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(Some("null".to_owned())),
+    };
+
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(json, "\\u{:04x}", c as u32)
+                    .into_internal("Failed to write escaped character")?;
+            },
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    Ok(Some(json))
+}
+
+fn lower(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("lower", args, 0, 0)?;
+    Ok(value.map(|v| v.to_lowercase()))
+}
+
+fn replace(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("replace", args, 2, 2)?;
+
+    let from = args[0].as_ref()
+        .into_internal("Filter 'replace' cannot replace an empty value")?;
+    if from.is_empty() {
+        return Err(InternalError::new("Filter 'replace' cannot replace an empty value"));
+    }
+
+    let to = args[1].clone().unwrap_or_default();
+
+    Ok(value.map(|v| v.replace(from, &to)))
+}
+
+fn trim(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("trim", args, 0, 0)?;
+    Ok(value.map(|v| v.trim().to_owned()))
+}
+
+fn truncate(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("truncate", args, 1, 1)?;
+
+    let length = args[0].as_ref()
+        .into_internal("Filter 'truncate' requires a length")?
+        .trim()
+        .parse::<usize>()
+        .into_internal("Filter 'truncate' requires a positive whole number length")?;
+
+    Ok(value.map(|v| v.chars().take(length).collect()))
+}
+
+fn upper(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("upper", args, 0, 0)?;
+    Ok(value.map(|v| v.to_uppercase()))
+}
+
+fn urlencode(value: Option<String>, args: &[Option<String>]) -> InternalResult<Option<String>> {
+    expect_args("urlencode", args, 0, 0)?;
+
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z'|b'a'..=b'z'|b'0'..=b'9'|b'-'|b'_'|b'.'|b'~' => encoded.push(b as char),
+            b => {
+                write!(encoded, "%{b:02X}")
+                    .into_internal("Failed to write encoded byte")?;
+            },
+        }
+    }
+
+    Ok(Some(encoded))
+}
@@ -0,0 +1,105 @@
+use {
+    crate::error::{ InternalError, InternalResult, IntoInternal, },
+    serde_json::{ Map, Number, Value, },
+    yaml_rust2::{ Yaml, YamlLoader, },
+};
+
+/// Metadata at the top of a template, between a pair of `---` (YAML) or `+++`
+/// (TOML) lines.
+#[derive(Debug)]
+pub(crate) enum FrontMatter {
+    Yaml(String),
+    Toml(String),
+}
+
+impl FrontMatter {
+    /// The front matter for an opening delimiter line, if it is one.
+    pub(crate) fn from_delimiter<S: AsRef<str>>(line: S) -> Option<Self> {
+        match line.as_ref().trim_end() {
+            "---" => Some(Self::Yaml(String::new())),
+            "+++" => Some(Self::Toml(String::new())),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn delimiter(&self) -> &'static str {
+        match self {
+            Self::Yaml(_) => "---",
+            Self::Toml(_) => "+++",
+        }
+    }
+
+    pub(crate) fn push_line<S: AsRef<str>>(&mut self, line: S) {
+        match self {
+            Self::Yaml(content)|Self::Toml(content) => content.push_str(line.as_ref()),
+        }
+    }
+
+    /// Convert the front matter to the same shape as JSON data, so it can be
+    /// merged into context alike.
+    pub(crate) fn into_json(self) -> InternalResult<Value> {
+        match self {
+            Self::Yaml(content) => {
+                let mut documents = YamlLoader::load_from_str(&content)
+                    .into_internal("Invalid YAML in front matter")?;
+
+                match documents.pop() {
+                    Some(document) => yaml_to_json(document),
+                    None => Ok(Value::Object(Map::new())),
+                }
+            },
+            Self::Toml(content) => {
+                let table = content.parse::<toml::Table>()
+                    .into_internal("Invalid TOML in front matter")?;
+
+                Ok(toml_to_json(toml::Value::Table(table)))
+            },
+        }
+    }
+}
+
+fn yaml_to_json(yaml: Yaml) -> InternalResult<Value> {
+    Ok(match yaml {
+        Yaml::Real(real) => Value::String(real),
+        Yaml::Integer(integer) => Value::Number(integer.into()),
+        Yaml::String(string) => Value::String(string),
+        Yaml::Boolean(boolean) => Value::Bool(boolean),
+        Yaml::Array(array) => Value::Array(
+            array.into_iter().map(yaml_to_json).collect::<InternalResult<Vec<Value>>>()?
+        ),
+        Yaml::Hash(hash) => {
+            let mut map = Map::new();
+            for (key, value) in hash.into_iter() {
+                let key = match key {
+                    Yaml::String(key)|Yaml::Real(key) => key,
+                    Yaml::Integer(key) => key.to_string(),
+                    Yaml::Boolean(key) => key.to_string(),
+                    _ => return Err(InternalError::new("Unsupported key in YAML front matter")),
+                };
+
+                map.insert(key, yaml_to_json(value)?);
+            }
+
+            Value::Object(map)
+        },
+        Yaml::Null => Value::Null,
+        Yaml::Alias(_)|Yaml::BadValue => {
+            return Err(InternalError::new("Unsupported value in YAML front matter"));
+        },
+    })
+}
+
+fn toml_to_json(toml: toml::Value) -> Value {
+    match toml {
+        toml::Value::String(string) => Value::String(string),
+        toml::Value::Integer(integer) => Value::Number(integer.into()),
+        toml::Value::Float(float) => Number::from_f64(float)
+            .map_or_else(|| Value::String(float.to_string()), Value::Number),
+        toml::Value::Boolean(boolean) => Value::Bool(boolean),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect()
+        ),
+    }
+}
@@ -2,8 +2,13 @@
 mod test;
 
 use {
-    crate::error::{ IntoInternal, InternalResult, },
+    crate::{
+        error::{ IntoInternal, InternalResult, },
+        front_matter::FrontMatter,
+    },
+    serde_json::Value,
     std::{
+        collections::VecDeque,
         fmt::Debug,
         fs::{ OpenOptions, File, },
         io::{ BufRead, BufReader, Read, },
@@ -48,6 +53,10 @@ impl InputString {
     fn is_end(&self) -> bool {
         self.current.is_none()
     }
+
+    fn peek(&self) -> Option<char> {
+        self.value.get(self.end_index..).and_then(|rest| rest.chars().next())
+    }
 }
 
 impl From<String> for InputString {
@@ -68,7 +77,12 @@ where
 {
     path: PathBuf,
     source: BufReader<R>,
+    // lines read ahead of the source that are still to be stepped through
+    pending: VecDeque<String>,
     read: Option<InputString>,
+    // the whole line of the template the input starts within, when it is a
+    // part of one, along with its number
+    hint: Option<(usize, String)>,
     line: usize,
     index: usize,
 }
@@ -99,7 +113,9 @@ where
         let mut input = Input::<R> {
             path: PathBuf::new(),
             source: br,
+            pending: VecDeque::new(),
             read: None,
+            hint: None,
             line: 0,
             index: 0,
         };
@@ -122,7 +138,9 @@ impl TryIntoInput<File> for &Path {
         let mut input = Input::<File> {
             path: self.to_owned(),
             source: BufReader::new(file),
+            pending: VecDeque::new(),
             read: None,
+            hint: None,
             line: 0,
             index: 0,
         };
@@ -176,13 +194,24 @@ where
     }
 
     pub(crate) fn line(&self) -> String {
-        self.read.as_ref().map_or(String::new(), |r| r.line())
+        match (self.hint.as_ref(), self.read.as_ref()) {
+            (Some((line, hint)), _) if *line == self.line => hint.to_owned(),
+            (_, Some(read)) => read.line(),
+            (Some((_, hint)), None) => hint.to_owned(),
+            (None, None) => String::new(),
+        }
     }
 
     pub(crate) fn current(&self) -> Option<&char> {
         self.read.as_ref().and_then(InputString::current)
     }
 
+    /// The character after the current one, only looking within the current
+    /// line.
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.read.as_ref().and_then(InputString::peek)
+    }
+
     pub(crate) fn path(&self) -> &PathBuf {
         &self.path
     }
@@ -194,6 +223,22 @@ where
         self.path = path.as_ref().into();
     }
 
+    /// Continue counting lines and indices from a position within another
+    /// input, i.e. when the input is a part of a template.
+    pub(crate) fn set_position(&mut self, line: usize, index: usize) {
+        self.line = line;
+        self.index = index;
+    }
+
+    /// Show the whole line of the template the input starts within near
+    /// errors, rather than the part of it the input holds.
+    pub(crate) fn set_hint<S>(&mut self, hint: S)
+    where
+        S: AsRef<str>,
+    {
+        self.hint = Some((self.line, hint.as_ref().to_owned()));
+    }
+
     pub(crate) fn step(&mut self) -> InternalResult<()> {
         if let Some(is) = self.read.as_mut() {
             is.step();
@@ -203,8 +248,14 @@ where
             }
         }
 
-        let mut content = String::new();
-        self.source.read_line(&mut content).into_internal("Failed to read line")?;
+        let content = match self.pending.pop_front() {
+            Some(content) => content,
+            None => {
+                let mut content = String::new();
+                self.source.read_line(&mut content).into_internal("Failed to read line")?;
+                content
+            },
+        };
 
         if content.is_empty() {
             self.read = None;
@@ -223,6 +274,56 @@ where
     pub(crate) fn is_end(&self) -> bool {
         self.read.as_ref().is_none_or(|r| r.is_end())
     }
+
+    /// Take the front matter from the top of a file, leaving the input at the
+    /// first line after it. Lines between a pair of delimiters are only front
+    /// matter when they hold a YAML or TOML table, otherwise the input is left
+    /// as is.
+    pub(crate) fn take_front_matter(&mut self) -> InternalResult<Option<Value>> {
+        if self.path.as_os_str().is_empty() || self.line != 1 || self.index != 0 {
+            return Ok(None);
+        }
+
+        let mut front_matter = match FrontMatter::from_delimiter(self.line()) {
+            Some(front_matter) => front_matter,
+            None => return Ok(None),
+        };
+
+        let mut lines = Vec::new();
+        let closed = loop {
+            let mut content = String::new();
+            self.source.read_line(&mut content).into_internal("Failed to read line")?;
+
+            if content.is_empty() {
+                break false;
+            }
+
+            let is_delimiter = content.trim_end() == front_matter.delimiter();
+            lines.push(content);
+
+            if is_delimiter {
+                break true;
+            }
+        };
+
+        if closed {
+            for line in lines[..lines.len() - 1].iter() {
+                front_matter.push_line(line);
+            }
+
+            if let Ok(table @ Value::Object(_)) = front_matter.into_json() {
+                self.line += lines.len();
+                self.read = None;
+                self.step()?;
+
+                return Ok(Some(table));
+            }
+        }
+
+        self.pending.extend(lines);
+
+        Ok(None)
+    }
 }
 
 impl<'string> TryIntoInput<&'string [u8]> for &'string str {
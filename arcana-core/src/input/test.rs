@@ -60,3 +60,21 @@ fn read_str_1() {
     assert_eq!(None, input.current());
     assert!(input.is_end());
 }
+
+#[test]
+fn peek_str_1() {
+    let mut input = "a|b\nc".try_into_input().unwrap();
+    assert_eq!(Some('|'), input.peek());
+
+    input.step().unwrap();
+    assert_eq!(Some('b'), input.peek());
+
+    input.step().unwrap();
+    input.step().unwrap();
+    assert_eq!(Some(&'\n'), input.current());
+    assert_eq!(None, input.peek());
+
+    input.step().unwrap();
+    assert_eq!(Some(&'c'), input.current());
+    assert_eq!(None, input.peek());
+}
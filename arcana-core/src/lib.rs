@@ -15,15 +15,28 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 mod context;
+mod decimal;
+mod entries;
 mod error;
+mod escape;
+mod filter;
+mod front_matter;
 mod input;
 mod macros;
 mod output;
 mod parser;
+mod records;
+mod report;
+mod template;
+mod watcher;
 
 pub use {
     context::Context,
     error::{ InternalResult, InternalError, },
+    escape::Escape,
+    report::CompileReport,
+    template::{ Template, Templates, },
+    watcher::Watcher,
 };
 
 use {
@@ -77,6 +90,106 @@ impl Arcana {
         Ok(())
     }
 
+    /// Compile the input template to a given output with a specific starting
+    /// context, reporting every file and directory read along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The [readable](Read) template.
+    /// * `output` - The [writable](Write) output.
+    /// * `ctx` - The [context](Context).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Context, Arcana, };
+    ///
+    /// let input = "{% include \"./resources/parse-include-1.txt\" /%}";
+    /// let mut output = Vec::<u8>::new();
+    /// let report = Arcana::compile_with_ctx_report(input, &mut output, Context::default())
+    ///     .unwrap();
+    /// assert_eq!(1, report.files().len());
+    /// ```
+    ///
+    pub fn compile_with_ctx_report<R, I, W>(input: I, output: W, ctx: Context) -> InternalResult<CompileReport>
+    where
+        R: Read + Debug,
+        I: TryIntoInput<R>,
+        W: Write + Debug,
+    {
+        let input = input.try_into_input()?;
+        let report = CompileReport::shared();
+        let mut parser = TemplateParser::new(ctx, input, output)?;
+
+        parser.set_dependencies(report.clone())?;
+        parser.parse()?;
+        drop(parser);
+
+        Ok(report.take())
+    }
+
+    /// Compile the input template to a given output, reporting every file and
+    /// directory read along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The [readable](Read) template.
+    /// * `output` - The [writable](Write) output.
+    ///
+    pub fn compile_report<R, I, W>(input: I, output: W) -> InternalResult<CompileReport>
+    where
+        R: Read + Debug,
+        I: TryIntoInput<R>,
+        W: Write + Debug,
+    {
+        Self::compile_with_ctx_report(input, output, Context::default())
+    }
+
+    /// Compile a template file to a given output with a specific context,
+    /// reporting every file and directory read along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The [path](Path) to the file.
+    /// * `output` - The [writable](Write) output.
+    /// * `ctx` - The [context](Context).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Context, Arcana, };
+    ///
+    /// let path = "./resources/parse_file_1/page.arct";
+    /// let mut output = Vec::<u8>::new();
+    /// let report = Arcana::compile_file_with_ctx_report(path, &mut output, Context::default())
+    ///     .unwrap();
+    /// assert!(report.files().contains(&std::fs::canonicalize(path).unwrap()));
+    /// ```
+    ///
+    pub fn compile_file_with_ctx_report<P, W>(path: P, output: W, ctx: Context) -> InternalResult<CompileReport>
+    where
+        P: AsRef<Path>,
+        W: Write + Debug,
+    {
+        Self::compile_with_ctx_report(path.as_ref(), output, ctx)
+    }
+
+    /// Compile a template file to a given output, reporting every file and
+    /// directory read along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The [path](Path) to the file.
+    /// * `output` - The [writable](Write) output.
+    ///
+    pub fn compile_file_report<P, W>(path: P, output: W) -> InternalResult<CompileReport>
+    where
+        P: AsRef<Path>,
+        W: Write + Debug,
+    {
+        Self::compile_file_with_ctx_report(path, output, Context::default())
+    }
+
     /// Compile the input template to a given output.
     ///
     /// # Arguments
@@ -268,4 +381,28 @@ mod test {
         let output = String::from_utf8(output).unwrap();
         assert_eq!("The number: 4", output);
     }
+
+    #[test]
+    fn compile_report_1() {
+        let canonical = |path: &str| std::fs::canonicalize(path).unwrap();
+
+        let report = crate::Arcana::compile_file_report(
+            "./resources/report_1/page.arct", Vec::<u8>::new()
+        ).unwrap();
+
+        let files = [
+            "./resources/report_1/page.arct",
+            "./resources/report_1/layout.arct",
+            "./resources/report_1/vars.arct",
+            "./resources/report_1/posts/first.txt",
+            "./resources/report_1/assets/logo.txt",
+        ];
+        assert_eq!(&std::collections::BTreeSet::from(files.map(canonical)), report.files());
+
+        let dirs = [
+            "./resources/report_1",
+            "./resources/report_1/posts",
+        ];
+        assert_eq!(&std::collections::BTreeSet::from(dirs.map(canonical)), report.dirs());
+    }
 }
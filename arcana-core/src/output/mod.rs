@@ -1,7 +1,10 @@
 use {
-    crate::error::{
-        IntoInternal,
-        InternalResult,
+    crate::{
+        error::{
+            IntoInternal,
+            InternalResult,
+        },
+        escape::Escape,
     },
     std::{
         fmt::Debug,
@@ -18,6 +21,9 @@ where
     buffer: Vec<u8>,
     content: Vec<u8>,
     destination: BufWriter<W>,
+    stream: bool,
+    holds: usize,
+    streamed: bool,
 }
 
 impl<W> Output<W>
@@ -29,37 +35,67 @@ where
             buffer: Vec::new(),
             content: Vec::new(),
             destination: BufWriter::new(writer),
+            stream: false,
+            holds: 0,
+            streamed: false,
         }
     }
 
-    pub(crate) fn write_bytes_to_buffer(&mut self, mut bytes: Vec<u8>) {
-        self.buffer.append(&mut bytes);
+    /// Write content to the destination as soon as it is final instead of
+    /// once parsing is done.
+    pub(crate) fn set_stream(&mut self, stream: bool) {
+        self.stream = stream;
+    }
+
+    /// Keep content from being streamed until it is released, i.e. while the
+    /// content of a template is still to be given to the template it extends.
+    pub(crate) fn hold(&mut self) {
+        self.holds += 1;
+    }
+
+    pub(crate) fn release(&mut self) {
+        self.holds = self.holds.saturating_sub(1);
+    }
+
+    pub(crate) fn has_streamed(&self) -> bool {
+        self.streamed
+    }
+
+    /// Write the content to the destination when streaming and nothing holds
+    /// it back.
+    pub(crate) fn stream(&mut self) -> InternalResult<()> {
+        if !self.stream || self.holds > 0 || self.content.is_empty() {
+            return Ok(());
+        }
+
+        self.streamed = true;
+        self.write_content_to_destination()
     }
 
     pub(crate) fn write_char(&mut self, c: char) {
-        self.buffer.push(c as u8);
+        let mut encoded = [0; 4];
+        self.buffer.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
     }
 
     pub(crate) fn write_str(&mut self, s: &str) {
-        for c in s.chars() {
-            self.write_char(c);
-        }
+        self.buffer.extend_from_slice(s.as_bytes());
     }
 
     pub(crate) fn flush_buffer_to_content(&mut self) {
         self.content.append(&mut self.buffer);
     }
 
-    pub(crate) fn flush_buffer_and_file(&mut self, file: File) -> InternalResult<()> {
+    pub(crate) fn flush_buffer_and_file(&mut self, file: File, escape: Escape) -> InternalResult<()> {
         self.flush_buffer_to_content();
 
         let br = BufReader::new(file);
+        let newline = escape.escape("\n");
         let mut first_line = true;
         let lines = br.lines();
         for line in lines {
-            let line = line.into_internal("Failed to read next line")?;
+            let line = escape.escape(line.into_internal("Failed to read next line")?);
             if !first_line {
-                self.content.write_all(format!("\n{line}").as_bytes())
+                self.content.write_all(format!("{newline}{line}").as_bytes())
                     .into_internal("Failed to write line from file")?;
             }
             else {
@@ -4,6 +4,7 @@ use {
             Context,
             Variable,
         },
+        decimal::Decimal,
         error::{
             IntoInternal,
             InternalError,
@@ -65,7 +66,7 @@ impl Condition {
 
     fn try_num<F, H>(a: Option<String>, b: Option<String>, num_cmp: F, str_cmp: H) -> Self
     where
-        F: Fn(&i64, &i64) -> bool,
+        F: Fn(&Decimal, &Decimal) -> bool,
         H: Fn(&Option<String>, &Option<String>) -> bool,
     {
         if a.is_none() {
@@ -73,7 +74,7 @@ impl Condition {
         }
 
         let ar = a.as_ref().unwrap();
-        let ir = ar.parse::<i64>();
+        let ir = ar.parse::<Decimal>();
         if ir.is_err() {
             return Self::from(str_cmp(&a, &b));
         }
@@ -83,7 +84,7 @@ impl Condition {
         }
 
         let br = b.as_ref().unwrap();
-        let jr = br.parse::<i64>();
+        let jr = br.parse::<Decimal>();
         if jr.is_err() {
             return Self::from(str_cmp(&a, &b));
         }
@@ -91,23 +92,36 @@ impl Condition {
         let i = ir.unwrap();
         let j = jr.unwrap();
 
+        // numbers too far apart in scale to compare
+        if i.partial_cmp(&j).is_none() {
+            return Self::from(str_cmp(&a, &b));
+        }
+
         Self::from(num_cmp(&i, &j))
     }
 
+    pub(crate) fn eq(a: Option<String>, b: Option<String>) -> Self {
+        Self::try_num(a, b, Decimal::eq, Option::<String>::eq)
+    }
+
+    pub(crate) fn ne(a: Option<String>, b: Option<String>) -> Self {
+        Self::try_num(a, b, Decimal::ne, Option::<String>::ne)
+    }
+
     pub(crate) fn gt(a: Option<String>, b: Option<String>) -> Self {
-        Self::try_num(a, b, i64::gt, Option::<String>::gt)
+        Self::try_num(a, b, Decimal::gt, Option::<String>::gt)
     }
 
     pub(crate) fn ge(a: Option<String>, b: Option<String>) -> Self {
-        Self::try_num(a, b, i64::ge, Option::<String>::ge)
+        Self::try_num(a, b, Decimal::ge, Option::<String>::ge)
     }
 
     pub(crate) fn lt(a: Option<String>, b: Option<String>) -> Self {
-        Self::try_num(a, b, i64::lt, Option::<String>::lt)
+        Self::try_num(a, b, Decimal::lt, Option::<String>::lt)
     }
 
     pub(crate) fn le(a: Option<String>, b: Option<String>) -> Self {
-        Self::try_num(a, b, i64::le, Option::<String>::le)
+        Self::try_num(a, b, Decimal::le, Option::<String>::le)
     }
 }
 
@@ -284,7 +298,7 @@ where
                 _ => {
                     self.bypass_whitespace()?;
 
-                    let left_value = self.parse_value(&tagname)?;
+                    let left_value = self.parse_expression_with(&tagname, self.bypass.is_none())?;
 
                     self.bypass_whitespace()?;
 
@@ -297,10 +311,10 @@ where
                                     self.input_mut().into_step()?.step().into_step()?;
                                     self.bypass_whitespace()?;
 
-                                    let right_value = self.parse_value(&tagname)?;
+                                    let right_value = self.parse_expression_with(&tagname, self.bypass.is_none())?;
                                     self.condition = Some(match self.bypass.as_ref() {
                                         Some(b) => Condition::from(*b),
-                                        None => Condition::from(left_value == right_value),
+                                        None => Condition::eq(left_value, right_value),
                                     });
                                 },
                                 _ => return self.tag_unexpected_char_expected(&tagname, "=")?,
@@ -314,10 +328,10 @@ where
                                     self.input_mut().into_step()?.step().into_step()?;
                                     self.bypass_whitespace()?;
 
-                                    let right_value = self.parse_value(&tagname)?;
+                                    let right_value = self.parse_expression_with(&tagname, self.bypass.is_none())?;
                                     self.condition = Some(match self.bypass.as_ref() {
                                         Some(b) => Condition::from(*b),
-                                        None => Condition::from(left_value != right_value),
+                                        None => Condition::ne(left_value, right_value),
                                     });
                                 },
                                 _ => return self.tag_unexpected_char_expected(&tagname, "="),
@@ -331,7 +345,7 @@ where
                                     self.input_mut().into_step()?.step().into_step()?;
                                     self.bypass_whitespace()?;
 
-                                    let right_value = self.parse_value(&tagname)?;
+                                    let right_value = self.parse_expression_with(&tagname, self.bypass.is_none())?;
                                     self.condition = Some(match self.bypass.as_ref() {
                                         Some(b) => Condition::from(*b),
                                         None => Condition::ge(left_value, right_value),
@@ -340,7 +354,7 @@ where
                                 _ => {
                                     self.bypass_whitespace()?;
 
-                                    let right_value = self.parse_value(&tagname)?;
+                                    let right_value = self.parse_expression_with(&tagname, self.bypass.is_none())?;
 
                                     self.condition = Some(match self.bypass.as_ref() {
                                         Some(b) => Condition::from(*b),
@@ -357,7 +371,7 @@ where
                                     self.input_mut().into_step()?.step().into_step()?;
                                     self.bypass_whitespace()?;
 
-                                    let right_value = self.parse_value(&tagname)?;
+                                    let right_value = self.parse_expression_with(&tagname, self.bypass.is_none())?;
                                     self.condition = Some(match self.bypass.as_ref() {
                                         Some(b) => Condition::from(*b),
                                         None => Condition::le(left_value, right_value),
@@ -366,7 +380,7 @@ where
                                 _ => {
                                     self.bypass_whitespace()?;
 
-                                    let right_value = self.parse_value(&tagname)?;
+                                    let right_value = self.parse_expression_with(&tagname, self.bypass.is_none())?;
                                     self.condition = Some(match self.bypass.as_ref() {
                                         Some(b) => Condition::from(*b),
                                         None => Condition::lt(left_value, right_value),
@@ -446,12 +460,6 @@ where
                                 break;
                             }
                         },
-                        p => {
-                            return Err(Err(InternalError::new(format!(
-                                "'{:?}' is not a valid parse until for '{}' tags",
-                                p, self.tagname.to_owned()
-                            ))));
-                        },
                     }
                 },
                 None => {
@@ -6,68 +6,62 @@ pub(crate) mod steps;
 
 use {
     crate::{
-        context::Context,
+        context::{ Block, BlockFrame, Context, Function, LoopFrame, },
+        decimal::{ Decimal, DIVISION_SCALE, },
+        entries::EntryOptions,
         error::{
             InternalError,
             InternalResult,
             IntoInternal,
             OrElseUpgrade,
         },
+        escape::Escape,
         input::{ Input, TryIntoInput },
+        macros::*,
         parser::{
             if_parser::IfParser,
             steps::*,
         },
         output::Output,
+        records::{ Record, Records, },
+        report::SharedReport,
+        template::{ self, Kind, Node, SharedTemplates, Source, Tag, Template, },
     },
     std::{
         fmt::Debug,
-        fs::{ canonicalize, File, OpenOptions },
+        fs::{ canonicalize, OpenOptions, read_to_string, },
         io::{ Read, self, Write, },
-        path::PathBuf,
+        path::{ Path, PathBuf, },
+        sync::Arc,
     },
 };
 
+/// Where the condition of an 'if' tag ends.
 #[derive(Clone, Debug)]
 pub(crate) enum ParseUntil {
-    EndAdd,
-    EndDiv,
-    EndFn,
-    EndForeach,
-    EndFordir,
-    EndForfile,
-    EndForsplit,
-    EndIf,
-    EndMod,
-    EndMul,
-    EndNth,
-    EndPow,
-    EndSet,
-    EndSub,
-    Eof,
-    // used exclusively by if tag
     ConditionEnd,
     Eot,
 }
 
+/// A tag which ends every tag up to the enclosing loop.
 #[derive(Clone, Debug)]
 pub(crate) enum EndPosition {
-    Add,
-    Else,
-    Div,
-    Fn,
-    Foreach,
-    Fordir,
-    Forfile,
-    Forsplit,
-    Nth,
-    If,
-    Mod,
-    Mul,
-    Pow,
-    Set,
-    Sub,
-    Eof,
+    Break,
+    Continue,
+}
+
+/// An item of a loop, with the variables it adds and the value it shows as
+/// the previous or next item of the loop context.
+#[derive(Clone, Debug)]
+struct LoopItem {
+    variables: Vec<(String, String)>,
+    text: String,
+}
+
+impl LoopItem {
+    fn new(variable: &str, value: String) -> Self {
+        Self { variables: vec![(variable.to_owned(), value.clone())], text: value, }
+    }
 }
 
 pub(crate) trait Parser<R, W>
@@ -98,10 +92,16 @@ where
     W: Write + Debug,
 {
     extend: Option<PathBuf>,
+    /// Whether the template being parsed extends another one, so that its
+    /// blocks are only defined.
+    extending: bool,
+    /// Whether the content of a loop is being parsed, so that 'break' and
+    /// 'continue' have a loop to end.
+    looping: bool,
     context: Option<Context>,
     input: Option<Input<R>>,
-    parse_until: ParseUntil,
-    bypass: bool,
+    dependencies: Option<SharedReport>,
+    templates: Option<SharedTemplates>,
     output: Option<Output<W>>,
     end_position: Option<EndPosition>,
 }
@@ -168,25 +168,88 @@ where
     R: Read + Debug,
     W: Write + Debug,
 {
-    pub(crate) fn bypass(&self) -> bool {
-        self.bypass
+    /// Record every file read from here on, starting with the input itself.
+    pub(crate) fn set_dependencies(&mut self, dependencies: SharedReport) -> InternalResult<()> {
+        self.dependencies = Some(dependencies);
+        self.record_dependency(self.input()?.path());
+
+        Ok(())
+    }
+
+    /// Take the templates read from here on from `templates`, adding the ones
+    /// parsed.
+    pub(crate) fn set_templates(&mut self, templates: SharedTemplates) {
+        self.templates = Some(templates);
+    }
+
+    /// Parse the template at path, unless it was parsed before.
+    fn load_template(&self, path: PathBuf) -> InternalResult<Arc<Template>> {
+        let loaded = self.templates.as_ref()
+            .and_then(|templates| templates.borrow().get(&path));
+
+        let template = match loaded {
+            Some(template) => template,
+            None => {
+                let template = Arc::new(Template::parse_file(&path)?);
+                if let Some(templates) = self.templates.as_ref() {
+                    templates.borrow_mut().insert(path, template.clone());
+                }
+                template
+            },
+        };
+        self.record_dependency(template.path());
+
+        Ok(template)
+    }
+
+    fn record_dependency<P: AsRef<Path>>(&self, path: P) {
+        if let Some(dependencies) = self.dependencies.as_ref() {
+            dependencies.borrow_mut().add_file(path);
+        }
     }
 
-    pub(crate) fn parse_until(&self) -> &ParseUntil {
-        &self.parse_until
+    fn record_dir_dependency<P: AsRef<Path>>(&self, path: P) {
+        if let Some(dependencies) = self.dependencies.as_ref() {
+            dependencies.borrow_mut().add_dir(path);
+        }
+    }
+
+    fn record_path_dependency<P: AsRef<Path>>(&self, path: P) {
+        if let Some(dependencies) = self.dependencies.as_ref() {
+            dependencies.borrow_mut().add_path(path);
+        }
     }
 
     pub(crate) fn set_end_position(&mut self, end_position: EndPosition) {
         self.end_position = Some(end_position);
     }
 
-    pub(crate) fn set_extend(&mut self, path: PathBuf) {
+    /// Extend the template at path once parsing is done, holding back any
+    /// streamed content until then.
+    pub(crate) fn set_extend(&mut self, path: PathBuf) -> InternalResult<()> {
+        if self.extend.is_none() {
+            let output = self.output_mut()?;
+            if output.has_streamed() {
+                return Err(InternalError::new(
+                    "Cannot extend after output was streamed, 'extend' must come before any output"
+                ));
+            }
+
+            output.hold();
+        }
+
         self.extend = Some(path);
+
+        Ok(())
+    }
+
+    fn extending(&self) -> bool {
+        self.extending || self.extend.is_some()
     }
 
     fn new_internal<I, O>(
         context: Context, into_input: I, into_output: O,
-        parse_until: ParseUntil, bypass: bool,
+        dependencies: Option<SharedReport>, templates: Option<SharedTemplates>,
     ) -> InternalResult<Self>
     where
         I: TryIntoInput<R>,
@@ -196,10 +259,12 @@ where
 
         Ok(Self {
             extend: None,
+            extending: false,
+            looping: false,
             context: Some(context),
             input: Some(input),
-            parse_until,
-            bypass,
+            dependencies,
+            templates,
             output: Some(into_output.into()),
             end_position: None,
         })
@@ -212,49 +277,125 @@ where
         I: TryIntoInput<R>,
         O: Into<Output<W>>,
     {
-        Self::new_internal(
-            context, into_input, into_output,
-            ParseUntil::Eof, false,
-        )
+        let mut parser = Self::new_internal(context, into_input, into_output, None, None)?;
+        parser.parse_front_matter()?;
+
+        let streaming = parser.context()?.streaming();
+        parser.output_mut()?.set_stream(streaming);
+
+        Ok(parser)
+    }
+
+    /// Strip the front matter from the top of the input, loading it into
+    /// context.
+    fn parse_front_matter(&mut self) -> InternalResult<()> {
+        let front_matter = match self.input_mut()?.take_front_matter() {
+            Ok(front_matter) => front_matter,
+            Err(e) => return Err(e).or_else_upgrade(self),
+        };
+
+        if let Some(front_matter) = front_matter {
+            let path = self.input()?.path().to_owned();
+            self.context_mut()?.merge_value("", path, &front_matter).or_else_upgrade(self)?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the front matter of a parsed template into context.
+    fn merge_front_matter(&mut self, template: &Template) -> InternalResult<()> {
+        if let Some(front_matter) = template.front_matter() {
+            self.context_mut()?.merge_value("", template.path(), front_matter).or_else_upgrade(self)?;
+        }
+
+        Ok(())
     }
 
-    pub(crate) fn spawn_sealed(&mut self, path: PathBuf) -> InternalResult<TemplateParser<File, W>> {
+    pub(crate) fn spawn_sealed(
+        &mut self, template: &Template
+    ) -> InternalResult<TemplateParser<&'static [u8], W>> {
+        // the blocks of the templates extending this one are not its own
+        let mut context = self.context()?.clone();
+        context.clear_blocks();
+
         TemplateParser::new_internal(
-            self.context()?.clone(),
-            path,
+            context,
+            template.empty_input()?,
             self.take_output()?,
-            ParseUntil::Eof,
-            self.bypass,
+            self.dependencies.clone(),
+            self.templates.clone(),
         )
     }
 
-    pub(crate) fn consume_sealed(&mut self, mut sealed: TemplateParser<File, W>) -> InternalResult<()> {
+    pub(crate) fn consume_sealed(
+        &mut self, mut sealed: TemplateParser<&'static [u8], W>
+    ) -> InternalResult<()> {
         self.give_output(Some(sealed.take_output()?));
 
         Ok(())
     }
 
     pub(crate) fn parse_sealed(&mut self, path: PathBuf) -> InternalResult<()> {
-        let mut sealed = self.spawn_sealed(path)?;
-        sealed.parse_internal()?;
+        let template = self.load_template(path)?;
+        let mut sealed = self.spawn_sealed(&template)?;
+        sealed.render_template(&template)?;
         self.consume_sealed(sealed)?;
 
         Ok(())
     }
 
-    pub(crate) fn spawn_unsealed(&mut self, file: PathBuf) -> InternalResult<TemplateParser<File, W>> {
+    /// Collect the functions defined by the template at path without compiling
+    /// anything else. Each function is given the others, so that they are
+    /// visible to its body wherever it is called.
+    pub(crate) fn parse_imported(&mut self, path: PathBuf) -> InternalResult<Vec<(String, Function)>> {
+        let mut context = self.context()?.clone();
+        context.clear_blocks();
+        context.clear_functions();
+
+        let template = self.load_template(path)?;
+        let mut parser = TemplateParser::new_internal(
+            context,
+            template.empty_input()?,
+            io::sink(),
+            self.dependencies.clone(),
+            self.templates.clone(),
+        )?;
+        parser.merge_front_matter(&template)?;
+
+        for node in template.nodes().iter() {
+            if matches!(node, Node::Tag(tag) if tag.kind() == Kind::Fn) {
+                parser.render_node(node)?;
+            }
+        }
+
+        let context = parser.take_context()?;
+        let module = context.functions()
+            .map(|(name, function)| (name.to_owned(), function.to_owned()))
+            .collect::<Vec<_>>();
+        let shared = Arc::new(module.clone());
+
+        Ok(module.into_iter()
+            .map(|(name, function)| (name, function.with_module(shared.clone())))
+            .collect())
+    }
+
+    pub(crate) fn spawn_unsealed(
+        &mut self, template: &Template
+    ) -> InternalResult<TemplateParser<&'static [u8], W>> {
         self.output_mut()?.flush_buffer_to_content();
 
         TemplateParser::new_internal(
             self.take_context()?,
-            file,
+            template.empty_input()?,
             self.take_output()?,
-            ParseUntil::Eof,
-            self.bypass,
+            self.dependencies.clone(),
+            self.templates.clone(),
         )
     }
 
-    pub(crate) fn consume_unsealed(&mut self, mut unsealed: TemplateParser<File, W>) -> InternalResult<()> {
+    pub(crate) fn consume_unsealed(
+        &mut self, mut unsealed: TemplateParser<&'static [u8], W>
+    ) -> InternalResult<()> {
         self.give_context(Some(unsealed.take_context()?));
         self.give_output(Some(unsealed.take_output()?));
 
@@ -262,3813 +403,2217 @@ where
     }
 
     pub(crate) fn parse_unsealed(&mut self, file: PathBuf) -> InternalResult<()> {
-        let mut unsealed = self.spawn_unsealed(file)?;
-        unsealed.parse_internal()?;
+        let template = self.load_template(file)?;
+        let mut unsealed = self.spawn_unsealed(&template)?;
+        unsealed.render_template(&template)?;
         self.consume_unsealed(unsealed)?;
 
         Ok(())
     }
 
-    pub(crate) fn spawn_extend(&mut self, extend: PathBuf) -> InternalResult<TemplateParser<File, W>> {
+    pub(crate) fn spawn_extend(
+        &mut self, extend: &Template
+    ) -> InternalResult<TemplateParser<&'static [u8], W>> {
         self.output_mut()?.flush_buffer_to_content();
         let content = String::from_utf8(self.output_mut()?.take_content())
             .into_internal("Invalid utf8 in content")?;
+        self.output_mut()?.release();
 
         let input_path = self.input()?.path().to_owned();
-        self.context_mut()?.add_variable("CONTENT", input_path, content);
+        self.context_mut()?.add_markup("CONTENT", input_path, content);
 
         TemplateParser::new_internal(
             self.take_context()?,
-            extend,
+            extend.empty_input()?,
             self.take_output()?,
-            ParseUntil::Eof,
-            self.bypass,
+            self.dependencies.clone(),
+            self.templates.clone(),
         )
     }
 
-    pub(crate) fn consume_extend(&mut self, mut extend: TemplateParser<File, W>) -> InternalResult<()> {
+    pub(crate) fn consume_extend(
+        &mut self, mut extend: TemplateParser<&'static [u8], W>
+    ) -> InternalResult<()> {
         self.give_context(Some(extend.take_context()?));
         self.context_mut()?.remove_variable("CONTENT");
+        self.context_mut()?.clear_blocks();
         self.give_output(Some(extend.take_output()?));
 
         Ok(())
     }
 
     pub(crate) fn parse_extend(&mut self, extend: PathBuf) -> InternalResult<()> {
-        let mut extend = self.spawn_extend(extend)?;
-        extend.parse_internal()?;
+        let template = self.load_template(extend)?;
+        let mut extend = self.spawn_extend(&template)?;
+        extend.render_template(&template)?;
         self.consume_extend(extend)?;
 
         Ok(())
     }
 
-    pub(crate) fn spawn_bypassed<'unsealed>(
-        &mut self, output: &'unsealed mut Vec<u8>, parse_until: ParseUntil,
-    ) -> InternalResult<TemplateParser<R, &'unsealed mut Vec<u8>>> {
-        TemplateParser::new_internal(
-            self.take_context()?,
-            self.take_input()?,
-            Output::from(output),
-            parse_until,
-            true,
-        )
-    }
-
-    pub(crate) fn consume_bypassed(&mut self, mut bypassed: TemplateParser<R, &mut Vec<u8>>) -> InternalResult<EndPosition> {
-        self.give_context(Some(bypassed.take_context()?));
-        self.give_input(Some(bypassed.take_input()?));
-
-        bypassed.end_position.take()
-            .into_internal("End position was None")
-    }
-
-    pub(crate) fn parse_bypassed(&mut self, parse_until: ParseUntil) -> InternalResult<(Vec<u8>, EndPosition)> {
-        let mut bytes = Vec::new();
-        let mut bypassed = self.spawn_bypassed(&mut bytes, parse_until)?;
-        bypassed.parse()?;
-        let end_pos = self.consume_bypassed(bypassed)?;
-
-        Ok((bytes, end_pos))
-    }
-
     pub(crate) fn spawn_limited<'limited>(
-        &mut self, input: Input<&'limited [u8]>, parse_until: ParseUntil
+        &mut self, input: Input<&'limited [u8]>
     ) -> InternalResult<TemplateParser<&'limited [u8], W>> {
-        TemplateParser::new_internal(
+        let mut parser = TemplateParser::new_internal(
             self.take_context()?,
             input,
             self.take_output()?,
-            parse_until,
-            self.bypass,
-        )
+            self.dependencies.clone(),
+            self.templates.clone(),
+        )?;
+        parser.extend = self.extend.take();
+        parser.extending = self.extending;
+        parser.looping = self.looping;
+
+        Ok(parser)
     }
 
     pub(crate) fn consume_limited(&mut self, mut limited: TemplateParser<&[u8], W>) -> InternalResult<()> {
         self.give_context(Some(limited.take_context()?));
         self.give_output(Some(limited.take_output()?));
 
+        // an extend found within a tag is applied to the whole template
+        self.extend = limited.extend.take();
+
+        // a 'break' or 'continue' ends every tag up to the enclosing loop
+        if let Some(position) = limited.end_position.take() {
+            self.set_end_position(position);
+        }
+
         Ok(())
     }
 
-    pub(crate) fn parse_limited<'limited, I>(
-        &mut self, into_input: I, parse_until: ParseUntil
-    ) -> InternalResult<()>
-    where
-        I: TryIntoInput<&'limited [u8]>,
-    {
-        // make sure input has the same path as it is within the same file
-        let mut input = into_input.try_into_input()?;
+    /// An input without content at the path of this one, for the nodes
+    /// parsed from it.
+    fn empty_input(&self) -> InternalResult<Input<&'static [u8]>> {
+        let mut input = "".try_into_input()?;
         input.set_path(self.input()?.path());
 
-        let mut limited = self.spawn_limited(
-            input,
-            parse_until
-        )?;
-        limited.parse_internal()?;
-        self.consume_limited(limited)?;
-
-        Ok(())
+        Ok(input)
     }
 
-    pub(crate) fn spawn_limited_sealed<'limited>(
-        &mut self, context: Context, input: Input<&'limited [u8]>, parse_until: ParseUntil
-    ) -> InternalResult<TemplateParser<&'limited [u8], W>> {
-        TemplateParser::new_internal(
-            context,
-            input,
-            self.take_output()?,
-            parse_until,
-            self.bypass,
-        )
+    /// Parse the rest of the input into nodes.
+    fn parse_nodes(&mut self) -> InternalResult<Vec<Node>> {
+        let input = self.input_mut()?;
+        let nodes = template::parse(input);
+
+        nodes.or_else_upgrade_from_input(input)
     }
 
-    pub(crate) fn consume_limited_sealed(&mut self, mut limited: TemplateParser<&[u8], W>) -> InternalResult<()> {
-        self.give_output(Some(limited.take_output()?));
+    /// Render nodes, text is written as is while tags and outputs are read from
+    /// their source. A 'break' or 'continue' leaves the remaining nodes.
+    pub(crate) fn render_nodes(&mut self, nodes: &[Node]) -> InternalResult<()> {
+        for node in nodes.iter() {
+            self.render_node(node)?;
+            self.output_mut()?.stream()?;
+
+            if self.end_position.is_some() {
+                break;
+            }
+        }
 
         Ok(())
     }
 
-    pub(crate) fn parse_limited_sealed<'limited, I>(
-        &mut self, context: Context, into_input: I, parse_until: ParseUntil
-    ) -> InternalResult<()>
+    fn render_node(&mut self, node: &Node) -> InternalResult<()> {
+        match node {
+            Node::Text(text) => {
+                let output = self.output_mut()?;
+                output.write_str(text);
+                output.flush_buffer_to_content();
+
+                Ok(())
+            },
+            Node::Output(source) => self.render_source(source, |limited| limited.parse_output()),
+            Node::Tag(tag) => self.render_source(tag.source(), |limited| limited.parse_tag(tag)),
+        }
+    }
+
+    /// Read the source of a tag or output with a parser limited to it.
+    fn render_source<'source, F>(&mut self, source: &'source Source, parse: F) -> InternalResult<()>
     where
-        I: TryIntoInput<&'limited [u8]>,
+        F: FnOnce(&mut TemplateParser<&'source [u8], W>) -> StepResult<()>,
     {
-        // make sure input has the same path as it is within the same file
-        let mut input = into_input.try_into_input()?;
-        input.set_path(self.input()?.path());
-
-        let mut limited = self.spawn_limited_sealed(context, input, parse_until)?;
-        limited.parse_internal()?;
-        self.consume_limited_sealed(limited)?;
+        let input = source.input(self.input()?.path())?;
 
-        Ok(())
+        let mut limited = self.spawn_limited(input)?;
+        if let Err(Err(e)) = parse(&mut limited).or_else_upgrade(&limited) {
+            return Err(e);
+        }
+        self.consume_limited(limited)
     }
 
-    pub(crate) fn spawn_child<O2: Write + Debug, W2: Into<Output<O2>>>(
-        &mut self, output: W2, parse_until: ParseUntil
-    ) -> InternalResult<TemplateParser<R, O2>> {
-        TemplateParser::new_internal(
-            self.take_context()?,
-            self.take_input()?,
-            output,
-            parse_until,
-            self.bypass,
-        )
+    /// Render an iteration of a loop, returning whether a 'break' tag ended the
+    /// loop.
+    pub(crate) fn render_iteration(&mut self, nodes: &[Node]) -> InternalResult<bool> {
+        let looping = self.looping;
+        self.looping = true;
+        let result = self.render_nodes(nodes);
+        self.looping = looping;
+        result?;
+
+        Ok(matches!(self.end_position.take(), Some(EndPosition::Break)))
     }
 
-    pub(crate) fn consume_child<O2: Write + Debug>(&mut self, mut child: TemplateParser<R, O2>) -> InternalResult<()> {
-        self.give_context(Some(child.take_context()?));
-        self.give_input(Some(child.take_input()?));
+    /// Render the content of a loop tag once per item, with the loop context
+    /// if the loop is named, or its 'else' content if there are no items.
+    fn run_loop<I>(&mut self, tag: &Tag, loop_variable: Option<&str>, size: usize, items: I) -> InternalResult<()>
+    where
+        I: Iterator<Item = InternalResult<LoopItem>>,
+    {
+        let mut items = items.peekable();
+        if items.peek().is_none() {
+            if let Some(otherwise) = tag.otherwise() {
+                self.render_nodes(otherwise)?;
+            }
 
-        Ok(())
-    }
+            return Ok(());
+        }
 
-    pub(crate) fn parse_child(&mut self, parse_until: ParseUntil) -> InternalResult<String> {
-        let mut output_bytes = Vec::new();
-        let mut child = self.spawn_child(&mut output_bytes, parse_until)?;
-        child.parse_internal()?;
-        child.write()?;
-        self.consume_child(child)?;
+        let path = self.input()?.path().to_owned();
+        let mut previous = None;
+        let mut index = 0;
 
-        let output_string = String::from_utf8(output_bytes)
-            .into_internal("Invalid utf-8 found in output")?;
+        while let Some(item) = items.next() {
+            let item = item?;
 
-        Ok(output_string)
-    }
+            for (name, value) in item.variables.iter() {
+                self.context_mut()?.add_variable(name, &path, value);
+            }
 
-    fn parse_add(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
+            if let Some(loop_variable) = loop_variable {
+                let next = match items.peek() {
+                    Some(Ok(next)) => Some(next.text.clone()),
+                    _ => None,
+                };
+                self.context_mut()?.push_loop_frame(
+                    &path, LoopFrame::new(loop_variable.to_owned(), index, size, previous, next)
+                );
             }
 
-            self.buffer_all_until_end_of_tag("add")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+            let broken = self.render_iteration(tag.content())?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndAdd)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+            if loop_variable.is_some() {
+                self.context_mut()?.pop_loop_frame();
+            }
 
-            match end_position {
-                EndPosition::Add => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'add' tag, '{pos:?}'"
-                )))),
-            };
+            for (name, _) in item.variables.iter().rev() {
+                self.context_mut()?.pop_variable(name);
+            }
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+            if broken {
+                break;
+            }
 
-            Ok(())
+            previous = Some(item.text);
+            index += 1;
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
+        Ok(())
+    }
 
-            let value = self.parse_value_as_number("add")?;
+    /// Render nodes with a context of their own, i.e. the content of a
+    /// function.
+    pub(crate) fn render_sealed(&mut self, context: Context, nodes: &[Node]) -> InternalResult<()> {
+        let mut sealed = TemplateParser::new_internal(
+            context,
+            self.empty_input()?,
+            self.take_output()?,
+            self.dependencies.clone(),
+            self.templates.clone(),
+        )?;
+        sealed.extending = self.extending();
+        sealed.render_nodes(nodes)?;
+        sealed.parse_extend_or_flush()?;
 
-            self.expect_end_of_tag("add")?;
+        self.give_output(Some(sealed.take_output()?));
 
-            let content = self.parse_child(ParseUntil::EndAdd).into_step()?
-                .trim()
-                .parse::<i64>()
-                .into_internal("Failed to parse content as a number")
-                .into_step()?;
+        Ok(())
+    }
 
-            self.output_mut().into_step()?.write_str(&(value + content).to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+    /// Render nodes to a string rather than the output, i.e. the content of a
+    /// 'set' tag.
+    pub(crate) fn render_to_string(&mut self, nodes: &[Node]) -> InternalResult<String> {
+        let context = self.take_context()?;
+        let (context, output) = self.render_sealed_to_string(context, nodes)?;
+        self.give_context(Some(context));
 
-            Ok(())
-        }
+        Ok(output)
     }
 
-    fn parse_ad(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_add()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+    /// Render nodes with a context of their own to a string, giving the context
+    /// back.
+    pub(crate) fn render_sealed_to_string(
+        &mut self, context: Context, nodes: &[Node]
+    ) -> InternalResult<(Context, String)> {
+        let mut output_bytes = Vec::new();
+        let mut child = TemplateParser::new_internal(
+            context,
+            self.empty_input()?,
+            &mut output_bytes,
+            self.dependencies.clone(),
+            self.templates.clone(),
+        )?;
+        child.extending = self.extending();
+        child.render_nodes(nodes)?;
+        child.parse_extend_or_flush()?;
+        child.write()?;
 
-    fn parse_assert(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        let context = child.take_context()?;
+        drop(child);
 
-            self.buffer_all_until_end_of_self_closing_tag("assert")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let output = String::from_utf8(output_bytes)
+            .into_internal("Invalid utf-8 found in output")?;
 
-            Ok(())
-        }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        Ok((context, output))
+    }
 
-            self.output_mut().into_step()?.clear_buffer();
+    /// Parse the value, precision and content of an arithmetic tag, writing
+    /// the result of `operation`. The result is rounded half away from zero
+    /// to exactly the precision when one is given.
+    fn parse_arithmetic<F>(&mut self, tagname: &str, tag: &Tag, operation: F) -> StepResult<()>
+    where
+        F: FnOnce(Decimal, Decimal, Option<u32>) -> InternalResult<Decimal>,
+    {
+        self.output_mut().into_step()?.clear_buffer();
 
-            let condition = IfParser::parse_result("assert", self)?;
+        let value = self.parse_value_as_decimal(tagname)?;
+        let precision = self.parse_precision(tagname)?;
 
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-            self.expect_end_of_self_close_tag("assert")?;
+        self.expect_end_of_tag(tagname)?;
 
-            if !condition.as_evaluation() {
-                return Err(Err(InternalError::new(
-                    "ASSERTION FAILED"
-                )));
-            }
+        let content = self.render_to_string(tag.content()).into_step()?
+            .trim()
+            .parse::<Decimal>()
+            .into_internal("Failed to parse content as a number")
+            .into_step()?;
 
-            Ok(())
+        let mut result = operation(value, content, precision).into_step()?;
+        if let Some(precision) = precision {
+            result = result.round(precision).into_step()?;
         }
-    }
 
-    fn parse_asser(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_assert()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.output_mut().into_step()?.write_str(&result.to_string());
+        self.output_mut().into_step()?.flush_buffer_to_content();
 
-    fn parse_asse(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'r' => {
-                self.push_step()?;
-                self.parse_asser()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Ok(())
     }
 
-    fn parse_ass(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_asse()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_add(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("add");
         }
+
+        self.parse_arithmetic("add", tag, |value, content, _| value.add(content))
     }
 
-    fn parse_as(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            's' => {
-                self.push_step()?;
-                self.parse_ass()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_assert(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("assert");
         }
-    }
 
-    fn parse_a(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_ad()
-            },
-            's' => {
-                self.push_step()?;
-                self.parse_as()
-            },
-            _ => self.unexpected_tag(),
+        self.output_mut().into_step()?.clear_buffer();
+
+        let condition = IfParser::parse_result("assert", self)?;
+
+        self.output_mut().into_step()?.clear_buffer();
+        self.bypass_whitespace()?;
+        self.expect_end_of_self_close_tag("assert")?;
+
+        if !condition.as_evaluation() {
+            return Err(Err(InternalError::new(
+                "ASSERTION FAILED"
+            )));
         }
+
+        Ok(())
     }
 
     fn parse_basename(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("basename");
+        }
+
+        self.output_mut().into_step()?.clear_buffer();
+
+        let path = self.parse_value("basename")?
+            .into_internal("Cannot canonicalize a path from an empty value")
+            .into_step()?;
+
+        self.bypass_whitespace()?;
+
+        let mut reldir = if self.tag_current_or_unexpected_eof("basename")? == 'i' {
+            self.push_step()?;
+
+            self.tag_expect_char("basename", |c| matches!(c, 'n'))?;
+            self.bypass_whitespace()?;
 
-            self.buffer_all_until_end_of_self_closing_tag("basename")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+            self.output_mut().into_step()?.clear_buffer();
 
-            Ok(())
+            self.parse_value_as_path("basename")?
+                .into_internal("Cannot create an absolute path from a None path")
+                .into_step()?
         }
         else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+            let mut input_path = self.input().into_step()?.path().to_owned();
+            input_path.pop();
+            input_path
+        };
 
-            self.output_mut().into_step()?.clear_buffer();
+        reldir.push(path);
 
-            let path = self.parse_value("basename")?
-                .into_internal("Cannot canonicalize a path from an empty value")
-                .into_step()?;
+        self.record_path_dependency(&reldir);
 
-            self.bypass_whitespace()?;
+        let basename = canonicalize(&reldir)
+            .into_internal(format!("Failed to canonicalize relative path {reldir:?}"))
+            .into_step()?
+            .file_name()
+            .map(|osstr| osstr.to_str().unwrap_or("").to_owned())
+            .unwrap_or_else(String::new);
 
-            let mut reldir = if self.tag_current_or_unexpected_eof("basename")? == 'i' {
-                self.push_step()?;
+        self.bypass_whitespace()?;
 
-                self.tag_expect_char("basename", |c| matches!(c, 'n'))?;
-                self.bypass_whitespace()?;
+        self.output_mut().into_step()?.clear_buffer();
+        self.output_mut().into_step()?.write_str(&basename);
+        self.output_mut().into_step()?.flush_buffer_to_content();
 
-                self.output_mut().into_step()?.clear_buffer();
+        self.expect_end_of_self_close_tag("basename")?;
 
-                self.parse_value_as_path("basename")?
-                    .into_internal("Cannot create an absolute path from a None path")
-                    .into_step()?
-            }
-            else {
-                let mut input_path = self.input().into_step()?.path().to_owned();
-                input_path.pop();
-                input_path
-            };
+        Ok(())
+    }
 
-            reldir.push(path);
+    /// Render the block of a frame in place.
+    fn parse_block_frame(&mut self, frame: BlockFrame) -> InternalResult<()> {
+        let block = frame.block()
+            .into_internal(format!("Block '{}' has no definition", frame.name()))?
+            .clone();
 
-            let basename = canonicalize(&reldir)
-                .into_internal(format!("Failed to canonicalize relative path {reldir:?}"))
-                .into_step()?
-                .file_name()
-                .map(|osstr| osstr.to_str().unwrap_or("").to_owned())
-                .unwrap_or_else(String::new);
+        self.output_mut()?.flush_buffer_to_content();
+        self.context_mut()?.push_block_frame(frame);
 
-            self.bypass_whitespace()?;
+        let mut input = "".try_into_input()?;
+        input.set_path(block.path());
 
-            self.output_mut().into_step()?.clear_buffer();
-            self.output_mut().into_step()?.write_str(&basename);
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let mut limited = self.spawn_limited(input)?;
+        limited.render_nodes(block.content())?;
+        self.consume_limited(limited)?;
 
-            self.expect_end_of_self_close_tag("basename")?;
+        self.context_mut()?.pop_block_frame();
 
-            Ok(())
-        }
+        Ok(())
     }
 
-    fn parse_basenam(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_basename()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_block(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("block");
         }
-    }
 
-    fn parse_basena(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'm' => {
-                self.push_step()?;
-                self.parse_basenam()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.output_mut().into_step()?.clear_buffer();
 
-    fn parse_basen(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_basena()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let name = self.parse_variable_name("block")?;
 
-    fn parse_base(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'n' => {
-                self.push_step()?;
-                self.parse_basen()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.expect_end_of_tag("block")?;
 
-    fn parse_bas(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_base()
-            },
-            _ => self.unexpected_tag(),
+        let block = Block::new(self.input().into_step()?.path(), tag.content().clone());
+
+        // the template it extends decides where the block goes
+        if self.extending() {
+            self.context_mut().into_step()?.add_block(name, block);
+            return Ok(());
         }
+
+        let mut blocks = self.context().into_step()?.blocks(&name);
+        blocks.push(block);
+
+        self.parse_block_frame(BlockFrame::new(name, blocks)).into_step()
     }
 
-    fn parse_ba(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            's' => {
-                self.push_step()?;
-                self.parse_bas()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_break(&mut self) -> StepResult<()> {
+        self.output_mut().into_step()?.clear_buffer();
+        self.expect_end_of_self_close_tag("break")?;
+
+        if !self.looping {
+            return Err(Err(InternalError::new("The 'break' tag must be used within a loop")));
         }
+
+        self.set_end_position(EndPosition::Break);
+
+        Err(Ok(FlowControl::Break))
     }
 
-    fn parse_b(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_ba()
-            },
-            _ => self.unexpected_tag(),
+    /// Parse the arguments of a call to a function defined in a template and
+    /// build the context its body is parsed with. Arguments are given by
+    /// position and then by name, missing ones take their default value or
+    /// are left undefined.
+    fn parse_function_call(&mut self, tagname: &str, alias: &str, function: &Function) -> StepResult<Context> {
+        let params = function.params();
+        let mut values: Vec<Option<Option<String>>> = vec![None; params.len()];
+
+        let args = self.parse_function_call_args(tagname)?;
+        let positional = args.iter().filter(|(keyword, _)| keyword.is_none()).count();
+        if positional > params.len() {
+            return Err(Err(InternalError::new(format!(
+                "Function '{alias}' takes {} argument(s) but {positional} were given",
+                params.len(),
+            ))));
         }
-    }
 
-    fn parse_call(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        let mut named = false;
+        for (index, (keyword, value)) in args.into_iter().enumerate() {
+            let position = match keyword {
+                Some(keyword) => {
+                    named = true;
 
-            self.buffer_all_until_end_of_self_closing_tag("call")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+                    params.iter().position(|param| param.name() == keyword)
+                        .into_internal(format!("Function '{alias}' has no argument '{keyword}'"))
+                        .into_step()?
+                },
+                None if named => return Err(Err(InternalError::new(format!(
+                    "Positional arguments must come before named arguments in the call to '{alias}'"
+                )))),
+                None => index,
+            };
 
-            Ok(())
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
+            if values[position].is_some() {
+                return Err(Err(InternalError::new(format!(
+                    "Argument '{}' of function '{alias}' was given more than once",
+                    params[position].name(),
+                ))));
             }
 
-            let path = self.parse_value_as_path("call")?
-                .into_internal("Path was None and this message should be better")
-                .into_step()?;
-            self.expect_end_of_self_close_tag("call")?;
+            values[position] = Some(value);
+        }
 
-            // make sure we write all buffered content before spawning the sealed
-            // parser
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.parse_unsealed(path).into_step()?;
+        let path = self.input().into_step()?.path().to_owned();
+        let mut ctx = self.context().into_step()?.to_owned();
+        ctx.remove_variable("caller");
 
-            Ok(())
+        // an imported function sees the functions of its own file
+        for (name, sibling) in function.module() {
+            ctx.import_function(name.to_owned(), sibling.to_owned());
         }
-    }
 
-    fn parse_cal(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_call()
-            },
-            _ => self.unexpected_tag(),
+        for (param, value) in params.iter().zip(values) {
+            ctx.remove_variable(param.name());
+
+            let value = match value {
+                Some(value) => value,
+                None => param.default().cloned(),
+            };
+
+            if let Some(value) = value {
+                ctx.add_variable(param.name(), &path, value);
+            }
         }
+
+        Ok(ctx)
     }
 
-    fn parse_ca(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_cal()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_callblock(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("callblock");
         }
-    }
 
-    fn parse_compile(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.buffer_all_until_end_of_self_closing_tag("compile")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let alias = self.parse_variable_name("callblock")?;
+        let function = self.context().into_step()?.function(&alias)
+            .into_internal(format!("Function '{alias}' never defined"))
+            .into_step()?
+            .to_owned();
 
-            Ok(())
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        let mut ctx = self.parse_function_call("callblock", &alias, &function)?;
 
-            let path = self.parse_value_as_path("compile")?
-                .into_internal("Cannot canonicalize an empty value")
-                .into_step()?;
-            self.expect_end_of_self_close_tag("compile")?;
+        self.expect_end_of_tag("callblock")?;
 
-            // make sure we write all buffered content before spawning the sealed
-            // parser
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.parse_sealed(path).into_step()?;
+        // the body belongs to the caller, so it is rendered with its context
+        let (_, caller) = self.render_sealed_to_string(
+                self.context().into_step()?.to_owned(), tag.content()
+            )
+            .into_step()?;
+        ctx.add_markup("caller", self.input().into_step()?.path(), caller);
 
-            Ok(())
-        }
+        self.output_mut().into_step()?.flush_buffer_to_content();
+        self.render_sealed(ctx, function.content())
+            .into_step()
     }
 
-    fn parse_compil(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_compile()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_call(&mut self) -> StepResult<()> {
+        self.output_mut().into_step()?.clear_buffer();
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("call");
         }
-    }
 
-    fn parse_compi(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_compil()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let path = self.parse_value_as_path("call")?
+            .into_internal("Path was None and this message should be better")
+            .into_step()?;
+        self.expect_end_of_self_close_tag("call")?;
 
-    fn parse_comp(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_compi()
-            },
-            _ => self.unexpected_tag(),
-        }
+        // make sure we write all buffered content before spawning the sealed
+        // parser
+        self.output_mut().into_step()?.flush_buffer_to_content();
+        self.parse_unsealed(path).into_step()?;
+
+        Ok(())
     }
 
-    fn parse_com(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'p' => {
-                self.push_step()?;
-                self.parse_comp()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_compile(&mut self) -> StepResult<()> {
+        self.output_mut().into_step()?.clear_buffer();
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("compile");
         }
-    }
 
-    fn parse_count(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        let path = self.parse_value_as_path("compile")?
+            .into_internal("Cannot canonicalize an empty value")
+            .into_step()?;
+        self.expect_end_of_self_close_tag("compile")?;
 
-            self.buffer_all_until_end_of_self_closing_tag("count")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        // make sure we write all buffered content before spawning the sealed
+        // parser
+        self.output_mut().into_step()?.flush_buffer_to_content();
+        self.parse_sealed(path).into_step()?;
+
+        Ok(())
+    }
 
-            Ok(())
+    fn parse_count(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("count");
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
-            let alias = self.parse_variable_name("count")?;
-            let count = self.context().into_step()?
-                .values(&alias)
-                .unwrap_or(vec![])
-                .len();
-            self.expect_end_of_self_close_tag("count")?;
+        self.output_mut().into_step()?.clear_buffer();
+        let alias = self.parse_variable_name("count")?;
+        let count = self.context().into_step()?
+            .values(&alias)
+            .unwrap_or(vec![])
+            .len();
+        self.expect_end_of_self_close_tag("count")?;
 
-            self.output_mut().into_step()?.write_str(&count.to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.output_mut().into_step()?.write_str(&count.to_string());
+        self.output_mut().into_step()?.flush_buffer_to_content();
 
-            Ok(())
-        }
+        Ok(())
     }
 
-    fn parse_coun(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_count()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+    fn parse_continue(&mut self) -> StepResult<()> {
+        self.output_mut().into_step()?.clear_buffer();
+        self.expect_end_of_self_close_tag("continue")?;
 
-    fn parse_cou(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'n' => {
-                self.push_step()?;
-                self.parse_coun()
-            },
-            _ => self.unexpected_tag(),
+        if !self.looping {
+            return Err(Err(InternalError::new("The 'continue' tag must be used within a loop")));
         }
-    }
 
-    fn parse_co(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'm' => {
-                self.push_step()?;
-                self.parse_com()
-            },
-            'u' => {
-                self.push_step()?;
-                self.parse_cou()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.set_end_position(EndPosition::Continue);
 
-    fn parse_c(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_ca()
-            },
-            'o' => {
-                self.push_step()?;
-                self.parse_co()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Err(Ok(FlowControl::Break))
     }
 
     fn parse_dirname(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("dirname");
+        }
 
-            self.buffer_all_until_end_of_self_closing_tag("dirname")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.output_mut().into_step()?.clear_buffer();
 
-            Ok(())
-        }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        let path = self.parse_value("dirname")?
+            .into_internal("Cannot canonicalize a path from an empty value")
+            .into_step()?;
 
-            self.output_mut().into_step()?.clear_buffer();
+        self.bypass_whitespace()?;
 
-            let path = self.parse_value("dirname")?
-                .into_internal("Cannot canonicalize a path from an empty value")
-                .into_step()?;
+        let mut reldir = if self.tag_current_or_unexpected_eof("dirname")? == 'i' {
+            self.push_step()?;
 
+            self.tag_expect_char("dirname", |c| matches!(c, 'n'))?;
             self.bypass_whitespace()?;
 
-            let mut reldir = if self.tag_current_or_unexpected_eof("dirname")? == 'i' {
-                self.push_step()?;
-
-                self.tag_expect_char("dirname", |c| matches!(c, 'n'))?;
-                self.bypass_whitespace()?;
+            self.output_mut().into_step()?.clear_buffer();
 
-                self.output_mut().into_step()?.clear_buffer();
+            self.parse_value_as_path("dirname")?
+                .into_internal("Cannot create an absolute path from a None path")
+                .into_step()?
+        }
+        else {
+            let mut input_path = self.input().into_step()?.path().to_owned();
+            input_path.pop();
+            input_path
+        };
 
-                self.parse_value_as_path("dirname")?
-                    .into_internal("Cannot create an absolute path from a None path")
-                    .into_step()?
-            }
-            else {
-                let mut input_path = self.input().into_step()?.path().to_owned();
-                input_path.pop();
-                input_path
-            };
+        reldir.push(path);
 
-            reldir.push(path);
+        self.record_path_dependency(&reldir);
 
-            let dirname = canonicalize(&reldir)
-                .into_internal(format!("Failed to canonicalize relative path {reldir:?}"))
-                .into_step()?
-                .parent()
-                .map_or_else(
-                    String::new,
-                    |path| path.to_str()
-                        .map(|s| s.to_owned())
-                        .unwrap_or_else(String::new)
-                );
+        let dirname = canonicalize(&reldir)
+            .into_internal(format!("Failed to canonicalize relative path {reldir:?}"))
+            .into_step()?
+            .parent()
+            .map_or_else(
+                String::new,
+                |path| path.to_str()
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(String::new)
+            );
 
-            self.bypass_whitespace()?;
+        self.bypass_whitespace()?;
 
-            self.output_mut().into_step()?.clear_buffer();
-            self.output_mut().into_step()?.write_str(&dirname);
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.output_mut().into_step()?.clear_buffer();
+        self.output_mut().into_step()?.write_str(&dirname);
+        self.output_mut().into_step()?.flush_buffer_to_content();
 
-            self.expect_end_of_self_close_tag("dirname")?;
+        self.expect_end_of_self_close_tag("dirname")?;
 
-            Ok(())
-        }
+        Ok(())
     }
 
-    fn parse_dirnam(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_dirname()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_div(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("div");
         }
-    }
 
-    fn parse_dirna(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'm' => {
-                self.push_step()?;
-                self.parse_dirnam()
-            },
-            _ => self.unexpected_tag(),
-        }
+        self.parse_arithmetic("div", tag, |value, content, precision| {
+            match precision {
+                None => value.quotient(content),
+                Some(precision) => value.div(content, precision),
+            }
+        })
     }
 
-    fn parse_dirn(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_dirna()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_extend_tag(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("extend");
         }
-    }
 
-    fn parse_dir(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'n' => {
-                self.push_step()?;
-                self.parse_dirn()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let path = self.parse_value_as_path("extend")?
+            .into_internal("The extended path was None and this message sucks")
+            .into_step()?;
+        self.set_extend(path).into_step()?;
 
-    fn parse_div(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+        self.expect_end_of_self_close_tag("extend")?;
 
-            self.buffer_all_until_end_of_tag("div")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        Ok(())
+    }
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndDiv)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+    /// Parse the options of the 'fordir' and 'forfile' tags which follow the
+    /// directory. As 'recursive' and 'reversed' can't be told apart until
+    /// their third character, a 'reversed' right after the options is parsed
+    /// here as well.
+    fn parse_entry_options(&mut self, tagname: &str) -> StepResult<(EntryOptions, bool)> {
+        let mut options = EntryOptions::default();
+        let mut reversed = false;
 
-            match end_position {
-                EndPosition::Div => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'div' tag, '{pos:?}'"
-                )))),
-            };
+        loop {
+            match self.tag_current_or_unexpected_eof(tagname)? {
+                'h' => {
+                    for expected in "hidden".chars() {
+                        self.tag_expect_char(tagname, |c| c.eq(&expected))?;
+                    }
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+                    options.set_hidden(true);
+                },
+                'm' => {
+                    for expected in "matching".chars() {
+                        self.tag_expect_char(tagname, |c| c.eq(&expected))?;
+                    }
 
-            Ok(())
-        }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+                    self.bypass_whitespace()?;
+                    let pattern = self.parse_value(tagname)?.unwrap_or_default();
+                    options.set_matching(pattern);
+                },
+                's' => {
+                    for expected in "sort".chars() {
+                        self.tag_expect_char(tagname, |c| c.eq(&expected))?;
+                    }
 
-            self.output_mut().into_step()?.clear_buffer();
+                    self.bypass_whitespace()?;
+                    self.tag_expect_char(tagname, |c| c.eq(&'b'))?;
+                    self.tag_expect_char(tagname, |c| c.eq(&'y'))?;
+                    self.bypass_whitespace()?;
 
-            let value = self.parse_value_as_number("div")?;
+                    let sort = self.parse_variable_name(tagname)?;
+                    options.set_sort(sort.parse().into_step()?);
+                },
+                'r' => {
+                    self.tag_expect_char(tagname, |c| c.eq(&'r'))?;
+                    self.tag_expect_char(tagname, |c| c.eq(&'e'))?;
 
-            self.expect_end_of_tag("div")?;
+                    match self.tag_current_or_unexpected_eof(tagname)? {
+                        'c' => {
+                            for expected in "cursive".chars() {
+                                self.tag_expect_char(tagname, |c| c.eq(&expected))?;
+                            }
 
-            let content = self.parse_child(ParseUntil::EndDiv).into_step()?
-                .trim()
-                .parse::<i64>()
-                .into_internal("Failed to parse content as a number")
-                .into_step()?;
+                            options.set_recursive(true);
+                        },
+                        'v' => {
+                            for expected in "versed".chars() {
+                                self.tag_expect_char(tagname, |c| c.eq(&expected))?;
+                            }
 
-            self.output_mut().into_step()?
-                .write_str(&value.checked_div(content).unwrap_or(0).to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+                            reversed = true;
+                        },
+                        _ => return self.tag_unexpected_char(tagname),
+                    }
+                },
+                _ => break,
+            }
 
-            Ok(())
+            self.bypass_whitespace()?;
         }
-    }
 
-    fn parse_di(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'r' => {
-                self.push_step()?;
-                self.parse_dir()
-            },
-            'v' => {
-                self.push_step()?;
-                self.parse_div()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Ok((options, reversed))
     }
 
-    fn parse_d(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_di()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_fordir(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("fordir");
         }
-    }
 
-    fn parse_else(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            match self.parse_until() {
-                ParseUntil::EndFordir|
-                ParseUntil::EndForeach|
-                ParseUntil::EndForfile|
-                ParseUntil::EndForsplit|
-                ParseUntil::EndIf => {},
-                _ => {
-                    return self.unexpected_tag();
-                },
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.expect_end_of_tag_buffer("else")?;
-            self.set_end_position(EndPosition::Else);
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let variable = self.parse_variable_name("fordir")?;
 
-            Err(Ok(FlowControl::Break))
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'fordir' tag")));
         }
-        else {
-            match self.parse_until() {
-                ParseUntil::EndFordir|
-                ParseUntil::EndForeach|
-                ParseUntil::EndForfile|
-                ParseUntil::EndForsplit|
-                ParseUntil::EndIf => {},
-                _ => {
-                    return self.unexpected_tag();
-                },
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
-            self.expect_end_of_tag("else")?;
-            self.set_end_position(EndPosition::Else);
+        self.tag_expect_char("fordir", |c| matches!(c, 'i'))?;
+        self.tag_expect_char("fordir", |c| matches!(c, 'n'))?;
 
-            Err(Ok(FlowControl::Break))
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'fordir' tag")));
         }
-    }
 
-    fn parse_els(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_else()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let path = self.parse_value_as_path("fordir")?
+            .into_internal("Cannot iterate over directories within a None path")
+            .into_step()?;
 
-    fn parse_el(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            's' => {
-                self.push_step()?;
-                self.parse_els()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.bypass_whitespace()?;
 
-    fn parse_extend_tag(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        let (options, reversed) = self.parse_entry_options("fordir")?;
 
-            self.buffer_all_until_end_of_self_closing_tag("extend")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let mut from_idx = None;
+        if self.tag_current_or_unexpected_eof("fordir")? == 'f' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("fordir", |c| matches!(c, 'r'))?;
+            self.tag_expect_buffer_char("fordir", |c| matches!(c, 'o'))?;
+            self.tag_expect_buffer_char("fordir", |c| matches!(c, 'm'))?;
 
-            Ok(())
+            self.bypass_whitespace()?;
+            from_idx = Some(self.parse_expression_as_number("fordir")?);
+            self.bypass_whitespace()?;
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            let path = self.parse_value_as_path("extend")?
-                .into_internal("The extended path was None and this message sucks")
-                .into_step()?;
-            self.set_extend(path);
-
-            self.expect_end_of_self_close_tag("extend")?;
-
-            Ok(())
-        }
-    }
+        let mut to_idx = None;
+        if self.tag_current_or_unexpected_eof("fordir")? == 't' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("fordir", |c| matches!(c, 'o'))?;
 
-    fn parse_exten(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_extend_tag()
-            },
-            _ => self.unexpected_tag(),
+            self.bypass_whitespace()?;
+            to_idx = Some(self.parse_expression_as_number("fordir")?);
+            self.bypass_whitespace()?;
         }
-    }
 
-    fn parse_exte(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'n' => {
-                self.push_step()?;
-                self.parse_exten()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let mut loop_variable = None;
+        if self.tag_current_or_unexpected_eof("fordir")? == 'a' {
+            self.push_step()?;
 
-    fn parse_ext(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_exte()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+            match self.tag_current_or_unexpected_eof("fordir")? {
+                's' => {
+                    self.push_step()?;
 
-    fn parse_ex(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_ext()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+                    self.bypass_whitespace()?;
 
-    fn parse_e(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_el()
-            },
-            'x' => {
-                self.push_step()?;
-                self.parse_ex()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+                    loop_variable = Some(self.parse_variable_name("fordir")?);
 
-    fn parse_fordir(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
+                    self.bypass_whitespace()?;
+                },
+                _ => return self.tag_unexpected_char("fordir"),
             }
+        }
 
-            self.buffer_all_until_end_of_tag("fordir")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndFordir)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        let reversed = if self.tag_current_or_unexpected_eof("fordir")? == 'r' {
+            self.push_step()?;
 
-            match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndFordir)
-                        .into_step()?;
-                    self.output_mut().into_step()?.write_bytes_to_buffer(else_content);
-                },
-                EndPosition::Fordir => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'fordir' tag, '{pos:?}'"
-                )))),
-            };
+            self.tag_expect_buffer_char("fordir", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("fordir", |c| c.eq(&'v'))?;
+            self.tag_expect_buffer_char("fordir", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("fordir", |c| c.eq(&'r'))?;
+            self.tag_expect_buffer_char("fordir", |c| c.eq(&'s'))?;
+            self.tag_expect_buffer_char("fordir", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("fordir", |c| c.eq(&'d'))?;
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+            self.output_mut().into_step()?.clear_buffer();
+            self.bypass_whitespace()?;
 
-            Ok(())
+            true
         }
         else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
-
-            self.output_mut().into_step()?.clear_buffer();
+            reversed
+        };
 
-            let variable = self.parse_variable_name("fordir")?;
+        self.expect_end_of_tag("fordir")?;
 
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'fordir' tag")));
-            }
+        let mut read = Vec::new();
+        let entries = options.read(&path, true, &mut read);
+        for dir in read.iter() {
+            self.record_dir_dependency(dir);
+        }
+        let mut dirpaths = entries.into_step()?;
 
-            self.tag_expect_char("fordir", |c| matches!(c, 'i'))?;
-            self.tag_expect_char("fordir", |c| matches!(c, 'n'))?;
+        if from_idx.is_some() || to_idx.is_some() {
+            let min = from_idx.unwrap_or(0_i64);
+            let max = to_idx.unwrap_or(dirpaths.len() as i64);
 
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'fordir' tag")));
-            }
+            dirpaths = dirpaths.into_iter()
+                .enumerate()
+                .filter(|(i, _)| (i.to_owned() as i64) >= min)
+                .filter(|(i, _)| (i.to_owned() as i64) < max)
+                .map(|(_, v)| v)
+                .collect::<Vec<PathBuf>>();
+        }
 
-            let path = self.parse_value_as_path("fordir")?
-                .into_internal("Cannot iterate over directories within a None path")
-                .into_step()?;
 
-            self.bypass_whitespace()?;
+        if reversed {
+            dirpaths.reverse();
+        }
 
-            let mut from_idx = None;
-            if self.tag_current_or_unexpected_eof("fordir")? == 'f' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("fordir", |c| matches!(c, 'r'))?;
-                self.tag_expect_buffer_char("fordir", |c| matches!(c, 'o'))?;
-                self.tag_expect_buffer_char("fordir", |c| matches!(c, 'm'))?;
+        let size = dirpaths.len();
+        let items = dirpaths.iter()
+            .map(|dirpath| Ok(LoopItem::new(&variable, dirpath.to_str().unwrap_or("").to_owned())));
 
-                self.bypass_whitespace()?;
-                from_idx = Some(self.parse_value_as_number("fordir")?);
-                self.bypass_whitespace()?;
-            }
+        self.run_loop(tag, loop_variable.as_deref(), size, items).into_step()
+    }
 
-            let mut to_idx = None;
-            if self.tag_current_or_unexpected_eof("fordir")? == 't' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("fordir", |c| matches!(c, 'o'))?;
+    fn parse_foreach(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("foreach");
+        }
 
-                self.bypass_whitespace()?;
-                to_idx = Some(self.parse_value_as_number("fordir")?);
-                self.bypass_whitespace()?;
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            let mut loop_variable = None;
-            if self.tag_current_or_unexpected_eof("fordir")? == 'a' {
-                self.push_step()?;
+        let variable = self.parse_variable_name("foreach")?;
 
-                match self.tag_current_or_unexpected_eof("fordir")? {
-                    's' => {
-                        self.push_step()?;
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'foreach' tag")));
+        }
 
-                        self.bypass_whitespace()?;
+        self.tag_expect_char("foreach", |c| matches!(c, 'i'))?;
+        self.tag_expect_char("foreach", |c| matches!(c, 'n'))?;
 
-                        loop_variable = Some(self.parse_variable_name("fordir")?);
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'foreach' tag")));
+        }
 
-                        self.bypass_whitespace()?;
-                    },
-                    _ => return self.tag_unexpected_char("fordir"),
-                }
-            }
+        let alias = self.parse_variable_name("foreach")?;
 
-            let reversed = if self.tag_current_or_unexpected_eof("fordir")? == 'r' {
-                self.push_step()?;
+        self.bypass_whitespace()?;
 
-                self.tag_expect_buffer_char("fordir", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("fordir", |c| c.eq(&'v'))?;
-                self.tag_expect_buffer_char("fordir", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("fordir", |c| c.eq(&'r'))?;
-                self.tag_expect_buffer_char("fordir", |c| c.eq(&'s'))?;
-                self.tag_expect_buffer_char("fordir", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("fordir", |c| c.eq(&'d'))?;
+        let mut from_idx = None;
+        if self.tag_current_or_unexpected_eof("foreach")? == 'f' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("foreach", |c| matches!(c, 'r'))?;
+            self.tag_expect_buffer_char("foreach", |c| matches!(c, 'o'))?;
+            self.tag_expect_buffer_char("foreach", |c| matches!(c, 'm'))?;
 
-                self.output_mut().into_step()?.clear_buffer();
-                self.bypass_whitespace()?;
+            self.bypass_whitespace()?;
+            from_idx = Some(self.parse_expression_as_number("foreach")?);
+            self.bypass_whitespace()?;
+        }
 
-                true
-            }
-            else {
-                false
-            };
+        let mut to_idx = None;
+        if self.tag_current_or_unexpected_eof("foreach")? == 't' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("foreach", |c| matches!(c, 'o'))?;
 
-            self.expect_end_of_tag("fordir")?;
+            self.bypass_whitespace()?;
+            to_idx = Some(self.parse_expression_as_number("foreach")?);
+            self.bypass_whitespace()?;
+        }
 
-            let mut dirpaths = path.read_dir()
-                .into_internal("Failed to read directory")
-                .into_step()?
-                .map(|direntry_res| direntry_res.map(|de| de.path()))
-                .collect::<io::Result<Vec<PathBuf>>>()
-                .into_internal("Failed to read paths of directory entries")
-                .into_step()?
-                .into_iter()
-                .filter(|direntry| direntry.is_dir())
-                .collect::<Vec<PathBuf>>();
+        let mut loop_variable = None;
+        if self.tag_current_or_unexpected_eof("foreach")? == 'a' {
+            self.push_step()?;
 
-            dirpaths.sort_unstable_by(|a, b| a.file_name().cmp(&b.file_name()));
+            self.tag_expect_buffer_char("foreach", |c| matches!(c, 's'))?;
 
-            if from_idx.is_some() || to_idx.is_some() {
-                let min = from_idx.unwrap_or(0_i64);
-                let max = to_idx.unwrap_or(dirpaths.len() as i64);
+            self.bypass_whitespace()?;
+            loop_variable = Some(self.parse_variable_name("foreach")?);
+            self.bypass_whitespace()?;
+        }
 
-                dirpaths = dirpaths.into_iter()
-                    .enumerate()
-                    .filter(|(i, _)| (i.to_owned() as i64) >= min)
-                    .filter(|(i, _)| (i.to_owned() as i64) < max)
-                    .map(|(_, v)| v)
-                    .collect::<Vec<PathBuf>>();
-            }
+        let reversed = if self.tag_current_or_unexpected_eof("foreach")? == 'r' {
+            self.push_step()?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndFordir)
-                .into_step()?;
-            let else_content = match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndFordir)
-                        .into_step()?;
-                    Some(else_content)
-                },
-                EndPosition::Fordir => None,
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'fordir' tag, '{pos:?}'"
-                )))),
-            };
+            self.tag_expect_buffer_char("foreach", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("foreach", |c| c.eq(&'v'))?;
+            self.tag_expect_buffer_char("foreach", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("foreach", |c| c.eq(&'r'))?;
+            self.tag_expect_buffer_char("foreach", |c| c.eq(&'s'))?;
+            self.tag_expect_buffer_char("foreach", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("foreach", |c| c.eq(&'d'))?;
 
-            if !dirpaths.is_empty() {
-                let last = dirpaths.len();
+            self.output_mut().into_step()?.clear_buffer();
+            self.bypass_whitespace()?;
 
-                if reversed {
-                    dirpaths.reverse();
-                }
+            true
+        }
+        else {
+            false
+        };
 
-                let path = self.input().into_step()?.path().to_owned();
-                for (index, dirpath) in dirpaths.into_iter().enumerate() {
-                    let dirstr = dirpath.to_str().unwrap_or("").to_owned();
-                    self.context_mut().into_step()?.add_variable(&variable, &path, dirstr);
-
-                    if let Some(loop_variable) = loop_variable.clone() {
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.index"), &path, index.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.size"), &path, last.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.isfirst"), &path, if index == 0 { "1" } else { "0" });
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.islast"), &path, if index + 1 == last { "1" } else { "0" });
-                    }
+        self.expect_end_of_tag("foreach")?;
 
-                    self.parse_limited(
-                        content.as_slice(),
-                        ParseUntil::EndFordir
-                    ).into_step()?;
+        let mut values = self.context().into_step()?
+            .values(&alias)
+            .unwrap_or(vec![])
+            .into_iter()
+            .map(|v| v.to_owned())
+            .collect::<Vec<String>>();
 
-                    self.context_mut().into_step()?.pop_variable(&variable);
-                }
-            }
-            else if let Some(content) = else_content {
-                self.parse_limited(content.as_slice(), ParseUntil::EndFordir)
-                    .into_step()?;
-            }
+        if from_idx.is_some() || to_idx.is_some() {
+            let min = from_idx.unwrap_or(0_i64);
+            let max = to_idx.unwrap_or(values.len() as i64);
 
-            Ok(())
+            values = values.into_iter()
+                .enumerate()
+                .filter(|(i, _)| (i.to_owned() as i64) >= min)
+                .filter(|(i, _)| (i.to_owned() as i64) < max)
+                .map(|(_, v)| v)
+                .collect::<Vec<String>>();
         }
-    }
 
-    fn parse_fordi(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'r' => {
-                self.push_step()?;
-                self.parse_fordir()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
 
-    fn parse_ford(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_fordi()
-            },
-            _ => self.unexpected_tag(),
+        if reversed {
+            values.reverse();
         }
-    }
 
-    fn parse_foreach(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+        let size = values.len();
+        let items = values.into_iter().map(|value| Ok(LoopItem::new(&variable, value)));
 
-            self.buffer_all_until_end_of_tag("foreach")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.run_loop(tag, loop_variable.as_deref(), size, items).into_step()
+    }
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndForeach)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+    fn parse_forfile(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("forfile");
+        }
 
-            match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndForeach)
-                        .into_step()?;
-                    self.output_mut().into_step()?.write_bytes_to_buffer(else_content);
-                },
-                EndPosition::Foreach => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'foreach' tag, '{pos:?}'"
-                )))),
-            };
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let variable = self.parse_variable_name("forfile")?;
 
-            Ok(())
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'forfile' tag")));
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
+        self.tag_expect_char("forfile", |c| matches!(c, 'i'))?;
+        self.tag_expect_char("forfile", |c| matches!(c, 'n'))?;
 
-            let variable = self.parse_variable_name("foreach")?;
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'forfile' tag")));
+        }
 
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'foreach' tag")));
-            }
+        let path = self.parse_value_as_path("forfile")?
+            .into_internal("Cannot iterate over files within a None path")
+            .into_step()?;
 
-            self.tag_expect_char("foreach", |c| matches!(c, 'i'))?;
-            self.tag_expect_char("foreach", |c| matches!(c, 'n'))?;
+        self.bypass_whitespace()?;
 
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'foreach' tag")));
-            }
+        let (options, reversed) = self.parse_entry_options("forfile")?;
 
-            let alias = self.parse_variable_name("foreach")?;
+        let mut from_idx = None;
+        if self.tag_current_or_unexpected_eof("forfile")? == 'f' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("forfile", |c| matches!(c, 'r'))?;
+            self.tag_expect_buffer_char("forfile", |c| matches!(c, 'o'))?;
+            self.tag_expect_buffer_char("forfile", |c| matches!(c, 'm'))?;
 
             self.bypass_whitespace()?;
+            from_idx = Some(self.parse_expression_as_number("forfile")?);
+            self.bypass_whitespace()?;
+        }
 
-            let mut from_idx = None;
-            if self.tag_current_or_unexpected_eof("foreach")? == 'f' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("foreach", |c| matches!(c, 'r'))?;
-                self.tag_expect_buffer_char("foreach", |c| matches!(c, 'o'))?;
-                self.tag_expect_buffer_char("foreach", |c| matches!(c, 'm'))?;
+        let mut to_idx = None;
+        if self.tag_current_or_unexpected_eof("forfile")? == 't' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("forfile", |c| matches!(c, 'o'))?;
 
-                self.bypass_whitespace()?;
-                from_idx = Some(self.parse_value_as_number("foreach")?);
-                self.bypass_whitespace()?;
-            }
+            self.bypass_whitespace()?;
+            to_idx = Some(self.parse_expression_as_number("forfile")?);
+            self.bypass_whitespace()?;
+        }
 
-            let mut to_idx = None;
-            if self.tag_current_or_unexpected_eof("foreach")? == 't' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("foreach", |c| matches!(c, 'o'))?;
+        let mut loop_variable = None;
+        if self.tag_current_or_unexpected_eof("forfile")? == 'a' {
+            self.push_step()?;
 
-                self.bypass_whitespace()?;
-                to_idx = Some(self.parse_value_as_number("foreach")?);
-                self.bypass_whitespace()?;
-            }
+            match self.tag_current_or_unexpected_eof("forfile")? {
+                's' => {
+                    self.push_step()?;
 
-            let mut loop_variable = None;
-            if self.tag_current_or_unexpected_eof("foreach")? == 'a' {
-                self.push_step()?;
+                    self.bypass_whitespace()?;
 
-                self.tag_expect_buffer_char("foreach", |c| matches!(c, 's'))?;
+                    loop_variable = Some(self.parse_variable_name("forfile")?);
 
-                self.bypass_whitespace()?;
-                loop_variable = Some(self.parse_variable_name("foreach")?);
-                self.bypass_whitespace()?;
+                    self.bypass_whitespace()?;
+                },
+                _ => return self.tag_unexpected_char("forfile"),
             }
+        }
 
-            let reversed = if self.tag_current_or_unexpected_eof("foreach")? == 'r' {
-                self.push_step()?;
+        let reversed = if self.tag_current_or_unexpected_eof("forfile")? == 'r' {
+            self.push_step()?;
 
-                self.tag_expect_buffer_char("foreach", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("foreach", |c| c.eq(&'v'))?;
-                self.tag_expect_buffer_char("foreach", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("foreach", |c| c.eq(&'r'))?;
-                self.tag_expect_buffer_char("foreach", |c| c.eq(&'s'))?;
-                self.tag_expect_buffer_char("foreach", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("foreach", |c| c.eq(&'d'))?;
+            self.tag_expect_buffer_char("forfile", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forfile", |c| c.eq(&'v'))?;
+            self.tag_expect_buffer_char("forfile", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forfile", |c| c.eq(&'r'))?;
+            self.tag_expect_buffer_char("forfile", |c| c.eq(&'s'))?;
+            self.tag_expect_buffer_char("forfile", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forfile", |c| c.eq(&'d'))?;
 
-                self.output_mut().into_step()?.clear_buffer();
-                self.bypass_whitespace()?;
+            self.output_mut().into_step()?.clear_buffer();
+            self.bypass_whitespace()?;
 
-                true
-            }
-            else {
-                false
-            };
+            true
+        }
+        else {
+            reversed
+        };
 
-            self.expect_end_of_tag("foreach")?;
+        self.expect_end_of_tag("forfile")?;
 
-            let mut values = self.context().into_step()?
-                .values(&alias)
-                .unwrap_or(vec![])
-                .into_iter()
-                .map(|v| v.to_owned())
-                .collect::<Vec<String>>();
+        let mut read = Vec::new();
+        let entries = options.read(&path, false, &mut read);
+        for dir in read.iter() {
+            self.record_dir_dependency(dir);
+        }
+        let mut filepaths = entries.into_step()?;
 
-            if from_idx.is_some() || to_idx.is_some() {
-                let min = from_idx.unwrap_or(0_i64);
-                let max = to_idx.unwrap_or(values.len() as i64);
+        if from_idx.is_some() || to_idx.is_some() {
+            let min = from_idx.unwrap_or(0_i64);
+            let max = to_idx.unwrap_or(filepaths.len() as i64);
 
-                values = values.into_iter()
-                    .enumerate()
-                    .filter(|(i, _)| (i.to_owned() as i64) >= min)
-                    .filter(|(i, _)| (i.to_owned() as i64) < max)
-                    .map(|(_, v)| v)
-                    .collect::<Vec<String>>();
-            }
+            filepaths = filepaths.into_iter()
+                .enumerate()
+                .filter(|(i, _)| (i.to_owned() as i64) >= min)
+                .filter(|(i, _)| (i.to_owned() as i64) < max)
+                .map(|(_, v)| v)
+                .collect::<Vec<PathBuf>>();
+        }
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndForeach)
-                .into_step()?;
-            let else_content = match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndForeach)
-                        .into_step()?;
-                    Some(else_content)
-                },
-                EndPosition::Foreach => None,
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'foreach' tag, '{pos:?}'"
-                )))),
-            };
 
-            if !values.is_empty() {
-                let last = values.len();
+        if reversed {
+            filepaths.reverse();
+        }
 
-                if reversed {
-                    values.reverse();
-                }
+        let size = filepaths.len();
+        let items = filepaths.iter()
+            .map(|filepath| Ok(LoopItem::new(&variable, filepath.to_str().unwrap_or("").to_owned())));
 
-                let path = self.input().into_step()?.path().to_owned();
-                for (index, value) in values.into_iter().enumerate() {
-                    self.context_mut().into_step()?.add_variable(&variable, &path, value.clone());
-
-                    if let Some(loop_variable) = loop_variable.clone() {
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.index"), &path, index.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.size"), &path, last.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.isfirst"), &path, if index == 0 { "1" } else { "0" });
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.islast"), &path, if index + 1 == last { "1" } else { "0" });
-                    }
+        self.run_loop(tag, loop_variable.as_deref(), size, items).into_step()
+    }
 
-                    self.parse_limited(content.as_slice(), ParseUntil::EndForeach).into_step()?;
+    fn parse_forsplit(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("forsplit");
+        }
 
-                    self.context_mut().into_step()?.pop_variable(&variable);
-                }
-            }
-            else if let Some(content) = else_content {
-                self.parse_limited(content.as_slice(), ParseUntil::EndForeach)
-                    .into_step()?;
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            Ok(())
-        }
-    }
+        let variable = self.parse_variable_name("forsplit")?;
 
-    fn parse_foreac(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'h' => {
-                self.push_step()?;
-                self.parse_foreach()
-            },
-            _ => self.unexpected_tag(),
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'forsplit' tag")));
         }
-    }
 
-    fn parse_forea(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'c' => {
-                self.push_step()?;
-                self.parse_foreac()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.tag_expect_char("forsplit", |c| matches!(c, 'i'))?;
+        self.tag_expect_char("forsplit", |c| matches!(c, 'n'))?;
 
-    fn parse_fore(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_forea()
-            },
-            _ => self.unexpected_tag(),
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'forsplit' tag")));
         }
-    }
 
-    fn parse_forfile(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+        let str_value = self.parse_value("forsplit")?;
 
-            self.buffer_all_until_end_of_tag("forfile")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.bypass_whitespace()?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndForfile)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        self.tag_expect_char("forsplit", |c| matches!(c, 'o'))?;
+        self.tag_expect_char("forsplit", |c| matches!(c, 'n'))?;
 
-            match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndForfile)
-                        .into_step()?;
-                    self.output_mut().into_step()?.write_bytes_to_buffer(else_content);
-                },
-                EndPosition::Forfile => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'forfile' tag, '{pos:?}'"
-                )))),
-            };
+        self.bypass_whitespace()?;
+
+        let delimiter = self.parse_value("forsplit")?;
+
+        self.bypass_whitespace()?;
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let mut from_idx = None;
+        if self.tag_current_or_unexpected_eof("forsplit")? == 'f' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'r'))?;
+            self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'o'))?;
+            self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'm'))?;
 
-            Ok(())
+            self.bypass_whitespace()?;
+            from_idx = Some(self.parse_expression_as_number("forsplit")?);
+            self.bypass_whitespace()?;
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
+        let mut to_idx = None;
+        if self.tag_current_or_unexpected_eof("forsplit")? == 't' {
+            self.push_step()?;
+            self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'o'))?;
+
+            self.bypass_whitespace()?;
+            to_idx = Some(self.parse_expression_as_number("forsplit")?);
+            self.bypass_whitespace()?;
+        }
 
-            let variable = self.parse_variable_name("forfile")?;
+        let mut loop_variable = None;
+        if self.tag_current_or_unexpected_eof("forsplit")? == 'a' {
+            self.push_step()?;
 
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'forfile' tag")));
-            }
+            match self.tag_current_or_unexpected_eof("forsplit")? {
+                's' => {
+                    self.push_step()?;
 
-            self.tag_expect_char("forfile", |c| matches!(c, 'i'))?;
-            self.tag_expect_char("forfile", |c| matches!(c, 'n'))?;
+                    self.bypass_whitespace()?;
 
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'forfile' tag")));
+                    loop_variable = Some(self.parse_variable_name("forsplit")?);
+
+                    self.bypass_whitespace()?;
+                },
+                _ => return self.tag_unexpected_char("forsplit"),
             }
+        }
 
-            let path = self.parse_value_as_path("forfile")?
-                .into_internal("Cannot iterate over files within a None path")
-                .into_step()?;
+        let reversed = if self.tag_current_or_unexpected_eof("forsplit")? == 'r' {
+            self.push_step()?;
 
+            self.tag_expect_buffer_char("forsplit", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forsplit", |c| c.eq(&'v'))?;
+            self.tag_expect_buffer_char("forsplit", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forsplit", |c| c.eq(&'r'))?;
+            self.tag_expect_buffer_char("forsplit", |c| c.eq(&'s'))?;
+            self.tag_expect_buffer_char("forsplit", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forsplit", |c| c.eq(&'d'))?;
+
+            self.output_mut().into_step()?.clear_buffer();
             self.bypass_whitespace()?;
 
-            let mut from_idx = None;
-            if self.tag_current_or_unexpected_eof("forfile")? == 'f' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("forfile", |c| matches!(c, 'r'))?;
-                self.tag_expect_buffer_char("forfile", |c| matches!(c, 'o'))?;
-                self.tag_expect_buffer_char("forfile", |c| matches!(c, 'm'))?;
+            true
+        }
+        else {
+            false
+        };
 
-                self.bypass_whitespace()?;
-                from_idx = Some(self.parse_value_as_number("forfile")?);
-                self.bypass_whitespace()?;
-            }
+        self.expect_end_of_tag("forsplit")?;
 
-            let mut to_idx = None;
-            if self.tag_current_or_unexpected_eof("forfile")? == 't' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("forfile", |c| matches!(c, 'o'))?;
+        let mut values = match str_value {
+            Some(str_value) => match delimiter {
+                Some(delimiter) => if !delimiter.is_empty() {
+                    str_value.split(&delimiter)
+                        .map(|s| s.to_owned())
+                        .collect::<Vec<String>>()
+                }
+                else {
+                    str_value.chars()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<String>>()
+                },
+                None => str_value.chars().map(|c| c.to_string()).collect::<Vec<String>>(),
+            },
+            None => Vec::new(),
+        };
 
-                self.bypass_whitespace()?;
-                to_idx = Some(self.parse_value_as_number("forfile")?);
-                self.bypass_whitespace()?;
-            }
+        if from_idx.is_some() || to_idx.is_some() {
+            let min = from_idx.unwrap_or(0_i64);
+            let max = to_idx.unwrap_or(values.len() as i64);
 
-            let mut loop_variable = None;
-            if self.tag_current_or_unexpected_eof("forfile")? == 'a' {
-                self.push_step()?;
+            values = values.into_iter()
+                .enumerate()
+                .filter(|(i, _)| (i.to_owned() as i64) >= min)
+                .filter(|(i, _)| (i.to_owned() as i64) < max)
+                .map(|(_, v)| v)
+                .collect::<Vec<String>>();
+        }
 
-                match self.tag_current_or_unexpected_eof("forfile")? {
-                    's' => {
-                        self.push_step()?;
 
-                        self.bypass_whitespace()?;
+        if reversed {
+            values.reverse();
+        }
 
-                        loop_variable = Some(self.parse_variable_name("forfile")?);
+        let size = values.len();
+        let items = values.into_iter().map(|value| Ok(LoopItem::new(&variable, value)));
 
-                        self.bypass_whitespace()?;
-                    },
-                    _ => return self.tag_unexpected_char("forfile"),
-                }
-            }
+        self.run_loop(tag, loop_variable.as_deref(), size, items).into_step()
+    }
 
-            let reversed = if self.tag_current_or_unexpected_eof("forfile")? == 'r' {
-                self.push_step()?;
+    fn parse_forcsv(&mut self, tag: &Tag) -> StepResult<()> {
+        self.parse_for_records(tag, "forcsv", true)
+    }
 
-                self.tag_expect_buffer_char("forfile", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("forfile", |c| c.eq(&'v'))?;
-                self.tag_expect_buffer_char("forfile", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("forfile", |c| c.eq(&'r'))?;
-                self.tag_expect_buffer_char("forfile", |c| c.eq(&'s'))?;
-                self.tag_expect_buffer_char("forfile", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("forfile", |c| c.eq(&'d'))?;
+    fn parse_forlines(&mut self, tag: &Tag) -> StepResult<()> {
+        self.parse_for_records(tag, "forlines", false)
+    }
 
-                self.output_mut().into_step()?.clear_buffer();
-                self.bypass_whitespace()?;
+    /// Parse a 'forlines' or 'forcsv' tag, which loop through the lines or the
+    /// rows of a file as it is read rather than loading it whole.
+    fn parse_for_records(&mut self, tag: &Tag, tagname: &str, csv: bool) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char(tagname);
+        }
 
-                true
-            }
-            else {
-                false
-            };
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.expect_end_of_tag("forfile")?;
+        let variable = self.parse_variable_name(tagname)?;
 
-            let mut filepaths = path.read_dir()
-                .into_internal("Failed to read directory")
-                .into_step()?
-                .map(|direntry_res| direntry_res.map(|de| de.path()))
-                .collect::<io::Result<Vec<PathBuf>>>()
-                .into_internal("Failed to read paths of directory entries")
-                .into_step()?
-                .into_iter()
-                .filter(|direntry| direntry.is_file())
-                .collect::<Vec<PathBuf>>();
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new(format!("Unexpected character in '{tagname}' tag"))));
+        }
 
-            if from_idx.is_some() || to_idx.is_some() {
-                let min = from_idx.unwrap_or(0_i64);
-                let max = to_idx.unwrap_or(filepaths.len() as i64);
+        self.tag_expect_char(tagname, |c| matches!(c, 'i'))?;
+        self.tag_expect_char(tagname, |c| matches!(c, 'n'))?;
 
-                filepaths = filepaths.into_iter()
-                    .enumerate()
-                    .filter(|(i, _)| (i.to_owned() as i64) >= min)
-                    .filter(|(i, _)| (i.to_owned() as i64) < max)
-                    .map(|(_, v)| v)
-                    .collect::<Vec<PathBuf>>();
-            }
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new(format!("Unexpected character in '{tagname}' tag"))));
+        }
 
-            filepaths.sort_unstable_by(|a, b| a.file_name().cmp(&b.file_name()));
+        let path = self.parse_value_as_path(tagname)?
+            .into_internal("Cannot iterate over the records of a None path")
+            .into_step()?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndForfile)
-                .into_step()?;
-            let else_content = match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndForfile)
-                        .into_step()?;
-                    Some(else_content)
-                },
-                EndPosition::Forfile => None,
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'forfile' tag, '{pos:?}'"
-                )))),
-            };
+        self.bypass_whitespace()?;
 
-            if !filepaths.is_empty() {
-                let last = filepaths.len();
+        let header = if csv && self.tag_current_or_unexpected_eof(tagname)? == 'h' {
+            self.push_step()?;
 
-                if reversed {
-                    filepaths.reverse();
-                }
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'a'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'d'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'r'))?;
 
-                let path = self.input().into_step()?.path().to_owned();
-                for (index, filepath) in filepaths.into_iter().enumerate() {
-                    let filestr = filepath.to_str().unwrap_or("").to_owned();
-                    self.context_mut().into_step()?.add_variable(&variable, &path, filestr);
-
-                    if let Some(loop_variable) = loop_variable.clone() {
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.index"), &path, index.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.size"), &path, last.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.isfirst"), &path, if index == 0 { "1" } else { "0" });
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.islast"), &path, if index + 1 == last { "1" } else { "0" });
-                    }
+            self.output_mut().into_step()?.clear_buffer();
+            self.bypass_whitespace()?;
 
-                    self.parse_limited(
-                        content.as_slice(),
-                        ParseUntil::EndForfile
-                    ).into_step()?;
+            true
+        }
+        else {
+            false
+        };
 
-                    self.context_mut().into_step()?.pop_variable(&variable);
-                }
-            }
-            else if let Some(content) = else_content {
-                self.parse_limited(content.as_slice(), ParseUntil::EndForfile)
-                    .into_step()?;
-            }
+        let mut from_idx = None;
+        if self.tag_current_or_unexpected_eof(tagname)? == 'f' {
+            self.push_step()?;
+            self.tag_expect_buffer_char(tagname, |c| matches!(c, 'r'))?;
+            self.tag_expect_buffer_char(tagname, |c| matches!(c, 'o'))?;
+            self.tag_expect_buffer_char(tagname, |c| matches!(c, 'm'))?;
 
-            Ok(())
+            self.bypass_whitespace()?;
+            from_idx = Some(self.parse_expression_as_number(tagname)?);
+            self.bypass_whitespace()?;
         }
-    }
 
-    fn parse_forfil(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_forfile()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let mut to_idx = None;
+        if self.tag_current_or_unexpected_eof(tagname)? == 't' {
+            self.push_step()?;
+            self.tag_expect_buffer_char(tagname, |c| matches!(c, 'o'))?;
 
-    fn parse_forfi(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_forfil()
-            },
-            _ => self.unexpected_tag(),
+            self.bypass_whitespace()?;
+            to_idx = Some(self.parse_expression_as_number(tagname)?);
+            self.bypass_whitespace()?;
         }
-    }
 
-    fn parse_forf(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_forfi()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let mut loop_variable = None;
+        if self.tag_current_or_unexpected_eof(tagname)? == 'a' {
+            self.push_step()?;
 
-    fn parse_forsplit(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+            match self.tag_current_or_unexpected_eof(tagname)? {
+                's' => {
+                    self.push_step()?;
 
-            self.buffer_all_until_end_of_tag("forsplit")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+                    self.bypass_whitespace()?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndForsplit)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+                    loop_variable = Some(self.parse_variable_name(tagname)?);
 
-            match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndForsplit)
-                        .into_step()?;
-                    self.output_mut().into_step()?.write_bytes_to_buffer(else_content);
+                    self.bypass_whitespace()?;
                 },
-                EndPosition::Forsplit => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'forsplit' tag, '{pos:?}'"
-                )))),
-            };
+                _ => return self.tag_unexpected_char(tagname),
+            }
+        }
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let reversed = if self.tag_current_or_unexpected_eof(tagname)? == 'r' {
+            self.push_step()?;
 
-            Ok(())
-        }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'v'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'r'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'s'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char(tagname, |c| c.eq(&'d'))?;
 
             self.output_mut().into_step()?.clear_buffer();
+            self.bypass_whitespace()?;
 
-            let variable = self.parse_variable_name("forsplit")?;
-
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'forsplit' tag")));
-            }
+            true
+        }
+        else {
+            false
+        };
 
-            self.tag_expect_char("forsplit", |c| matches!(c, 'i'))?;
-            self.tag_expect_char("forsplit", |c| matches!(c, 'n'))?;
+        self.expect_end_of_tag(tagname)?;
 
-            if !self.bypass_whitespace_enforce_one()? {
-                return Err(Err(InternalError::new("Unexpected character in 'forsplit' tag")));
-            }
+        self.record_dependency(&path);
 
-            let str_value = self.parse_value("forsplit")?;
 
-            self.bypass_whitespace()?;
+        let min = from_idx.unwrap_or(0).max(0) as usize;
+        let count = to_idx.map_or(usize::MAX, |to| (to.max(0) as usize).saturating_sub(min));
 
-            self.tag_expect_char("forsplit", |c| matches!(c, 'o'))?;
-            self.tag_expect_char("forsplit", |c| matches!(c, 'n'))?;
+        let mut records = Records::open(&path, csv).into_step()?;
+        let columns = if header {
+            records.next().transpose().into_step()?.map(|record| record.fields).unwrap_or_default()
+        }
+        else {
+            Vec::new()
+        };
+        let records = records.skip(min).take(count);
 
-            self.bypass_whitespace()?;
+        // reversing, and knowing the size for the loop context, take
+        // reading through the file before looping
+        let buffered = reversed || loop_variable.is_some();
+        let (records, size): (Box<dyn Iterator<Item = InternalResult<Record>>>, usize) = if buffered {
+            let mut records = records.collect::<InternalResult<Vec<Record>>>()
+                .into_step()?;
 
-            let delimiter = self.parse_value("forsplit")?;
+            if reversed {
+                records.reverse();
+            }
 
-            self.bypass_whitespace()?;
+            let size = records.len();
+            (Box::new(records.into_iter().map(Ok)), size)
+        }
+        else {
+            (Box::new(records), 0)
+        };
 
-            let mut from_idx = None;
-            if self.tag_current_or_unexpected_eof("forsplit")? == 'f' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'r'))?;
-                self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'o'))?;
-                self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'm'))?;
+        let items = records.map(|record| {
+            let Record { fields, text, } = record?;
 
-                self.bypass_whitespace()?;
-                from_idx = Some(self.parse_value_as_number("forsplit")?);
-                self.bypass_whitespace()?;
+            if !csv {
+                return Ok(LoopItem::new(&variable, text));
             }
 
-            let mut to_idx = None;
-            if self.tag_current_or_unexpected_eof("forsplit")? == 't' {
-                self.push_step()?;
-                self.tag_expect_buffer_char("forsplit", |c| matches!(c, 'o'))?;
+            let mut variables = Vec::new();
+            for (i, field) in fields.into_iter().enumerate() {
+                if let Some(column) = columns.get(i).filter(|column| !column.is_empty()) {
+                    variables.push((format!("{variable}.{column}"), field.clone()));
+                }
 
-                self.bypass_whitespace()?;
-                to_idx = Some(self.parse_value_as_number("forsplit")?);
-                self.bypass_whitespace()?;
+                variables.push((format!("{variable}.{i}"), field));
             }
 
-            let mut loop_variable = None;
-            if self.tag_current_or_unexpected_eof("forsplit")? == 'a' {
-                self.push_step()?;
+            // the previous and next rows of the loop context are the rows as
+            // written in the file
+            Ok(LoopItem { variables, text, })
+        });
 
-                match self.tag_current_or_unexpected_eof("forsplit")? {
-                    's' => {
-                        self.push_step()?;
+        self.run_loop(tag, loop_variable.as_deref(), size, items).into_step()
+    }
 
-                        self.bypass_whitespace()?;
+    fn parse_forrange(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("forrange");
+        }
 
-                        loop_variable = Some(self.parse_variable_name("forsplit")?);
+        self.output_mut().into_step()?.clear_buffer();
 
-                        self.bypass_whitespace()?;
-                    },
-                    _ => return self.tag_unexpected_char("forsplit"),
-                }
-            }
+        let variable = self.parse_variable_name("forrange")?;
 
-            let reversed = if self.tag_current_or_unexpected_eof("forsplit")? == 'r' {
-                self.push_step()?;
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new("Unexpected character in 'forrange' tag")));
+        }
 
-                self.tag_expect_buffer_char("forsplit", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("forsplit", |c| c.eq(&'v'))?;
-                self.tag_expect_buffer_char("forsplit", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("forsplit", |c| c.eq(&'r'))?;
-                self.tag_expect_buffer_char("forsplit", |c| c.eq(&'s'))?;
-                self.tag_expect_buffer_char("forsplit", |c| c.eq(&'e'))?;
-                self.tag_expect_buffer_char("forsplit", |c| c.eq(&'d'))?;
+        let mut from = 0_i64;
+        if self.tag_current_or_unexpected_eof("forrange")? == 'f' {
+            self.tag_expect_char("forrange", |c| matches!(c, 'f'))?;
+            self.tag_expect_char("forrange", |c| matches!(c, 'r'))?;
+            self.tag_expect_char("forrange", |c| matches!(c, 'o'))?;
+            self.tag_expect_char("forrange", |c| matches!(c, 'm'))?;
 
-                self.output_mut().into_step()?.clear_buffer();
-                self.bypass_whitespace()?;
+            self.bypass_whitespace()?;
+            from = self.parse_expression_as_number("forrange")?;
+            self.bypass_whitespace()?;
+        }
 
-                true
-            }
-            else {
-                false
-            };
+        self.tag_expect_char("forrange", |c| matches!(c, 't'))?;
+        self.tag_expect_char("forrange", |c| matches!(c, 'o'))?;
 
-            self.expect_end_of_tag("forsplit")?;
+        self.bypass_whitespace()?;
+        let to = self.parse_expression_as_number("forrange")?;
+        self.bypass_whitespace()?;
 
-            let mut values = match str_value {
-                Some(str_value) => match delimiter {
-                    Some(delimiter) => if !delimiter.is_empty() {
-                        str_value.split(&delimiter)
-                            .map(|s| s.to_owned())
-                            .collect::<Vec<String>>()
-                    }
-                    else {
-                        str_value.chars()
-                            .map(|c| c.to_string())
-                            .collect::<Vec<String>>()
-                    },
-                    None => str_value.chars().map(|c| c.to_string()).collect::<Vec<String>>(),
-                },
-                None => Vec::new(),
-            };
+        let mut step = 1_i64;
+        if self.tag_current_or_unexpected_eof("forrange")? == 's' {
+            self.tag_expect_char("forrange", |c| matches!(c, 's'))?;
+            self.tag_expect_char("forrange", |c| matches!(c, 't'))?;
+            self.tag_expect_char("forrange", |c| matches!(c, 'e'))?;
+            self.tag_expect_char("forrange", |c| matches!(c, 'p'))?;
 
-            if from_idx.is_some() || to_idx.is_some() {
-                let min = from_idx.unwrap_or(0_i64);
-                let max = to_idx.unwrap_or(values.len() as i64);
+            self.bypass_whitespace()?;
+            step = self.parse_expression_as_number("forrange")?;
+            self.bypass_whitespace()?;
 
-                values = values.into_iter()
-                    .enumerate()
-                    .filter(|(i, _)| (i.to_owned() as i64) >= min)
-                    .filter(|(i, _)| (i.to_owned() as i64) < max)
-                    .map(|(_, v)| v)
-                    .collect::<Vec<String>>();
+            if step == 0 {
+                return Err(Err(InternalError::new("The step of a 'forrange' tag cannot be 0")));
             }
+        }
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndForsplit)
-                .into_step()?;
-            let else_content = match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndForsplit)
-                        .into_step()?;
-                    Some(else_content)
-                },
-                EndPosition::Forsplit => None,
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'forsplit' tag, '{pos:?}'"
-                )))),
-            };
-
-            if !values.is_empty() {
-                let last = values.len();
+        let mut loop_variable = None;
+        if self.tag_current_or_unexpected_eof("forrange")? == 'a' {
+            self.push_step()?;
 
-                if reversed {
-                    values.reverse();
-                }
+            match self.tag_current_or_unexpected_eof("forrange")? {
+                's' => {
+                    self.push_step()?;
 
-                let path = self.input().into_step()?.path().to_owned();
-                for (index, value) in values.into_iter().enumerate() {
-                    self.context_mut().into_step()?.add_variable(&variable, &path, value.clone());
-
-                    if let Some(loop_variable) = loop_variable.clone() {
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.index"), &path, index.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.size"), &path, last.to_string());
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.isfirst"), &path, if index == 0 { "1" } else { "0" });
-                        self.context_mut().into_step()?
-                            .add_variable(format!("{loop_variable}.islast"), &path, if index + 1 == last { "1" } else { "0" });
-                    }
+                    self.bypass_whitespace()?;
 
-                    self.parse_limited(content.as_slice(), ParseUntil::EndForsplit).into_step()?;
+                    loop_variable = Some(self.parse_variable_name("forrange")?);
 
-                    self.context_mut().into_step()?.pop_variable(&variable);
-                }
-            }
-            else if let Some(content) = else_content {
-                self.parse_limited(content.as_slice(), ParseUntil::EndForsplit)
-                    .into_step()?;
+                    self.bypass_whitespace()?;
+                },
+                _ => return self.tag_unexpected_char("forrange"),
             }
-
-            Ok(())
         }
-    }
 
-    fn parse_forspli(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_forsplit()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        let reversed = if self.tag_current_or_unexpected_eof("forrange")? == 'r' {
+            self.push_step()?;
 
-    fn parse_forspl(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_forspli()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+            self.tag_expect_buffer_char("forrange", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forrange", |c| c.eq(&'v'))?;
+            self.tag_expect_buffer_char("forrange", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forrange", |c| c.eq(&'r'))?;
+            self.tag_expect_buffer_char("forrange", |c| c.eq(&'s'))?;
+            self.tag_expect_buffer_char("forrange", |c| c.eq(&'e'))?;
+            self.tag_expect_buffer_char("forrange", |c| c.eq(&'d'))?;
 
-    fn parse_forsp(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_forspl()
-            },
-            _ => self.unexpected_tag(),
+            self.output_mut().into_step()?.clear_buffer();
+            self.bypass_whitespace()?;
+
+            true
         }
-    }
+        else {
+            false
+        };
 
-    fn parse_fors(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'p' => {
-                self.push_step()?;
-                self.parse_forsp()
-            },
-            _ => self.unexpected_tag(),
+        self.expect_end_of_tag("forrange")?;
+
+        // the number of values from 'from' up to, but not including, 'to'
+        let (from, to, step) = (from as i128, to as i128, step as i128);
+        let last = if (step > 0 && to > from) || (step < 0 && to < from) {
+            ((to - from).abs() - 1) / step.abs() + 1
         }
-    }
+        else {
+            0
+        };
 
-    fn parse_for(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_ford()
-            },
-            'e' => {
-                self.push_step()?;
-                self.parse_fore()
-            },
-            'f' => {
-                self.push_step()?;
-                self.parse_forf()
-            },
-            's' => {
-                self.push_step()?;
-                self.parse_fors()
-            },
-            _ => self.unexpected_tag(),
+
+        let value_at = |index: i128| if reversed {
+            from + (last - 1 - index) * step
         }
+        else {
+            from + index * step
+        };
+        let items = (0..last).map(|index| Ok(LoopItem::new(&variable, value_at(index).to_string())));
+
+        self.run_loop(tag, loop_variable.as_deref(), last as usize, items).into_step()
     }
 
-    fn parse_fo(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'r' => {
-                self.push_step()?;
-                self.parse_for()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_fn(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("fn");
         }
-    }
 
-    fn parse_fn(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.buffer_all_until_end_of_tag("fn")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let name = self.parse_variable_name("fn")?;
+        let args = self.parse_function_params("fn")?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndFn)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        self.expect_end_of_tag("fn")?;
 
-            match end_position {
-                EndPosition::Fn => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'fn' tag, '{pos:?}'"
-                )))),
-            };
+        self.context_mut().into_step()?.add_function(name, args, tag.content().clone());
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        Ok(())
+    }
 
-            Ok(())
+    fn parse_from(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("from");
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
+        self.output_mut().into_step()?.clear_buffer();
 
-            let name = self.parse_variable_name("fn")?;
-            let args = self.parse_function_args("fn")?;
+        let path = self.parse_value_as_path("from")?
+            .into_internal("The imported path was None")
+            .into_step()?;
 
-            self.expect_end_of_tag("fn")?;
+        self.bypass_whitespace()?;
+        for expected in "import".chars() {
+            self.tag_expect_char("from", |c| c.eq(&expected))?;
+        }
 
-            let (content, end_pos,) = self.parse_bypassed(ParseUntil::EndFn).into_step()?;
-            match end_pos {
-                EndPosition::Fn => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'fn' tag, '{pos:?}'"
-                )))),
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("from");
+        }
+
+        let mut names = Vec::new();
+        loop {
+            names.push(self.parse_variable_name("from")?);
+            self.bypass_whitespace()?;
+
+            if self.tag_current_or_unexpected_eof("from")? != ',' {
+                break;
             }
 
-            let content_str = String::from_utf8(content)
-                .into_internal(format!("Invalid utf-8 in function body of '{name}'"))
-                .into_step()?;
+            self.tag_expect_char("from", |c| c.eq(&','))?;
+            self.bypass_whitespace()?;
+        }
 
-            self.context_mut().into_step()?.add_function(name, args, content_str);
+        self.expect_end_of_self_close_tag("from")?;
 
-            Ok(())
+        let imported = self.parse_imported(path.clone()).into_step()?;
+        for name in names {
+            let function = imported.iter()
+                .find_map(|(imported, function)| imported.eq(&name).then_some(function))
+                .into_internal(format!("Function '{name}' is not defined in {path:?}"))
+                .into_step()?
+                .to_owned();
+
+            self.context_mut().into_step()?.import_function(name, function);
         }
+
+        Ok(())
     }
 
-    fn parse_f(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'n' => {
-                self.push_step()?;
-                self.parse_fn()
-            },
-            'o' => {
-                self.push_step()?;
-                self.parse_fo()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_if(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("if");
         }
-    }
 
-    fn parse_if(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.buffer_all_until_sequence("if", &['%', '}'])?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let condition = IfParser::parse_result("if", self)?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndIf)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        self.output_mut().into_step()?.clear_buffer();
+        self.bypass_whitespace()?;
+        self.expect_end_of_tag("if")?;
 
-            match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndIf)
-                        .into_step()?;
-                    self.output_mut().into_step()?.write_bytes_to_buffer(else_content);
-                },
-                EndPosition::If => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'if' tag, '{pos:?}'"
-                )))),
-            };
+        // render if block
+        if condition.as_evaluation() {
+            self.render_nodes(tag.content()).into_step()?;
+        }
+        // render else block
+        else if let Some(otherwise) = tag.otherwise() {
+            self.render_nodes(otherwise).into_step()?;
+        }
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        Ok(())
+    }
 
-            Ok(())
+    fn parse_include(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("include");
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
+        self.output_mut().into_step()?.clear_buffer();
 
-            let condition = IfParser::parse_result("if", self)?;
+        let path = self.parse_value_as_path("include")?
+            .into_internal("The included path was None and this message needs improvement")
+            .into_step()?;
 
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-            self.expect_end_of_tag("if")?;
+        self.bypass_whitespace()?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndIf)
-                .into_step()?;
-            let else_content = match end_position {
-                EndPosition::Else => {
-                    let (else_content, ..) = self.parse_bypassed(ParseUntil::EndIf)
-                        .into_step()?;
-                    Some(else_content)
-                },
-                EndPosition::If => None,
-                pos => return Err(Err(InternalError::new(format!("Invalid end position in 'if' tag, '{pos:?}'")))),
-            };
+        let escape = if self.tag_current_or_unexpected_eof("include")? == 'r' {
+            self.push_step()?;
 
-            // parse if block
-            if condition.as_evaluation() {
-                self.parse_limited(content.as_slice(), ParseUntil::EndIf).into_step()?;
-            }
-            // parse else block
-            else if let Some(else_content) = else_content {
-                self.parse_limited(else_content.as_slice(), ParseUntil::EndIf).into_step()?;
-            }
+            self.tag_expect_buffer_char("include", |c| c.eq(&'a'))?;
+            self.tag_expect_buffer_char("include", |c| c.eq(&'w'))?;
+
+            self.output_mut().into_step()?.clear_buffer();
 
-            Ok(())
+            Escape::None
         }
-    }
+        else {
+            self.context().into_step()?.escape()
+        };
 
-    fn parse_include(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        self.record_dependency(&path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(false)
+            .create(false)
+            .open(&path)
+            .into_internal(format!("Failed to open file {path:?}"))
+            .into_step()?;
+
+        self.output_mut().into_step()?.flush_buffer_and_file(file, escape)
+            .into_step()?;
+
+        self.expect_end_of_self_close_tag("include")?;
 
-            self.buffer_all_until_end_of_self_closing_tag("include")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        Ok(())
+    }
 
-            Ok(())
+    fn parse_import(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("import");
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
+        self.output_mut().into_step()?.clear_buffer();
 
-            let path = self.parse_value_as_path("include")?
-                .into_internal("The included path was None and this message needs improvement")
-                .into_step()?;
-            let file = OpenOptions::new()
-                .read(true)
-                .write(false)
-                .create(false)
-                .open(&path)
-                .into_internal(format!("Failed to open file {path:?}"))
-                .into_step()?;
+        let path = self.parse_value_as_path("import")?
+            .into_internal("The imported path was None")
+            .into_step()?;
 
-            self.output_mut().into_step()?.flush_buffer_and_file(file)
-                .into_step()?;
+        self.bypass_whitespace()?;
+        self.tag_expect_char("import", |c| c.eq(&'a'))?;
+        self.tag_expect_char("import", |c| c.eq(&'s'))?;
+        self.bypass_whitespace()?;
 
-            self.expect_end_of_self_close_tag("include")?;
+        let namespace = self.parse_variable_name("import")?;
+        self.bypass_whitespace()?;
 
-            Ok(())
-        }
-    }
+        self.expect_end_of_self_close_tag("import")?;
 
-    fn parse_includ(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_include()
-            },
-            _ => self.unexpected_tag(),
+        let imported = self.parse_imported(path).into_step()?;
+        let context = self.context_mut().into_step()?;
+        for (name, function) in imported {
+            context.import_function(format!("{namespace}.{name}"), function);
         }
-    }
 
-    fn parse_inclu(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_includ()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Ok(())
     }
 
-    fn parse_incl(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'u' => {
-                self.push_step()?;
-                self.parse_inclu()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_length(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("length");
         }
-    }
 
-    fn parse_inc(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_incl()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.output_mut().into_step()?.clear_buffer();
+        let length = self.parse_value("length")?
+            .unwrap_or_else(String::new)
+            .chars()
+            .count();
+        self.expect_end_of_self_close_tag("length")?;
 
-    fn parse_in(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'c' => {
-                self.push_step()?;
-                self.parse_inc()
-            },
-            _ => self.unexpected_tag(),
-        }
+        self.output_mut().into_step()?.write_str(&length.to_string());
+        self.output_mut().into_step()?.flush_buffer_to_content();
+
+        Ok(())
     }
 
-    fn parse_i(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'f' => {
-                self.push_step()?;
-                self.parse_if()
-            },
-            'n' => {
-                self.push_step()?;
-                self.parse_in()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_load(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("load");
         }
-    }
 
-    fn parse_length(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.buffer_all_until_end_of_self_closing_tag("length")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let path = self.parse_value_as_path("load")?
+            .into_internal("Cannot load data from a None path")
+            .into_step()?;
 
-            Ok(())
-        }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        self.bypass_whitespace()?;
 
-            self.output_mut().into_step()?.clear_buffer();
-            let length = self.parse_value("length")?
-                .unwrap_or_else(String::new)
-                .len();
-            self.expect_end_of_self_close_tag("length")?;
+        let mut prefix = String::new();
+        if self.tag_current_or_unexpected_eof("load")? == 'a' {
+            self.push_step()?;
 
-            self.output_mut().into_step()?.write_str(&length.to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+            self.tag_expect_buffer_char("load", |c| matches!(c, 's'))?;
 
-            Ok(())
+            self.bypass_whitespace()?;
+            prefix = self.parse_variable_name("load")?;
         }
-    }
 
-    fn parse_lengt(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'h' => {
-                self.push_step()?;
-                self.parse_length()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.expect_end_of_self_close_tag("load")?;
 
-    fn parse_leng(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_lengt()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.record_dependency(&path);
+        let json = read_to_string(&path)
+            .into_internal(format!("Failed to read file {path:?}"))
+            .into_step()?;
 
-    fn parse_len(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'g' => {
-                self.push_step()?;
-                self.parse_leng()
-            },
-            _ => self.unexpected_tag(),
-        }
+        self.context_mut().into_step()?.merge_json(prefix, &path, json)
+            .into_step()?;
+
+        Ok(())
     }
 
-    fn parse_le(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'n' => {
-                self.push_step()?;
-                self.parse_len()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_mod(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("mod");
         }
+
+        self.parse_arithmetic("mod", tag, |value, content, _| value.rem(content))
     }
 
-    fn parse_l(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_le()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_mul(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("mul");
         }
+
+        self.parse_arithmetic("mul", tag, |value, content, _| value.mul(content))
     }
 
-    fn parse_mod(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+    fn parse_nth(&mut self, tag: &Tag) -> StepResult<()> {
+        self.output_mut().into_step()?.clear_buffer();
+        self.bypass_whitespace()?;
 
-            self.buffer_all_until_end_of_tag("mod")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let alias = self.parse_variable_name("nth")?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndMod)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        self.expect_end_of_tag("nth")?;
 
-            match end_position {
-                EndPosition::Mod => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'mod' tag, '{pos:?}'"
-                )))),
-            };
+        let output = self.render_to_string(tag.content()).into_step()?;
+        let values = self.context().into_step()?.values(&alias);
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let trimmed = output.trim();
 
-            Ok(())
+        let was_neg_zero;
+        let idx = if trimmed == "-0" {
+            was_neg_zero = true;
+            0
         }
         else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
-
-            self.output_mut().into_step()?.clear_buffer();
-
-            let value = self.parse_value_as_number("mod")?;
+            was_neg_zero = false;
+            output.trim()
+                .parse::<i64>()
+                .map_err(|_| InternalError::new(format!(
+                    "Content was not an integer:\n{output}"
+                )))
+                .into_step()?
+        };
 
-            self.expect_end_of_tag("mod")?;
+        let idx_to_take = if was_neg_zero || idx < 0_i64 {
+            values.as_ref().map_or(0_usize, |vs| vs.len()
+                .saturating_sub(1)
+                .saturating_sub((0 - idx) as usize)
+            )
+        }
+        else {
+            idx as usize
+        };
 
-            let content = self.parse_child(ParseUntil::EndMod).into_step()?
-                .trim()
-                .parse::<i64>()
-                .into_internal("Failed to parse content as a number")
-                .into_step()?;
+        let value = values.and_then(
+            |vs| vs.into_iter().nth(idx_to_take).map(|v| v.to_owned())
+        );
 
-            self.output_mut().into_step()?.write_str(&(value % content).to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.output_mut().into_step()?.write_str(&value.unwrap_or(String::new()));
+        self.output_mut().into_step()?.flush_buffer_to_content();
 
-            Ok(())
-        }
+        Ok(())
     }
 
-    fn parse_mo(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_mod()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_path(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("path");
         }
-    }
 
-    fn parse_mul(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.buffer_all_until_end_of_tag("mul")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let path = self.parse_value("path")?
+            .into_internal("Cannot canonicalize a path from an empty value")
+            .into_step()?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndMul)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        self.bypass_whitespace()?;
 
-            match end_position {
-                EndPosition::Mul => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'mul' tag, '{pos:?}'"
-                )))),
-            };
+        let mut reldir = if self.tag_current_or_unexpected_eof("path")? == 'i' {
+            self.push_step()?;
+
+            self.tag_expect_char("path", |c| matches!(c, 'n'))?;
+            self.bypass_whitespace()?;
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+            self.output_mut().into_step()?.clear_buffer();
 
-            Ok(())
+            self.parse_value_as_path("path")?
+                .into_internal("Cannot create an absolute path from a None path")
+                .into_step()?
         }
         else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+            let mut input_path = self.input().into_step()?.path().to_owned();
+            input_path.pop();
+            input_path
+        };
 
-            self.output_mut().into_step()?.clear_buffer();
+        reldir.push(path);
 
-            let value = self.parse_value_as_number("mul")?;
+        self.record_path_dependency(&reldir);
 
-            self.expect_end_of_tag("mul")?;
+        let dir = canonicalize(&reldir)
+            .into_internal(format!("Failed to canonicalize relative path {reldir:?}"))
+            .into_step()?;
 
-            let content = self.parse_child(ParseUntil::EndMul).into_step()?
-                .trim()
-                .parse::<i64>()
-                .into_internal("Failed to parse content as a number")
-                .into_step()?;
+        self.bypass_whitespace()?;
 
-            self.output_mut().into_step()?.write_str(&(value * content).to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.output_mut().into_step()?.clear_buffer();
+        self.output_mut().into_step()?.write_str(dir.to_str().unwrap_or(""));
+        self.output_mut().into_step()?.flush_buffer_to_content();
 
-            Ok(())
-        }
-    }
+        self.expect_end_of_self_close_tag("path")?;
 
-    fn parse_mu(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_mul()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Ok(())
     }
 
-    fn parse_m(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'o' => {
-                self.push_step()?;
-                self.parse_mo()
-            },
-            'u' => {
-                self.push_step()?;
-                self.parse_mu()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_pow(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("pow");
         }
-    }
 
-    fn parse_nth(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+        self.parse_arithmetic("pow", tag, |value, content, precision| {
+            value.pow(content, precision.unwrap_or(DIVISION_SCALE))
+        })
+    }
 
-            self.buffer_all_until_end_of_tag("nth")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+    fn parse_pop(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("pop");
+        }
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndNth)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        self.output_mut().into_step()?.clear_buffer();
+        let variable = self.parse_variable_name("pop")?;
+        self.expect_end_of_self_close_tag("pop")?;
 
-            match end_position {
-                EndPosition::Nth => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'nth' tag, '{pos:?}'"
-                )))),
-            };
+        self.context_mut().into_step()?.pop_variable(&variable);
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        Ok(())
+    }
 
-            Ok(())
+    fn parse_replace(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("replace");
         }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
 
-            let alias = self.parse_variable_name("nth")?;
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.expect_end_of_tag("nth")?;
+        let variable = self.parse_variable_name("replace")?;
 
-            let output = self.parse_child(ParseUntil::EndNth).into_step()?;
-            let values = self.context().into_step()?.values(&alias);
+        self.bypass_whitespace()?;
+        if self.tag_current_or_unexpected_eof("replace")? == '=' {
+            self.input_mut().into_step()?.step().into_step()?;
 
-            let trimmed = output.trim();
+            let value = self.parse_expression("replace")?.unwrap_or_default();
+            self.expect_end_of_self_close_tag("replace")?;
 
-            let was_neg_zero;
-            let idx = if trimmed == "-0" {
-                was_neg_zero = true;
-                0
-            }
-            else {
-                was_neg_zero = false;
-                output.trim()
-                    .parse::<i64>()
-                    .map_err(|_| InternalError::new(format!(
-                        "Content was not an integer:\n{output}"
-                    )))
-                    .into_step()?
-            };
+            let path = self.input().into_step()?.path().to_owned();
+            self.context_mut().into_step()?.replace_variable(variable, path, value);
 
-            let idx_to_take = if was_neg_zero || idx < 0_i64 {
-                values.as_ref().map_or(0_usize, |vs| vs.len()
-                    .saturating_sub(1)
-                    .saturating_sub((0 - idx) as usize)
-                )
-            }
-            else {
-                idx as usize
-            };
+            return Ok(());
+        }
 
-            let value = values.and_then(
-                |vs| vs.into_iter().nth(idx_to_take).map(|v| v.to_owned())
-            );
+        self.expect_end_of_tag("replace")?;
 
-            self.output_mut().into_step()?.write_str(&value.unwrap_or(String::new()));
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        let content = self.render_to_string(tag.content()).into_step()?;
+        let path = self.input().into_step()?.path().to_owned();
 
-            Ok(())
-        }
-    }
+        self.context_mut().into_step()?.replace_markup(variable, path, content);
 
-    fn parse_nt(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'h' => {
-                self.push_step()?;
-                self.parse_nth()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Ok(())
     }
 
-    fn parse_n(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_nt()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_set(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("set");
         }
-    }
-
-    fn parse_path(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.buffer_all_until_end_of_self_closing_tag("path")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.output_mut().into_step()?.clear_buffer();
 
-            Ok(())
-        }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+        let variable = self.parse_variable_name("set")?;
 
-            self.output_mut().into_step()?.clear_buffer();
+        self.bypass_whitespace()?;
+        if self.tag_current_or_unexpected_eof("set")? == '=' {
+            self.input_mut().into_step()?.step().into_step()?;
 
-            let path = self.parse_value("path")?
-                .into_internal("Cannot canonicalize a path from an empty value")
-                .into_step()?;
+            let value = self.parse_expression("set")?.unwrap_or_default();
+            self.expect_end_of_self_close_tag("set")?;
 
-            self.bypass_whitespace()?;
+            let path = self.input().into_step()?.path().to_owned();
+            self.context_mut().into_step()?.add_variable(variable, path, value);
 
-            let mut reldir = if self.tag_current_or_unexpected_eof("path")? == 'i' {
-                self.push_step()?;
+            return Ok(());
+        }
 
-                self.tag_expect_char("path", |c| matches!(c, 'n'))?;
-                self.bypass_whitespace()?;
+        self.expect_end_of_tag("set")?;
 
-                self.output_mut().into_step()?.clear_buffer();
+        let content = self.render_to_string(tag.content()).into_step()?;
+        let path = self.input().into_step()?.path().to_owned();
 
-                self.parse_value_as_path("path")?
-                    .into_internal("Cannot create an absolute path from a None path")
-                    .into_step()?
-            }
-            else {
-                let mut input_path = self.input().into_step()?.path().to_owned();
-                input_path.pop();
-                input_path
-            };
+        self.context_mut().into_step()?.add_markup(variable, path, content);
 
-            reldir.push(path);
+        Ok(())
+    }
 
-            let dir = canonicalize(&reldir)
-                .into_internal(format!("Failed to canonicalize relative path {reldir:?}"))
-                .into_step()?;
+    fn parse_sub(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("sub");
+        }
 
-            self.bypass_whitespace()?;
+        self.parse_arithmetic("sub", tag, |value, content, _| value.sub(content))
+    }
 
-            self.output_mut().into_step()?.clear_buffer();
-            self.output_mut().into_step()?.write_str(dir.to_str().unwrap_or(""));
-            self.output_mut().into_step()?.flush_buffer_to_content();
+    fn parse_super(&mut self) -> StepResult<()> {
+        self.output_mut().into_step()?.clear_buffer();
+        self.expect_end_of_self_close_tag("super")?;
 
-            self.expect_end_of_self_close_tag("path")?;
+        let frame = self.context().into_step()?.block_frame()
+            .into_internal("The 'super' tag must be used within a block")
+            .into_step()?;
+        let parent = frame.parent()
+            .into_internal(format!("Block '{}' does not override another block", frame.name()))
+            .into_step()?;
 
-            Ok(())
-        }
+        self.parse_block_frame(parent).into_step()
     }
 
-    fn parse_pat(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'h' => {
-                self.push_step()?;
-                self.parse_path()
-            },
-            _ => self.unexpected_tag(),
+    fn parse_unset(&mut self) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("unset");
         }
-    }
 
-    fn parse_pa(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_pat()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
+        self.output_mut().into_step()?.clear_buffer();
+        let variable = self.parse_variable_name("unset")?;
+        self.expect_end_of_self_close_tag("unset")?;
 
-    fn parse_pow(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+        self.context_mut().into_step()?.remove_variable(&variable);
 
-            self.buffer_all_until_end_of_tag("pow")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        Ok(())
+    }
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndPow)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+    fn parse_with(&mut self, tag: &Tag) -> StepResult<()> {
+        if !self.bypass_whitespace_enforce_one()? {
+            return self.tag_unexpected_char("with");
+        }
 
-            match end_position {
-                EndPosition::Pow => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'pow' tag, '{pos:?}'"
-                )))),
-            };
+        self.output_mut().into_step()?.clear_buffer();
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        // every value is taken from the context outside of the block
+        let mut variables = Vec::new();
+        loop {
+            let variable = self.parse_variable_name("with")?;
 
-            Ok(())
-        }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
+            self.bypass_whitespace()?;
+            self.tag_expect_char("with", |c| matches!(c, '='))?;
+            self.bypass_whitespace()?;
 
-            self.output_mut().into_step()?.clear_buffer();
+            let value = self.parse_expression("with")?.unwrap_or_default();
+            variables.push((variable, value));
 
-            let value = self.parse_value_as_number("pow")?;
+            self.bypass_whitespace()?;
+            if self.tag_current_or_unexpected_eof("with")? != ',' {
+                break;
+            }
 
-            self.expect_end_of_tag("pow")?;
+            self.input_mut().into_step()?.step().into_step()?;
+        }
 
-            let content = self.parse_child(ParseUntil::EndPow).into_step()?
-                .trim()
-                .parse::<i64>()
-                .into_internal("Failed to parse content as a number")
-                .into_step()?;
+        self.expect_end_of_tag("with")?;
 
-            let val_u32: u32 = value.try_into()
-                .into_internal("Value was too large for 'pow' operations")
-                .into_step()?;
-            let con_u32: u32 = content.try_into()
-                .into_internal("Content produced too large a number for 'pow' operations")
-                .into_step()?;
-            let pow_u32 = val_u32.checked_pow(con_u32)
-                .into_internal("Value produced by 'pow' operation caused an overflow")
-                .into_step()?;
+        let path = self.input().into_step()?.path().to_owned();
+        let mut depths = Vec::new();
+        for (variable, value) in variables.iter() {
+            let context = self.context_mut().into_step()?;
+            depths.push(context.values(variable).map(|values| values.len()).unwrap_or(0));
+            context.add_variable(variable, &path, value);
+        }
 
-            self.output_mut().into_step()?.write_str(&pow_u32.to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.render_nodes(tag.content()).into_step()?;
 
-            Ok(())
+        // values set within the block are popped along with those of the
+        // block itself
+        for ((variable, _), depth) in variables.iter().zip(depths).rev() {
+            let context = self.context_mut().into_step()?;
+            while context.values(variable).map(|values| values.len()).unwrap_or(0) > depth {
+                context.pop_variable(variable);
+            }
         }
-    }
 
-    fn parse_po(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'w' => {
-                self.push_step()?;
-                self.parse_pow()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Ok(())
     }
 
-    fn parse_p(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_pa()
-            },
-            'o' => {
-                self.push_step()?;
-                self.parse_po()
-            },
-            _ => self.unexpected_tag(),
+    fn write_output_value(&mut self, value: Option<String>, raw: bool) -> StepResult<()> {
+        let output = value.unwrap_or_default();
+        let output = if raw {
+            output
         }
-    }
-
-    fn parse_set(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
-
-            self.buffer_all_until_end_of_tag("set")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        else {
+            self.context().into_step()?.escape().escape(output)
+        };
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndSet)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+        self.output_mut().into_step()?.write_str(&output);
+        self.output_mut().into_step()?.flush_buffer_to_content();
 
-            match end_position {
-                EndPosition::Set => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'set' tag, '{pos:?}'"
-                )))),
-            };
+        Ok(())
+    }
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+    fn parse_output(&mut self) -> StepResult<()> {
+        self.output_mut().into_step()?.clear_buffer();
 
-            Ok(())
+        // a third bracket writes the value without escaping
+        let raw = self.current()? == Some('{');
+        if raw {
+            self.input_mut().into_step()?.step().into_step()?;
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
 
-            self.output_mut().into_step()?.clear_buffer();
+        self.bypass_whitespace()?;
 
-            let variable = self.parse_variable_name("set")?;
+        let alias = match self.current_or_unexpected_eof_in_tag()? {
+            '"'|'('|'-'|number_chars!() => None,
+            _ => Some(self.parse_variable_name("output")?),
+        };
 
-            self.expect_end_of_tag("set")?;
+        self.bypass_whitespace()?;
 
-            let content = self.parse_child(ParseUntil::EndSet).into_step()?;
-            let path = self.input().into_step()?.path().to_owned();
+        match (alias, self.current_or_unexpected_eof_in_tag()?) {
+            (Some(alias), '(') if self.context().into_step()?.function(&alias).is_none() => {
+                let native = self.context().into_step()?.native_function(&alias)
+                    .into_internal(format!("Function '{alias}' never defined"))
+                    .into_step()?
+                    .to_owned();
 
-            self.context_mut().into_step()?.add_variable(variable, path, content);
+                let args = self.parse_function_arg_values("output")?;
+                let value = native.call(&args).into_step()?;
+                let (value, mut escaped) = self.parse_filters_escaped("output", Some(value), false)?;
 
-            Ok(())
-        }
-    }
+                // an escaped value is escaped again once combined with other
+                // values
+                if self.peek_operator()?.is_some() {
+                    escaped = false;
+                }
+                let value = self.parse_expression_from("output", value, 0, true)?;
 
-    fn parse_se(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_set()
+                self.write_output_value(value, raw || escaped)?;
             },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_sub(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            if !self.buffer_whitespace_enforce_one()? {
-                return self.unexpected_tag();
-            }
+            (Some(alias), '(') => {
+                let function = self.context().into_step()?.function(&alias)
+                    .into_internal(format!("Function '{alias}' never defined"))
+                    .into_step()?
+                    .to_owned();
 
-            self.buffer_all_until_end_of_tag("sub")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
+                let mut ctx = self.parse_function_call("exec", &alias, &function)?;
 
-            let (content, end_position) = self.parse_bypassed(ParseUntil::EndSub)
-                .into_step()?;
-            self.output_mut().into_step()?.write_bytes_to_buffer(content);
+                // the function body is trusted markup, its own output tags
+                // follow the escaping of the call
+                if raw {
+                    ctx.set_escape(Escape::None);
+                }
 
-            match end_position {
-                EndPosition::Sub => {},
-                pos => return Err(Err(InternalError::new(format!(
-                    "Invalid end position in 'sub' tag, '{pos:?}'"
-                )))),
-            };
+                if self.at_filter()? {
+                    // filters need the whole output of the function, so it
+                    // is collected before being written
+                    let (_, output) = self.render_sealed_to_string(ctx, function.content())
+                        .into_step()?;
+                    let output = self.parse_filters("output", Some(output))?
+                        .unwrap_or_default();
 
-            self.output_mut().into_step()?.flush_buffer_to_content();
+                    self.output_mut().into_step()?.write_str(&output);
+                    self.output_mut().into_step()?.flush_buffer_to_content();
+                }
+                else {
+                    // make sure we write all buffered content before spawning the sealed
+                    // parser
+                    self.output_mut().into_step()?.flush_buffer_to_content();
+                    self.render_sealed(ctx, function.content())
+                        .into_step()?;
+                }
+            },
+            (alias, _) => {
+                let mut escaped = false;
+                let value = match alias {
+                    Some(alias) => {
+                        // markup rendered from a template is already escaped
+                        let context = self.context().into_step()?;
+                        let value = context.value(&alias).map(|v| v.to_owned());
+                        let markup = context.is_markup(&alias);
+
+                        let (value, filtered) = self.parse_filters_escaped("output", value, markup)?;
+                        escaped = filtered;
+
+                        // an escaped value is escaped again once combined
+                        // with other values
+                        if self.peek_operator()?.is_some() {
+                            escaped = false;
+                        }
+                        self.parse_expression_from("output", value, 0, true)?
+                    },
+                    None => self.parse_expression("output")?,
+                };
 
-            Ok(())
+                self.write_output_value(value, raw || escaped)?;
+            },
         }
-        else {
-            if !self.bypass_whitespace_enforce_one()? {
-                return self.unknown_tag();
-            }
-
-            self.output_mut().into_step()?.clear_buffer();
 
-            let value = self.parse_value_as_number("sub")?;
-
-            self.expect_end_of_tag("sub")?;
-
-            let content = self.parse_child(ParseUntil::EndSub).into_step()?
-                .trim()
-                .parse::<i64>()
-                .into_internal("Failed to parse content as a number")
-                .into_step()?;
-
-            self.output_mut().into_step()?.write_str(&(value - content).to_string());
-            self.output_mut().into_step()?.flush_buffer_to_content();
+        self.bypass_whitespace()?;
+        self.tag_expect_char("output", |c| matches!(c, '}'))?;
+        self.tag_expect_char("output", |c| matches!(c, '}'))?;
 
-            Ok(())
+        if raw {
+            self.tag_expect_char("output", |c| matches!(c, '}'))?;
         }
-    }
 
-    fn parse_su(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'b' => {
-                self.push_step()?;
-                self.parse_sub()
-            },
-            _ => self.unexpected_tag(),
-        }
+        Ok(())
     }
 
-    fn parse_s(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_se()
-            },
-            'u' => {
-                self.push_step()?;
-                self.parse_su()
-            },
-            _ => self.unexpected_tag(),
+    /// Render a tag from its source, leaving its content to the tag.
+    fn parse_tag(&mut self, tag: &Tag) -> StepResult<()> {
+        match tag.kind() {
+            Kind::Add => self.parse_add(tag),
+            Kind::Assert => self.parse_assert(),
+            Kind::Basename => self.parse_basename(),
+            Kind::Block => self.parse_block(tag),
+            Kind::Break => self.parse_break(),
+            Kind::Call => self.parse_call(),
+            Kind::Callblock => self.parse_callblock(tag),
+            Kind::Compile => self.parse_compile(),
+            Kind::Continue => self.parse_continue(),
+            Kind::Count => self.parse_count(),
+            Kind::Dirname => self.parse_dirname(),
+            Kind::Div => self.parse_div(tag),
+            Kind::Extend => self.parse_extend_tag(),
+            Kind::Fn => self.parse_fn(tag),
+            Kind::Forcsv => self.parse_forcsv(tag),
+            Kind::Foreach => self.parse_foreach(tag),
+            Kind::Fordir => self.parse_fordir(tag),
+            Kind::Forfile => self.parse_forfile(tag),
+            Kind::Forlines => self.parse_forlines(tag),
+            Kind::Forrange => self.parse_forrange(tag),
+            Kind::Forsplit => self.parse_forsplit(tag),
+            Kind::From => self.parse_from(),
+            Kind::If => self.parse_if(tag),
+            Kind::Import => self.parse_import(),
+            Kind::Include => self.parse_include(),
+            Kind::Length => self.parse_length(),
+            Kind::Load => self.parse_load(),
+            Kind::Mod => self.parse_mod(tag),
+            Kind::Mul => self.parse_mul(tag),
+            Kind::Nth => self.parse_nth(tag),
+            Kind::Path => self.parse_path(),
+            Kind::Pop => self.parse_pop(),
+            Kind::Pow => self.parse_pow(tag),
+            Kind::Replace => self.parse_replace(tag),
+            Kind::Set => self.parse_set(tag),
+            Kind::Sub => self.parse_sub(tag),
+            Kind::Super => self.parse_super(),
+            Kind::Unset => self.parse_unset(),
+            Kind::With => self.parse_with(tag),
         }
     }
 
-    fn parse_end_add(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndAdd => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("add")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Add);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndAdd => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("add")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_ad(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_end_add()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_a(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_end_ad()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_div(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndDiv => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("mul")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Div);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndDiv => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("mul")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_di(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'v' => {
-                self.push_step()?;
-                self.parse_end_div()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_d(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_end_di()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_fordir(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndFordir => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("fordir")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Fordir);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndFordir => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("fordir")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_fordi(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'r' => {
-                self.push_step()?;
-                self.parse_end_fordir()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_ford(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_end_fordi()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_foreach(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndForeach => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("foreach")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Foreach);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndForeach => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("foreach")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_foreac(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'h' => {
-                self.push_step()?;
-                self.parse_end_foreach()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_forea(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'c' => {
-                self.push_step()?;
-                self.parse_end_foreac()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_fore(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_end_forea()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_forfile(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndForfile => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("forfile")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Forfile);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndForfile => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("forfile")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_forfil(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_end_forfile()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_forfi(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_end_forfil()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_forf(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_end_forfi()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_forsplit(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndForsplit => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("forsplit")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Forsplit);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndForsplit => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("forsplit")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_forspli(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_end_forsplit()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_forspl(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'i' => {
-                self.push_step()?;
-                self.parse_end_forspli()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_forsp(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_end_forspl()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_fors(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'p' => {
-                self.push_step()?;
-                self.parse_end_forsp()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_for(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_end_ford()
-            },
-            'e' => {
-                self.push_step()?;
-                self.parse_end_fore()
-            },
-            'f' => {
-                self.push_step()?;
-                self.parse_end_forf()
-            },
-            's' => {
-                self.push_step()?;
-                self.parse_end_fors()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_fo(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'r' => {
-                self.push_step()?;
-                self.parse_end_for()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_fn(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndFn => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("fn")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Fn);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndFn => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("fn")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_f(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'n' => {
-                self.push_step()?;
-                self.parse_end_fn()
-            },
-            'o' => {
-                self.push_step()?;
-                self.parse_end_fo()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_if(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndIf => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("if")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::If);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndIf => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("if")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_i(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'f' => {
-                self.push_step()?;
-                self.parse_end_if()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_mod(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndMod => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-            self.expect_end_of_end_tag_buffer("mod")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Mod);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndMod => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("mod")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_mo(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'd' => {
-                self.push_step()?;
-                self.parse_end_mod()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_mul(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndMul => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("mul")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Mul);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndMul => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("mul")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_mu(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'l' => {
-                self.push_step()?;
-                self.parse_end_mul()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_m(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'o' => {
-                self.push_step()?;
-                self.parse_end_mo()
-            },
-            'u' => {
-                self.push_step()?;
-                self.parse_end_mu()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_nth(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndNth => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-            self.expect_end_of_end_tag_buffer("nth")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Nth);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndNth => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("nth")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_nt(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'h' => {
-                self.push_step()?;
-                self.parse_end_nth()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_n(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_end_nt()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_pow(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndPow => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("pow")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Pow);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndPow => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("pow")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_po(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'w' => {
-                self.push_step()?;
-                self.parse_end_pow()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_p(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'o' => {
-                self.push_step()?;
-                self.parse_end_po()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_set(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndSet => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("set")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Set);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndSet => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("set")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_se(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            't' => {
-                self.push_step()?;
-                self.parse_end_set()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_sub(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndSub => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag_buffer("sub")?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-            self.set_end_position(EndPosition::Sub);
-
-            Err(Ok(FlowControl::Break))
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            match self.parse_until() {
-                ParseUntil::EndSub => {},
-                _ => {
-                    return self.unexpected_end_tag();
-                },
-            }
-
-            self.expect_end_of_end_tag("sub")?;
-
-            Err(Ok(FlowControl::Break))
-        }
-    }
-
-    fn parse_end_su(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'b' => {
-                self.push_step()?;
-                self.parse_end_sub()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end_s(&mut self) -> StepResult<()> {
-        match self.current_or_unexpected_eof_in_tag()? {
-            'e' => {
-                self.push_step()?;
-                self.parse_end_se()
-            },
-            'u' => {
-                self.push_step()?;
-                self.parse_end_su()
-            },
-            _ => self.unexpected_tag(),
-        }
-    }
-
-    fn parse_end(&mut self) -> StepResult<()> {
-        if let ParseUntil::Eof = self.parse_until() {
-            return self.unexpected_end_tag();
-        }
-
-        if !self.bypass() {
-            // clear buffer, we got an end tag
-            self.output_mut().into_step()?.clear_buffer();
-        }
-
-        // bypass all starting whitespace
-        self.bypass_whitespace()?;
-
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_end_a()
-            },
-            'd' => {
-                self.push_step()?;
-                self.parse_end_d()
-            },
-            'f' => {
-                self.push_step()?;
-                self.parse_end_f()
-            },
-            'i' => {
-                self.push_step()?;
-                self.parse_end_i()
-            },
-            'm' => {
-                self.push_step()?;
-                self.parse_end_m()
-            },
-            'n' => {
-                self.push_step()?;
-                self.parse_end_n()
-            },
-            'p' => {
-                self.push_step()?;
-                self.parse_end_p()
-            },
-            's' => {
-                self.push_step()?;
-                self.parse_end_s()
-            },
-            _ => self.unknown_end_tag(),
-        }
-    }
-
-    fn parse_tag(&mut self) -> StepResult<()> {
-        if !self.bypass() {
-            // clear buffer, we got a tag
-            self.output_mut().into_step()?.clear_buffer();
-        }
-
-        // bypass all starting whitespace
-        self.buffer_whitespace()?;
-
-        match self.current_or_unexpected_eof_in_tag()? {
-            'a' => {
-                self.push_step()?;
-                self.parse_a()
-            },
-            'b' => {
-                self.push_step()?;
-                self.parse_b()
-            },
-            'c' => {
-                self.push_step()?;
-                self.parse_c()
-            },
-            'd' => {
-                self.push_step()?;
-                self.parse_d()
-            },
-            'e' => {
-                self.push_step()?;
-                self.parse_e()
-            },
-            'f' => {
-                self.push_step()?;
-                self.parse_f()
-            },
-            'i' => {
-                self.push_step()?;
-                self.parse_i()
-            },
-            'l' => {
-                self.push_step()?;
-                self.parse_l()
-            },
-            'm' => {
-                self.push_step()?;
-                self.parse_m()
-            },
-            'n' => {
-                self.push_step()?;
-                self.parse_n()
-            },
-            'p' => {
-                self.push_step()?;
-                self.parse_p()
-            },
-            's' => {
-                self.push_step()?;
-                self.parse_s()
-            },
-            '/' => {
-                self.push_step()?;
-                self.parse_end()
-            },
-            _ => self.unknown_tag(),
-        }
-    }
-
-    fn parse_comment(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.buffer_all_until_sequence("comment", &['#', '}'])
-        }
-        else {
-            // clear buffer, we got a comment
-            self.output_mut().into_step()?.clear_buffer();
-
-            loop {
-                self.bypass_all_until(|c| matches!(c, '#'))?;
-
-                self.input_mut().into_step()?.step().into_step()?;
-                let c = match self.current()? {
-                    Some(c) => c,
-                    None => return Err(Err(InternalError::new("Unclosed comment"))),
-                };
-
-                if c == '}' {
-                    self.input_mut().into_step()?.step().into_step()?;
-                    break;
-                }
-            }
-
-            Ok(())
-        }
-    }
-
-    fn parse_output(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            self.buffer_whitespace()?;
-            self.buffer_all_until_sequence("output", &['}', '}'])?;
-            self.output_mut().into_step()?.flush_buffer_to_content();
-
-            Ok(())
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-
-            let alias = self.parse_variable_name("output")?;
-
-            self.bypass_whitespace()?;
-
-            match self.current_or_unexpected_eof_in_tag()? {
-                '(' => {
-                    let function = self.context().into_step()?.function(&alias)
-                        .into_internal(format!("Function '{alias}' never defined"))
-                        .into_step()?
-                        .to_owned();
-
-                    let mut args = self.parse_function_arg_values("exec")?
-                        .into_iter();
-                    let mut ctx = self.context().into_step()?.to_owned();
-
-                    for named in function.args().iter() {
-                        ctx.remove_variable(named);
-
-                        if let Some(Some(arg)) = args.next() {
-                            ctx.add_variable(named, self.input().into_step()?
-                                .path(), &arg);
-                        }
-                    }
-
-                    // make sure we write all buffered content before spawning the sealed
-                    // parser
-                    self.output_mut().into_step()?.flush_buffer_to_content();
-                    self.parse_limited_sealed(ctx, function.as_bytes(), ParseUntil::EndFn)
-                        .into_step()?;
-                },
-                _ => {
-                    let value = self.context().into_step()?.value(&alias);
-
-                    let output = value.map_or(String::new(), |v| v.to_owned());
-                    self.output_mut().into_step()?.write_str(&output);
-                    self.output_mut().into_step()?.flush_buffer_to_content();
-                },
-            }
-
-            self.bypass_whitespace()?;
-            self.tag_expect_char("output", |c| matches!(c, '}'))?;
-            self.tag_expect_char("output", |c| matches!(c, '}'))?;
-
-            Ok(())
-        }
-    }
-
-    fn parse_bracket(&mut self) -> StepResult<()> {
-        let c = match self.current()? {
-            Some(c) => c,
-            None => return Err(Ok(FlowControl::Continue)),
-        };
-
-        match c {
-            '{' => {
-                self.push_step()?;
-                self.parse_output()
-            },
-            '%' => {
-                self.push_step()?;
-                self.parse_tag()
-            },
-            '#' => {
-                self.push_step()?;
-                self.parse_comment()
-            },
-            _ => {
-                self.output_mut().into_step()?.flush_buffer_to_content();
-                Ok(())
-            },
-        }
-    }
-
-    fn handle_escape(&mut self) -> StepResult<()> {
-        if self.bypass() {
-            Ok(())
-        }
-        else {
-            self.output_mut().into_step()?.clear_buffer();
-            self.bypass_whitespace()?;
-            Ok(())
-        }
-    }
-
-    fn parse_internal(&mut self) -> InternalResult<()> {
-        loop {
-            if self.input()?.is_end() {
-                break;
-            }
-
-            let c = flow_internal!(self.current_or_continue());
-            let res = match c {
-                '{' => {
-                    self.push_step_internal().or_else_upgrade(self)?;
-                    self.parse_bracket().or_else_upgrade(self)
-                },
-                '\\' => {
-                    self.push_step_internal().or_else_upgrade(self)?;
-                    self.handle_escape().or_else_upgrade(self)
-                },
-                _ => {
-                    match self.output_mut() {
-                        Ok(output) => output.write_char(c),
-                        Err(e) => Err(e).or_else_upgrade(self)?,
-                    }
-
-                    match self.input_mut() {
-                        Ok(input) => match input.step() {
-                            Ok(_) => {},
-                            Err(e) => Err(e).or_else_upgrade_from_input(input)?,
-                        },
-                        Err(e) => Err(e).or_else_upgrade(self)?,
-                    }
-
-                    match self.output_mut() {
-                        Ok(output) => output.flush_buffer_to_content(),
-                        Err(e) => Err(e).or_else_upgrade(self)?,
-                    }
-
-                    continue;
-                },
-            };
-
-            flow_internal!(res);
-
-            self.set_end_position(EndPosition::Eof);
-        }
+    fn parse_internal(&mut self) -> InternalResult<()> {
+        let nodes = self.parse_nodes()?;
+        self.render_nodes(&nodes)?;
+        self.parse_extend_or_flush()
+    }
 
+    fn parse_extend_or_flush(&mut self) -> InternalResult<()> {
         if let Some(extend) = self.extend.take() {
             self.parse_extend(extend)?;
         }
@@ -4084,6 +2629,19 @@ where
         self.write()
     }
 
+    /// Render a parsed template, starting with its front matter. Any extend is
+    /// only applied once every node is rendered.
+    fn render_template(&mut self, template: &Template) -> InternalResult<()> {
+        self.merge_front_matter(template)?;
+        self.render_nodes(template.nodes())?;
+        self.parse_extend_or_flush()
+    }
+
+    pub(crate) fn render(&mut self, template: &Template) -> InternalResult<()> {
+        self.render_template(template)?;
+        self.write()
+    }
+
     fn write(&mut self) -> InternalResult<()> {
         if let Some(output) = self.output.as_mut() {
             output.write_content_to_destination()?;
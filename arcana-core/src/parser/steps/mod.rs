@@ -3,6 +3,8 @@ mod test;
 
 use {
     crate::{
+        context::Parameter,
+        decimal::{ Decimal, MAX_PRECISION, },
         error::{
             InternalError,
             InternalResult,
@@ -20,9 +22,57 @@ use {
     },
 };
 
+/// A binary operator of an expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Operator {
+    Concat,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl Operator {
+    /// How tightly the operator binds, `~` binds the loosest.
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Concat => 0,
+            Self::Add|Self::Sub => 1,
+            Self::Mul|Self::Div|Self::Mod => 2,
+        }
+    }
+
+    fn as_decimal(value: Option<String>) -> InternalResult<Decimal> {
+        value.into_internal("Cannot coerce an empty value into a number")?
+            .trim()
+            .parse::<Decimal>()
+            .into_internal("Failed to coerce value into a number")
+    }
+
+    fn apply(&self, a: Option<String>, b: Option<String>) -> InternalResult<Option<String>> {
+        if let Self::Concat = self {
+            return Ok(Some(a.unwrap_or_default() + &b.unwrap_or_default()));
+        }
+
+        let a = Self::as_decimal(a)?;
+        let b = Self::as_decimal(b)?;
+
+        let result = match self {
+            Self::Add => a.add(b)?,
+            Self::Sub => a.sub(b)?,
+            Self::Mul => a.mul(b)?,
+            Self::Div => a.quotient(b)?,
+            Self::Mod => a.rem(b)?,
+            Self::Concat => unreachable!(),
+        };
+
+        Ok(Some(result.to_string()))
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum FlowControl {
-    Continue,
     Break,
 }
 
@@ -69,13 +119,10 @@ macro_rules! flow_ctrl {
     ($flow:expr) => {
         match $flow {
             FlowControl::Break => break,
-            FlowControl::Continue => continue,
         }
     }
 }
 
-pub(crate) use flow_ctrl;
-
 macro_rules! flow {
     ($to_result:expr) => {
         match $to_result {
@@ -88,19 +135,6 @@ macro_rules! flow {
     }
 }
 
-macro_rules! flow_internal {
-    ($to_result:expr) => {
-        match $to_result {
-            Ok(c) => c,
-            Err(e) => match e {
-                Ok(ctrl) => flow_ctrl!(ctrl),
-                Err(e) => return Err(e),
-            },
-        }
-    }
-}
-
-pub(crate) use flow_internal;
 
 pub(crate) trait Steps<R, W>
 where
@@ -119,14 +153,8 @@ where
         self.current_internal().into_step()
     }
 
-    fn current_or_continue(&mut self) -> StepResult<char> {
-        match self.current()? {
-            Some(c) => Ok(c),
-            None => {
-                self.output_mut().into_step()?.flush_buffer_to_content();
-                Err(Ok(FlowControl::Continue))
-            },
-        }
+    fn peek(&self) -> StepResult<Option<char>> {
+        Ok(self.input().into_step()?.peek())
     }
 
     fn current_or_break(&self) -> StepResult<char> {
@@ -186,26 +214,6 @@ where
             .into_step()
     }
 
-    fn unknown_tag<T>(&mut self) -> StepResult<T> {
-        let tagname = self.buffer_rest_of_tagname()?;
-        Err(Err(InternalError::new(format!("Unknown tag '{tagname}'"))))
-    }
-
-    fn unexpected_tag<T>(&mut self) -> StepResult<T> {
-        let tagname = self.buffer_rest_of_tagname()?;
-        Err(Err(InternalError::new(format!("Unexpected tag '{tagname}"))))
-    }
-
-    fn unknown_end_tag<T>(&mut self) -> StepResult<T> {
-        let tagname = self.buffer_rest_of_tagname()?;
-        Err(Err(InternalError::new(format!("Unknown end-tag '{tagname}"))))
-    }
-
-    fn unexpected_end_tag<T>(&mut self) -> StepResult<T> {
-        let tagname = self.buffer_rest_of_tagname()?;
-        Err(Err(InternalError::new(format!("Unexpected end-tag '{tagname}"))))
-    }
-
     fn unexpected_eof_in_tag<T>(&mut self) -> StepResult<T> {
         let tagname = self.buffer_rest_of_tagname()?;
         Err(Err(InternalError::new(format!("Unexpected EOF in tag '{tagname}"))))
@@ -266,20 +274,6 @@ where
         ))))
     }
 
-    fn end_tag_unexpected_eof<S, T>(&self, tagname: S) -> StepResult<T>
-    where
-        S: AsRef<str>,
-    {
-        Err(Err(InternalError::new(format!("Unexpected EOF in end-tag '{}'", tagname.as_ref()))))
-    }
-
-    fn end_tag_unexpected_char<S, T>(&self, tagname: S) -> StepResult<T>
-    where
-        S: AsRef<str>,
-    {
-        Err(Err(InternalError::new(format!("Unexpected character in end-tag '{}'", tagname.as_ref()))))
-    }
-
     fn bypass_whitespace(&mut self) -> StepResult<()> {
         loop {
             let c = flow!(self.current_or_break());
@@ -295,22 +289,6 @@ where
         Ok(())
     }
 
-    fn buffer_whitespace(&mut self) -> StepResult<()> {
-        loop {
-            let c = flow!(self.current_or_break());
-
-            if c.is_whitespace() {
-                self.output_mut().into_step()?.write_char(c);
-                self.input_mut().into_step()?.step().into_step()?;
-                continue;
-            }
-
-            break;
-        }
-
-        Ok(())
-    }
-
     fn bypass_whitespace_enforce_one(&mut self) -> StepResult<bool> {
         let c = match self.current()? {
             Some(c) => c,
@@ -327,22 +305,6 @@ where
         }
     }
 
-    fn buffer_whitespace_enforce_one(&mut self) -> StepResult<bool> {
-        let c = match self.current()? {
-            Some(c) => c,
-            None => return Ok(false),
-        };
-
-        if c.is_whitespace() {
-            self.buffer_whitespace()?;
-
-            Ok(true)
-        }
-        else {
-            Ok(false)
-        }
-    }
-
     fn buffer_all_until<F>(&mut self, matches: F) -> StepResult<()>
     where
         F: Fn(char) -> bool,
@@ -363,85 +325,6 @@ where
         Ok(())
     }
 
-    fn buffer_all_until_sequence<S>(&mut self, tagname: S, seq: &[char]) -> StepResult<()>
-    where
-        S: AsRef<str>,
-    {
-        let tagname = tagname.as_ref();
-
-        loop {
-            let c = flow!(self.current_or_break());
-            if c == '\\' {
-                self.output_mut().into_step()?.write_char(c);
-                self.input_mut().into_step()?.step().into_step()?;
-
-                let d = match self.current()? {
-                    Some(d) => d,
-                    None => return self.tag_unexpected_eof(tagname),
-                };
-
-                self.output_mut().into_step()?.write_char(d);
-                self.input_mut().into_step()?.step().into_step()?;
-                continue;
-            }
-
-            let mut flow = None;
-            for ch in seq.iter() {
-                let c = match self.current()? {
-                    Some(c) => c,
-                    None => return self.tag_unexpected_eof(tagname),
-                };
-
-                self.output_mut().into_step()?.write_char(c);
-                self.input_mut().into_step()?.step().into_step()?;
-                if c != *ch {
-                    flow = Some(FlowControl::Continue);
-                    break;
-                }
-            }
-
-            match flow {
-                Some(f) => flow_ctrl!(f),
-                None => break,
-            }
-        }
-
-        Ok(())
-    }
-
-    fn buffer_all_until_end_of_self_closing_tag<S>(&mut self, tagname: S) -> StepResult<()>
-    where
-        S: AsRef<str>,
-    {
-        self.buffer_all_until_sequence(tagname, &['/', '%', '}'])
-    }
-
-    fn buffer_all_until_end_of_tag<S>(&mut self, tagname: S) -> StepResult<()>
-    where
-        S: AsRef<str>,
-    {
-        self.buffer_all_until_sequence(tagname, &['%', '}'])
-    }
-
-    fn bypass_all_until<F>(&mut self, matches: F) -> StepResult<()>
-    where
-        F: Fn(char) -> bool,
-    {
-        loop {
-            let c = flow!(self.current_or_break());
-
-            if matches(c) {
-                break;
-            }
-            else {
-                self.input_mut().into_step()?.step().into_step()?;
-                continue;
-            }
-        }
-
-        Ok(())
-    }
-
     fn tag_expect_char_internal<F, S>(&self, tagname: S, matches: F) -> StepResult<char>
     where
         S: AsRef<str>,
@@ -481,43 +364,6 @@ where
         Ok(())
     }
 
-    fn end_tag_expect_char_internal<F, S>(&mut self, tagname: S, matches: F) -> StepResult<char>
-    where
-        S: AsRef<str>,
-        F: FnOnce(char) -> bool,
-    {
-        match self.current()? {
-            Some(c) => if matches(c) {
-                Ok(c)
-            }
-            else {
-                self.end_tag_unexpected_char(tagname)
-            },
-            None => self.end_tag_unexpected_eof(tagname)
-        }
-    }
-
-    fn end_tag_expect_char<F, S>(&mut self, tagname: S, matches: F) -> StepResult<()>
-    where
-        S: AsRef<str>,
-        F: FnOnce(char) -> bool,
-    {
-        self.end_tag_expect_char_internal(tagname, matches)?;
-        self.input_mut().into_step()?.step().into_step()?;
-        Ok(())
-    }
-
-    fn end_tag_expect_buffer_char<F, S>(&mut self, tagname: S, matches: F) -> StepResult<()>
-    where
-        S: AsRef<str>,
-        F: FnOnce(char) -> bool,
-    {
-        let c = self.end_tag_expect_char_internal(tagname, matches)?;
-        self.output_mut().into_step()?.write_char(c);
-        self.input_mut().into_step()?.step().into_step()?;
-        Ok(())
-    }
-
     fn expect_end_of_self_close_tag<S>(&mut self, tagname: S) -> StepResult<()>
     where
         S: AsRef<str>,
@@ -543,42 +389,6 @@ where
         Ok(())
     }
 
-    fn expect_end_of_end_tag<S>(&mut self, tagname: S) -> StepResult<()>
-    where
-        S: AsRef<str>,
-    {
-        self.bypass_whitespace()?;
-
-        self.end_tag_expect_char(tagname.as_ref(), |c| matches!(c, '%'))?;
-        self.end_tag_expect_char(tagname.as_ref(), |c| matches!(c, '}'))?;
-
-        Ok(())
-    }
-
-    fn expect_end_of_tag_buffer<S>(&mut self, tagname: S) -> StepResult<()>
-    where
-        S: AsRef<str>,
-    {
-        self.buffer_whitespace()?;
-
-        self.tag_expect_buffer_char(tagname.as_ref(), |c| matches!(c, '%'))?;
-        self.tag_expect_buffer_char(tagname.as_ref(), |c| matches!(c, '}'))?;
-
-        Ok(())
-    }
-
-    fn expect_end_of_end_tag_buffer<S>(&mut self, tagname: S) -> StepResult<()>
-    where
-        S: AsRef<str>,
-    {
-        self.buffer_whitespace()?;
-
-        self.end_tag_expect_buffer_char(tagname.as_ref(), |c| matches!(c, '%'))?;
-        self.end_tag_expect_buffer_char(tagname.as_ref(), |c| matches!(c, '}'))?;
-
-        Ok(())
-    }
-
     fn parse_variable_name<S>(&mut self, tagname: S) -> StepResult<String>
     where
         S: AsRef<str>,
@@ -652,7 +462,7 @@ where
         S: AsRef<str>,
     {
         self.output_mut().into_step()?.clear_buffer();
-        self.buffer_all_while(|c| matches!(c, number_chars!()))?;
+        self.buffer_all_while(|c| matches!(c, number_chars!()|'.'))?;
         String::from_utf8(self.output_mut().into_step()?.take_buffer())
             .into_internal(format!("Invalid UTF-8 in number literal of '{}' tag", tagname.as_ref()))
             .into_step()
@@ -688,16 +498,162 @@ where
     fn parse_value<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Option<String>> {
         let c = self.tag_current_or_unexpected_eof(tagname.as_ref())?;
 
-        match c {
+        let value = match c {
             // string
-            '"' => Ok(Some(self.parse_text(tagname)?)),
+            '"' => Some(self.parse_text(tagname.as_ref())?),
             // number
-            number_chars!() => Ok(Some(self.parse_number(tagname)?)),
+            number_chars!() => Some(self.parse_number(tagname.as_ref())?),
             // variable
-            _ => self.parse_variable(tagname),
-        }
+            _ => self.parse_variable(tagname.as_ref())?,
+        };
+
+        self.parse_filters(tagname, value)
      }
 
+    fn at_filter(&mut self) -> StepResult<bool> {
+        self.bypass_whitespace()?;
+
+        // a second pipe is the '||' operator of a condition
+        Ok(self.current()? == Some('|') && self.peek()? != Some('|'))
+    }
+
+    fn parse_filters<S: AsRef<str>>(&mut self, tagname: S, value: Option<String>) -> StepResult<Option<String>> {
+        Ok(self.parse_filters_escaped(tagname, value, false)?.0)
+    }
+
+    /// Parse the filters after a value, also returning whether the value is
+    /// escaped once they are applied. An escaped value stays so through the
+    /// filters after the one escaping it.
+    fn parse_filters_escaped<S: AsRef<str>>(
+        &mut self, tagname: S, mut value: Option<String>, mut escaped: bool,
+    ) -> StepResult<(Option<String>, bool)> {
+        while self.at_filter()? {
+            self.input_mut().into_step()?.step().into_step()?;
+
+            let name = self.parse_variable_name(tagname.as_ref())?;
+            self.bypass_whitespace()?;
+
+            let args = if self.current()? == Some('(') {
+                self.parse_function_arg_values(tagname.as_ref())?
+            }
+            else {
+                Vec::new()
+            };
+
+            let filter = self.context().into_step()?.filters().get(&name).into_step()?.clone();
+            value = filter.apply(value, &args).into_step()?;
+            escaped |= filter.escapes();
+        }
+
+        Ok((value, escaped))
+    }
+
+    /// The operator at the current position, without stepping past it. The
+    /// `%` of `%}` and the `/` of `/%}` end a tag and are not operators.
+    fn peek_operator(&mut self) -> StepResult<Option<Operator>> {
+        self.bypass_whitespace()?;
+
+        Ok(match (self.current()?, self.peek()?) {
+            (Some('~'), _) => Some(Operator::Concat),
+            (Some('+'), _) => Some(Operator::Add),
+            (Some('-'), _) => Some(Operator::Sub),
+            (Some('*'), _) => Some(Operator::Mul),
+            (Some('/'), next) if next != Some('%') => Some(Operator::Div),
+            (Some('%'), next) if next != Some('}') => Some(Operator::Mod),
+            _ => None,
+        })
+    }
+
+    /// A value, a negated operand or an expression in parentheses, along with
+    /// its filters.
+    fn parse_operand<S: AsRef<str>>(&mut self, tagname: S, evaluate: bool) -> StepResult<Option<String>> {
+        let tagname = tagname.as_ref();
+
+        self.bypass_whitespace()?;
+        match self.tag_current_or_unexpected_eof(tagname)? {
+            '(' => {
+                self.input_mut().into_step()?.step().into_step()?;
+
+                let value = self.parse_expression_with(tagname, evaluate)?;
+
+                self.bypass_whitespace()?;
+                self.tag_expect_char(tagname, |c| matches!(c, ')'))?;
+
+                self.parse_filters(tagname, value)
+            },
+            '-' => {
+                self.input_mut().into_step()?.step().into_step()?;
+
+                let value = self.parse_operand(tagname, evaluate)?;
+                if !evaluate {
+                    return Ok(value);
+                }
+
+                let negated = Operator::as_decimal(value).into_step()?
+                    .neg()
+                    .into_step()?;
+
+                Ok(Some(negated.to_string()))
+            },
+            _ => self.parse_value(tagname),
+        }
+    }
+
+    /// Continue an expression of which the first operand was already parsed.
+    /// Operators of a lower precedence than `min` are left for the caller.
+    fn parse_expression_from<S: AsRef<str>>(
+        &mut self, tagname: S, mut value: Option<String>,
+        min: u8, evaluate: bool,
+    ) -> StepResult<Option<String>> {
+        let tagname = tagname.as_ref();
+
+        while let Some(operator) = self.peek_operator()? {
+            if operator.precedence() < min {
+                break;
+            }
+
+            self.input_mut().into_step()?.step().into_step()?;
+
+            let mut right = self.parse_operand(tagname, evaluate)?;
+            while let Some(next) = self.peek_operator()? {
+                if next.precedence() <= operator.precedence() {
+                    break;
+                }
+
+                right = self.parse_expression_from(tagname, right, next.precedence(), evaluate)?;
+            }
+
+            if evaluate {
+                value = operator.apply(value, right).into_step()?;
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// An expression of values joined by the `~` concatenation operator or
+    /// the arithmetic operators `+`, `-`, `*`, `/` and `%`. Nothing is
+    /// computed when not evaluating, the expression is only read.
+    fn parse_expression_with<S: AsRef<str>>(&mut self, tagname: S, evaluate: bool) -> StepResult<Option<String>> {
+        let value = self.parse_operand(tagname.as_ref(), evaluate)?;
+
+        self.parse_expression_from(tagname, value, 0, evaluate)
+    }
+
+    fn parse_expression<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Option<String>> {
+        self.parse_expression_with(tagname, true)
+    }
+
+    fn parse_expression_as_number<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<i64> {
+        self.parse_expression(tagname)?
+            .into_internal("Cannot coerce an empty value into a number")
+            .into_step()?
+            .trim()
+            .parse::<i64>()
+            .into_internal("Failed to coerce value into a number")
+            .into_step()
+    }
+
     fn parse_value_as_path<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Option<PathBuf>> {
         let c = self.tag_current_or_unexpected_eof(tagname.as_ref())?;
 
@@ -719,7 +675,48 @@ where
             .into_step()
      }
 
-    fn parse_function_args<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Vec<String>> {
+    fn parse_value_as_decimal<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Decimal> {
+        self.parse_value(tagname)?
+            .into_internal("Cannot coerce an empty value into a number")
+            .into_step()?
+            .trim()
+            .parse::<Decimal>()
+            .into_internal("Failed to coerce value into a number")
+            .into_step()
+    }
+
+    /// An optional `precision <value>` option of a tag, the number of decimal
+    /// places to round a result to.
+    fn parse_precision<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Option<u32>> {
+        let tagname = tagname.as_ref();
+
+        self.bypass_whitespace()?;
+        if self.tag_current_or_unexpected_eof(tagname)? != 'p' {
+            return Ok(None);
+        }
+
+        self.push_step()?;
+        for expected in "recision".chars() {
+            self.tag_expect_buffer_char(tagname, |c| c == expected)?;
+        }
+        self.output_mut().into_step()?.clear_buffer();
+
+        if !self.bypass_whitespace_enforce_one()? {
+            return Err(Err(InternalError::new(format!("Unexpected character in '{tagname}' tag"))));
+        }
+
+        let precision = self.parse_value_as_number(tagname)?;
+        match u32::try_from(precision) {
+            Ok(precision) if precision <= MAX_PRECISION => Ok(Some(precision)),
+            _ => Err(Err(InternalError::new(format!(
+                "Precision of '{tagname}' tag must be from 0 to {MAX_PRECISION}"
+            )))),
+        }
+    }
+
+    /// The parameters of a function definition, `(a, b="default")`. Default
+    /// values are evaluated when the function is defined.
+    fn parse_function_params<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Vec<Parameter>> {
         self.bypass_whitespace()?;
         self.tag_expect_char(tagname.as_ref(), |c| matches!(c, '('))?;
         self.output_mut().into_step()?.clear_buffer();
@@ -737,8 +734,25 @@ where
                 self.bypass_whitespace()?;
             }
 
-            args.push(self.parse_variable_name(tagname.as_ref())?);
+            let name = self.parse_variable_name(tagname.as_ref())?;
             self.bypass_whitespace()?;
+
+            let default = if self.tag_current_or_unexpected_eof(tagname.as_ref())? == '=' {
+                self.input_mut().into_step()?.step().into_step()?;
+                let default = self.parse_expression(tagname.as_ref())?;
+                self.bypass_whitespace()?;
+
+                Some(default.unwrap_or_default())
+            }
+            else {
+                None
+            };
+
+            if args.iter().any(|param: &Parameter| param.name() == name) {
+                return Err(Err(InternalError::new(format!("Duplicate argument '{name}' in '{}' tag", tagname.as_ref()))));
+            }
+
+            args.push(Parameter::new(name, default));
         }
 
         self.tag_expect_char(tagname.as_ref(), |c| matches!(c, ')'))?;
@@ -764,7 +778,7 @@ where
                 self.bypass_whitespace()?;
             }
 
-            args.push(self.parse_value(tagname.as_ref())?);
+            args.push(self.parse_expression(tagname.as_ref())?);
             self.bypass_whitespace()?;
         }
 
@@ -772,6 +786,57 @@ where
 
         Ok(args)
     }
+
+    /// The arguments of a call to a function defined in a template, each
+    /// positional or given by name, `(value, name=value)`.
+    fn parse_function_call_args<S: AsRef<str>>(&mut self, tagname: S) -> StepResult<Vec<(Option<String>, Option<String>)>> {
+        let tagname = tagname.as_ref();
+
+        self.bypass_whitespace()?;
+        self.tag_expect_char(tagname, |c| matches!(c, '('))?;
+        self.output_mut().into_step()?.clear_buffer();
+
+        let mut args = Vec::new();
+        let mut first = true;
+        while self.tag_current_or_unexpected_eof(tagname)? != ')' {
+            self.bypass_whitespace()?;
+
+            if first {
+                first = false;
+            }
+            else {
+                self.tag_expect_char(tagname, |c| matches!(c, ','))?;
+                self.bypass_whitespace()?;
+            }
+
+            if !matches!(self.tag_current_or_unexpected_eof(tagname)?, first_variable_chars!()) {
+                args.push((None, self.parse_expression(tagname)?));
+                self.bypass_whitespace()?;
+                continue;
+            }
+
+            // a name is either a keyword or the variable starting an
+            // expression
+            let name = self.parse_variable_name(tagname)?;
+            self.bypass_whitespace()?;
+
+            if self.current()? == Some('=') && self.peek()? != Some('=') {
+                self.input_mut().into_step()?.step().into_step()?;
+                args.push((Some(name), self.parse_expression(tagname)?));
+            }
+            else {
+                let value = self.context().into_step()?.value(&name).map(|v| v.to_owned());
+                let value = self.parse_filters(tagname, value)?;
+                args.push((None, self.parse_expression_from(tagname, value, 0, true)?));
+            }
+
+            self.bypass_whitespace()?;
+        }
+
+        self.tag_expect_char(tagname, |c| matches!(c, ')'))?;
+
+        Ok(args)
+    }
 }
 
 impl<R, W, P> Steps<R, W> for P
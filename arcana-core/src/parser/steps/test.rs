@@ -1,4 +1,5 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
     use {
         crate::{
@@ -40,30 +41,6 @@ mod test {
         assert_eq!(Some(&'x'), parser.input.as_ref().and_then(|i| i.current()));
     }
 
-    #[test]
-    fn buffer_whitespace() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("        \n x", &mut output);
-        parser.buffer_whitespace().unwrap();
-        assert_eq!(Some(&'x'), parser.input.as_ref().and_then(|i| i.current()));
-    }
-
-    #[test]
-    fn buffer_whitespace_enforce_one_1() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("        \n x", &mut output);
-        assert!(parser.buffer_whitespace_enforce_one().unwrap());
-        assert_eq!(Some(&'x'), parser.input.as_ref().and_then(|i| i.current()));
-    }
-
-    #[test]
-    fn buffer_whitespace_enforce_one_2() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("x", &mut output);
-        assert!(!parser.buffer_whitespace_enforce_one().unwrap());
-        assert_eq!(Some(&'x'), parser.input.as_ref().and_then(|i| i.current()));
-    }
-
     #[test]
     fn bypass_whitespace_enforce_one_1() {
         let mut output = Vec::new();
@@ -88,31 +65,6 @@ mod test {
         assert_eq!(Some(&'$'), parser.input.as_ref().and_then(|i| i.current()));
     }
 
-    #[test]
-    fn buffer_all_until_sequence_1() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("this that the other \\/%}/%}", &mut output);
-        parser.buffer_all_until_sequence("test", &['/', '%', '}']).unwrap();
-        // should buffer until end
-        assert_eq!(None, parser.input.as_ref().and_then(|i| i.current()));
-    }
-
-    #[test]
-    fn buffer_all_until_sequence_2() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("this that the other /%}/%}", &mut output);
-        parser.buffer_all_until_sequence("test", &['/', '%', '}']).unwrap();
-        assert_eq!(Some(&'/'), parser.input.as_ref().and_then(|i| i.current()));
-    }
-
-    #[test]
-    fn bypass_all_until() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("abcdef_x\n\t$", &mut output);
-        parser.bypass_all_until(|c| matches!(c, '$')).unwrap();
-        assert_eq!(Some(&'$'), parser.input.as_ref().and_then(|i| i.current()));
-    }
-
     #[test]
     fn tag_expect_char_1() {
         let mut output = Vec::new();
@@ -149,42 +101,6 @@ mod test {
         assert_eq!(None, parser.input.as_ref().and_then(|i| i.current()));
     }
 
-    #[test]
-    fn end_tag_expect_char_1() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("a", &mut output);
-        parser.end_tag_expect_char("test", |c| matches!(c, 'a')).unwrap();
-        assert_eq!(None, parser.input.as_ref().and_then(|i| i.current()));
-    }
-
-    #[test]
-    fn end_tag_expect_char_2() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("$", &mut output);
-        match parser.end_tag_expect_char("test", |c| matches!(c, 'a')) {
-            Ok(_) => panic!("Expected err (ok)"),
-            Err(e) => match e {
-                Ok(_) => panic!("Expected err (flow)"),
-                Err(e) => assert_eq!("Unexpected character in end-tag 'test'", e.message()),
-            },
-        }
-        assert_eq!(Some(&'$'), parser.input.as_ref().and_then(|i| i.current()));
-    }
-
-    #[test]
-    fn end_tag_expect_char_3() {
-        let mut output = Vec::new();
-        let mut parser = str_parser("", &mut output);
-        match parser.end_tag_expect_char("test", |c| matches!(c, 'a')) {
-            Ok(_) => panic!("Expected err (ok)"),
-            Err(e) => match e {
-                Ok(_) => panic!("Expected err (flow)"),
-                Err(e) => assert_eq!(e.message(), "Unexpected EOF in end-tag 'test'"),
-            },
-        }
-        assert_eq!(None, parser.input.as_ref().and_then(|i| i.current()));
-    }
-
     #[test]
     fn expect_end_of_self_close_tag_1() {
         let mut output = Vec::new();
@@ -323,4 +239,36 @@ mod test {
         let mut parser = str_parser(" \"./resources/template.txt\" /%}", &mut output);
         parser.parse_compile().unwrap();
     }
+
+    #[test]
+    fn parse_expression_1() {
+        for (input, expected) in [
+            ("1 + 2 * 3 }}", "7"),
+            ("(1 + 2) * 3 }}", "9"),
+            ("10 - 2 - 3 }}", "5"),
+            ("-(2 * 3) + 1 }}", "-5"),
+            ("7 / 2 }}", "3"),
+            ("\"7.0\" / 2 }}", "3.5"),
+            ("7 % 4 %}", "3"),
+            ("\"a\" ~ 1 + 2 ~ \"b\" /%}", "a3b"),
+        ] {
+            let mut output = Vec::new();
+            let mut parser = str_parser(input, &mut output);
+            let value = parser.parse_expression("test").unwrap();
+            assert_eq!(Some(expected.to_owned()), value, "{input}");
+            assert!(matches!(parser.input.as_ref().and_then(|i| i.current()), Some('}'|'%'|'/')));
+        }
+    }
+
+    #[test]
+    fn parse_expression_2() {
+        let mut output = Vec::new();
+        let mut parser = str_parser("1 / 0 }}", &mut output);
+        assert!(parser.parse_expression("test").is_err());
+
+        let mut output = Vec::new();
+        let mut parser = str_parser("1 / 0 }}", &mut output);
+        assert_eq!(Some("1".to_owned()), parser.parse_expression_with("test", false).unwrap());
+        assert_eq!(Some(&'}'), parser.input.as_ref().and_then(|i| i.current()));
+    }
 }
@@ -1,9 +1,16 @@
 use {
     crate::{
         context::Context,
+        error::InternalError,
+        escape::Escape,
         parser::{ Parser, TemplateParser },
     },
-    std::path::PathBuf,
+    std::{
+        env::temp_dir,
+        fs::{ copy, create_dir_all, File, remove_dir_all, },
+        path::{ Path, PathBuf, },
+        time::{ Duration, UNIX_EPOCH, },
+    },
 };
 
 #[test]
@@ -173,6 +180,71 @@ fn parse_unexpected_eof_in_tag_name() {
     drop(parser);
 }
 
+#[test]
+fn parse_import_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+		PathBuf::from("./resources/parse_import_1/test.arct"),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "<h2>Hi</h2>|<h3>Yo</h3>|<p>x</p>|clean",
+        output_str
+    );
+}
+
+#[test]
+fn parse_import_2() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% if \"a\" == \"b\" %}{% import \"./missing.arct\" as m /%}{% /if %}\\\n",
+            "{% from \"./resources/parse_import_1/macros.arct\" import nope /%}",
+        ),
+		&mut output,
+	).unwrap();
+	let error = parser.parse().unwrap_err();
+	assert_eq!(
+        "Function 'nope' is not defined in \"./resources/parse_import_1/macros.arct\"",
+        error.message()
+    );
+}
+
+#[test]
+fn parse_import_3() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+		PathBuf::from("./resources/parse_import_3/test.arct"),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "<div><b>Hi</b></div>|<div><b>Yo</b></div>\n",
+        output_str
+    );
+
+    // the functions of the imported file are only visible to its own functions
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% from \"./resources/parse_import_3/macros.arct\" import card /%}",
+            "{{ helper(\"x\") }}",
+        ),
+		&mut output,
+	).unwrap();
+	let error = parser.parse().unwrap_err();
+	assert_eq!("Function 'helper' never defined", error.message());
+}
+
 #[test]
 fn parse_include_1() {
     let input = "File: {% include \"./resources/parse-include-1.txt\" /%}";
@@ -388,13 +460,13 @@ fn parse_if_inline_3() {
     context.add_variable("id1", "./", "2");
     context.add_variable("id2", "./", "5");
     context.add_variable("id3", "./", "10");
-    // str(10) < str(2.5)
+    // 2.5 < 10
     let input = r#"{% if (id1 == "0" || id2 > "4") && "2.5" < id3 %}True{% else %}False{% /if %}"#;
     let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
     parser.parse().unwrap();
     drop(parser);
     let output_str = String::from_utf8(output).unwrap();
-    assert_eq!("False", output_str);
+    assert_eq!("True", output_str);
 }
 
 #[test]
@@ -555,7 +627,7 @@ fn parse_complex_2() {
         &mut output
     ).unwrap();
     parser.parse().unwrap();
-    let split = parser.context().unwrap().value(&"split");
+    let split = parser.context().unwrap().value("split");
     assert_eq!(None, split);
     drop(parser);
 
@@ -1112,6 +1184,52 @@ fn parse_div_3() {
 	assert_eq!("3", output_str);
 }
 
+#[test]
+fn parse_loop_context_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% forsplit x in \"a,b,c\" on \",\" as outer %}",
+            "{{ outer.index1 }}/{{ outer.revindex }} {{ outer.previous }}<{{ x }}>{{ outer.next }} ",
+            "{{ outer.cycle(\"odd\", \"even\") }} ",
+            "{% forrange i to 2 as inner %}({{ inner.parent.index }}.{{ inner.index }}){% /forrange %}\\\n",
+            "{% /forsplit %}\\\n",
+            "{{ outer.index }}",
+        ),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "1/2 <a>b odd (0.0)(0.1)2/1 a<b>c even (1.0)(1.1)3/0 b<c> odd (2.0)(2.1)",
+        output_str
+    );
+}
+
+#[test]
+fn parse_loop_context_2() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% forrange i to 2 as loop %}",
+            "{% forrange j to 2 as loop %}{{ loop.parent.index }}{{ loop.index }}{{ loop.cycle(\"a\", \"b\") }} {% /forrange %}",
+            "{{ loop.cycle(\"A\", \"B\") }}|",
+            "{% /forrange %}",
+        ),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "00a 01b A|10a 11b B|",
+        output_str
+    );
+}
+
 #[test]
 fn parse_mod_1() {
 	let mut output = Vec::<u8>::new();
@@ -1434,6 +1552,20 @@ fn parse_if_8() {
 	assert_eq!("true", output_str);
 }
 
+#[test]
+fn parse_if_9() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+		PathBuf::from("./resources/parse_if_9/test.arct"),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!("YYYYY", output_str);
+}
+
 #[test]
 fn parse_fn_call_1() {
 	let mut output = Vec::<u8>::new();
@@ -1472,6 +1604,263 @@ fn parse_fn_call_2() {
     );
 }
 
+#[test]
+fn parse_fn_defaults_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% fn card(title, level = \"2\") %}<h{{ level }}>{{ title }}</h{{ level }}>{% /fn %}\\\n",
+            "{{ card(\"A\") }}|{{ card(\"B\", \"3\") }}|",
+            "{{ card(level = \"4\", title = \"C\") }}|{{ card(\"D\", level=\"5\") }}",
+        ),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "<h2>A</h2>|<h3>B</h3>|<h4>C</h4>|<h5>D</h5>",
+        output_str
+    );
+}
+
+#[test]
+fn parse_fn_defaults_2() {
+    for (call, message) in [
+        ("card(\"a\", \"b\", \"c\")", "Function 'card' takes 2 argument(s) but 3 were given"),
+        ("card(nope = \"x\")", "Function 'card' has no argument 'nope'"),
+        ("card(\"a\", title = \"b\")", "Argument 'title' of function 'card' was given more than once"),
+        (
+            "card(title = \"a\", \"b\")",
+            "Positional arguments must come before named arguments in the call to 'card'",
+        ),
+    ] {
+        let mut output = Vec::<u8>::new();
+        let input = format!(
+            "{{% fn card(title, level = \"2\") %}}{{{{ title }}}}{{% /fn %}}{{{{ {call} }}}}"
+        );
+        let mut parser = TemplateParser::new(
+            Context::default(),
+            input.as_str(),
+            &mut output,
+        ).unwrap();
+        let error = parser.parse().unwrap_err();
+        assert_eq!(message, error.message());
+    }
+}
+
+#[test]
+fn parse_break_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% forrange i to 10 as loop %}",
+            "{% if i == \"5\" %}{% break /%}{% /if %}",
+            "{% if i == \"2\" %}{% continue /%}{% /if %}",
+            "{{ i }}",
+            "{% /forrange %}|",
+            "{% forrange i to 3 %}{% forrange j to 3 %}",
+            "{% if j == \"1\" %}{% break /%}{% /if %}{{ i }}{{ j }} ",
+            "{% /forrange %}{% /forrange %}|",
+            "{% forsplit x in \"a,b,c\" on \",\" %}{% with y = x %}",
+            "{% if y == \"b\" %}{% continue /%}{% /if %}[{{ y }}]",
+            "{% /with %}{% /forsplit %}{{ y }}",
+        ),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "0134|00 10 20 |[a][c]",
+        output_str
+    );
+}
+
+#[test]
+fn parse_break_2() {
+    for (input, message) in [
+        ("{% break /%}", "The 'break' tag must be used within a loop"),
+        (
+            "{% fn skip() %}{% continue /%}{% /fn %}{% forrange i to 2 %}{{ skip() }}{% /forrange %}",
+            "The 'continue' tag must be used within a loop",
+        ),
+        ("{% if \"\" %}{% break /%}{% /if %}ok", "The 'break' tag must be used within a loop"),
+        (
+            "{% forrange i to 2 %}{% if \"\" %}{% fn skip() %}{% continue /%}{% /fn %}{% /if %}{% /forrange %}",
+            "The 'continue' tag must be used within a loop",
+        ),
+        (
+            "{% foreach x in xs %}{% else %}{% if \"\" %}{% break /%}{% /if %}{% /foreach %}",
+            "The 'break' tag must be used within a loop",
+        ),
+    ] {
+        let mut output = Vec::<u8>::new();
+        let mut parser = TemplateParser::new(
+            Context::default(),
+            input,
+            &mut output,
+        ).unwrap();
+        let error = parser.parse().unwrap_err();
+        assert_eq!(message, error.message());
+    }
+}
+
+#[test]
+fn parse_callblock_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% fn box(cls = \"box\") %}<div class=\"{{ cls }}\">{{{ caller }}}</div>{% /fn %}\\\n",
+            "{% set name = \"x\" /%}\\\n",
+            "{% callblock box(cls = \"panel\") %}<b>{{ name }}</b>{% /callblock %}|",
+            "{% callblock box() %}plain{% /callblock %}|",
+            "{% if \"a\" == \"b\" %}{% callblock box() %}{% /callblock %}{% /if %}",
+        ),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "<div class=\"panel\"><b>x</b></div>|<div class=\"box\">plain</div>|",
+        output_str
+    );
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    create_dir_all(to).unwrap();
+    for entry in from.read_dir().unwrap() {
+        let path = entry.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target);
+        }
+        else {
+            copy(&path, &target).unwrap();
+        }
+    }
+}
+
+#[test]
+fn parse_forfile_2() {
+    // checkouts don't keep modification times, so they are set on a copy
+    let dir = temp_dir().join(format!("arcana-parse-forfile-2-{}", std::process::id()));
+    copy_dir(Path::new("./resources/parse_forfile_2"), &dir);
+    for (name, secs) in [("2.fred.md", 1_000), ("notes.txt", 2_000), ("10.mark.md", 3_000)] {
+        File::options()
+            .write(true)
+            .open(dir.join("posts").join(name))
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+		dir.join("test.arct"),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	let posts = dir.join("./posts").to_string_lossy().into_owned();
+	assert_eq!(
+        [
+            format!("{posts}/2.fred.md {posts}/10.mark.md \n"),
+            format!("{posts}/archive/1.old.md {posts}/10.mark.md {posts}/2.fred.md {posts}/.draft.md \n"),
+            format!("{posts}/10.mark.md {posts}/notes.txt {posts}/2.fred.md \n"),
+            format!("{posts}/archive"),
+        ].concat(),
+        output_str
+    );
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parse_forrange_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        concat!(
+            "{% forrange i from 1 to 10 step 2 as loop %}",
+            "{{ i }}:{{ loop.index }}/{{ loop.size }}{% if !loop.islast %}, {% /if %}",
+            "{% /forrange %}|",
+            "{% forrange i from 10 to 0 step -3 %}{{ i }}{% /forrange %}|",
+            "{% forrange i to 4 reversed %}{{ i }}{% /forrange %}|",
+            "{% forrange i from 5 to 1 %}{{ i }}{% else %}empty{% /forrange %}",
+        ),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        "1:0/5, 3:1/5, 5:2/5, 7:3/5, 9:4/5|10741|3210|empty",
+        output_str
+    );
+}
+
+#[test]
+fn parse_forrange_2() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+        "{% forrange i to 3 step 0 %}{{ i }}{% /forrange %}",
+		&mut output,
+	).unwrap();
+	let error = parser.parse().unwrap_err();
+	assert_eq!("The step of a 'forrange' tag cannot be 0", error.message());
+}
+
+#[test]
+fn parse_forlines_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+		PathBuf::from("./resources/parse_forlines_1/test.arct"),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        concat!(
+            "1/4 apples < pears;2/4 pears;3/4  < plums;4/4 plums;\n",
+            "[][pears]\n",
+            "apples pears \n",
+            "empty\n",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_forcsv_1() {
+	let mut output = Vec::<u8>::new();
+	let mut parser = TemplateParser::new(
+		Context::default(),
+		PathBuf::from("./resources/parse_forcsv_1/test.arct"),
+		&mut output,
+	).unwrap();
+	parser.parse().unwrap();
+	drop(parser);
+	let output_str = String::from_utf8(output).unwrap();
+	assert_eq!(
+        concat!(
+            "Mark (Portland, OR), Fred &quot;the&quot; Third (Austin)\n",
+            "name/city\n",
+            "1/2 Portland, OR;2/2 Austin after Mark,&quot;Portland, OR&quot;;\n",
+        ),
+        output_str
+    );
+}
+
 #[test]
 fn parse_forsplit_1() {
 	let mut output = Vec::<u8>::new();
@@ -1808,3 +2197,787 @@ fn parse_basename_1() {
 	let output_str = String::from_utf8(output).unwrap();
 	assert_eq!("page.arct", output_str);
 }
+
+#[test]
+fn parse_output_escape_1() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::Html);
+    context.add_variable("name", "./", "<script>alert('x')</script> & \"y\"");
+    let input = "{{ name }}|{{{ name }}}";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; &quot;y&quot;",
+            "|<script>alert('x')</script> & \"y\"",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_output_escape_2() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::XmlAttribute);
+    context.add_variable("title", "./", "a\tb\n\"c\"");
+    let input = "<img alt=\"{{ title }}\">";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("<img alt=\"a&#9;b&#10;&quot;c&quot;\">", output_str);
+}
+
+#[test]
+fn parse_output_escape_3() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.add_variable("name", "./", "<b>");
+    let input = "{{ name }}|{{{ name }}}";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    // values are escaped as html unless told otherwise
+    assert_eq!("&lt;b&gt;|<b>", output_str);
+}
+
+#[test]
+fn parse_output_escape_4() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::Html);
+    context.add_variable("url", "./", "/?a=1&b=2");
+    context.add_variable("name", "./", "<Home>");
+    let mut parser = TemplateParser::new(
+        context,
+        PathBuf::from("./resources/parse_escape_html_1/test.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "<a href=\"/?a=1&amp;b=2\">&lt;Home&gt;</a>",
+            "|<a href=\"/?a=1&b=2\"><Home></a>\n",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_include_3() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::Html);
+    let mut parser = TemplateParser::new(
+        context,
+        PathBuf::from("./resources/parse_include_3/test.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "&lt;b&gt;&quot;bold&quot;&lt;/b&gt;\n&amp; more",
+            "|<b>\"bold\"</b>\n& more\n",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_output_escape_bypassed_1() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::Html);
+    context.add_variable("name", "./", "<i>");
+    let input = "{% if \"1\" %}{{ name }}|{{{ name }}}{% /if %}";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("&lt;i&gt;|<i>", output_str);
+}
+
+#[test]
+fn parse_output_escape_5() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.add_variable("name", "./", "<i>");
+    // content rendered from the template is already escaped
+    let input = concat!(
+        "{% set b %}<b>{{ name }}</b>{% /set %}",
+        "{% replace b %}<p>{{ b }}</p>{% /replace %}",
+        "{{ b }}|{{ b | replace(\"p>\", \"div>\") }}|{{ b ~ name }}|{% set c = b /%}{{ c }}",
+    );
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "<p><b>&lt;i&gt;</b></p>",
+            "|<div><b>&lt;i&gt;</b></div>",
+            "|&lt;p&gt;&lt;b&gt;&amp;lt;i&amp;gt;&lt;/b&gt;&lt;/p&gt;&lt;i&gt;",
+            "|&lt;p&gt;&lt;b&gt;&amp;lt;i&amp;gt;&lt;/b&gt;&lt;/p&gt;",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_filter_1() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.add_variable("title", "./", "  Arcana Templates ");
+    context.add_variable("quote", "./", "say \"hi\"\n");
+    let mut parser = TemplateParser::new(
+        context,
+        PathBuf::from("./resources/parse_filter_1/test.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "ARCAN\n",
+            "N/A\n",
+            "a%20b%26c|&quot;say \\&quot;hi\\&quot;\\n&quot;|null\n",
+            "Hello, arcana templates?\n",
+            "yes\n",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_filter_2() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::Html);
+    context.add_variable("name", "./", "<b>");
+    let input = "{{ name | upper }}|{{{ name | upper }}}|{{{ name | escape }}}|{{ \"x\" | escape(\"none\") }}";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("&lt;B&gt;|<B>|&lt;b&gt;|x", output_str);
+}
+
+#[test]
+fn parse_filter_3() {
+    let mut output = Vec::<u8>::new();
+    let input = "{% set x %}{{ \"abc\" | unknown }}{% /set %}";
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    assert!(parser.parse().is_err());
+}
+
+#[test]
+fn parse_filter_4() {
+    let mut output = Vec::<u8>::new();
+    let input = "{{ \"abc\" | truncate }}";
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    assert!(parser.parse().is_err());
+}
+
+#[test]
+fn parse_filter_5() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.register_filter("wrap", |value, args| {
+        let around = args.first().cloned().flatten().unwrap_or_default();
+        Ok(value.map(|v| format!("{around}{v}{around}")))
+    });
+    context.register_filter("upper", |value, _| Ok(value.map(|v| v.to_lowercase())));
+    context.register_filter("fail", |_, _| Err(InternalError::new("Cannot filter")));
+    context.add_variable("x", "./", "Ab");
+    let input = "{{ x | wrap(\"*\") | upper }}|{% if x | wrap(\"-\") == \"-Ab-\" %}yes{% /if %}";
+    let mut parser = TemplateParser::new(context.clone(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("*ab*|yes", output_str);
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(context, "{{ x | fail }}", &mut output).unwrap();
+    assert_eq!("Cannot filter", parser.parse().unwrap_err().message());
+}
+
+#[test]
+fn parse_filter_6() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::Html);
+    context.register_native_function("tag", |_| Ok("<b>".to_owned()));
+    context.add_variable("v", "./", "<i>");
+    // the value of the escape filter is not escaped a second time, unless it
+    // is combined with other values
+    let input = concat!(
+        "{{ v | escape }}|{{ v | escape | replace(\"i\", \"u\") }}|{{ tag() | escape }}",
+        "|{{ v | escape(\"xml-attribute\") }}|{{ v | escape ~ \"&\" }}",
+    );
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("&lt;i&gt;|&lt;u&gt;|&lt;b&gt;|&lt;i&gt;|&amp;lt;i&amp;gt;&amp;", output_str);
+}
+
+#[test]
+fn parse_filter_7() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    // a registered filter replaces the built-in one, and only escapes when
+    // it says so
+    context.register_filter("escape", |value, _| Ok(value.map(|v| v.replace('<', "["))));
+    context.add_variable("v", "./", "<i>");
+    let mut parser = TemplateParser::new(context, "{{ v | escape }}", &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("[i&gt;", output_str);
+}
+
+#[test]
+fn parse_native_function_1() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.set_escape(Escape::Html);
+    context.register_native_function("join", |args| {
+        Ok(args.iter().map(|a| a.clone().unwrap_or_default()).collect::<Vec<String>>().join("<>"))
+    });
+    context.add_variable("x", "./", "a");
+    let input = "{{ join(x, \"b\", missing) }}|{{{ join(x, \"b\") }}}|{{ join(x) | upper }}";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("a&lt;&gt;b&lt;&gt;|a<>b|A", output_str);
+}
+
+#[test]
+fn parse_native_function_2() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.register_native_function("greet", |_| Ok("native".to_owned()));
+    let input = "{{ greet() }}|{% fn greet() %}template{% /fn %}{{ greet() }}";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("native|template", output_str);
+}
+
+#[test]
+fn parse_native_function_3() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.register_native_function("fail", |_| Err(InternalError::new("Price not found")));
+    let input = "first line\n{{ fail() }}";
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    let error = parser.parse().unwrap_err();
+    assert_eq!("Price not found", error.message());
+    assert!(error.to_string().contains("Occured on line 2"));
+}
+
+#[test]
+fn parse_load_1() {
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(
+        Context::default(),
+        PathBuf::from("./resources/parse_load_1/test.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "Arcana by A Test\n",
+            "3 tags: one, two, three\n",
+            "Second has 80 words\n",
+            "activepublished\n",
+            "logo",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_load_2() {
+    let mut output = Vec::<u8>::new();
+    let input = "{% load \"./resources/parse_load_1/data.json\" /%}{{ title }}|{% if \"1\" %}{% load \"x.json\" /%}{% /if %}";
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    assert!(parser.parse().is_err());
+    drop(parser);
+
+    let mut output = Vec::<u8>::new();
+    let input = "{% load \"./resources/parse_load_1/data.json\" /%}{{ title }}";
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("Arcana", output_str);
+}
+
+#[test]
+fn parse_front_matter_1() {
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(
+        Context::default(),
+        PathBuf::from("./resources/parse_front_matter_1/test.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "none\n",
+            "First Post (2026-01-02): news rust \n",
+            "Second body\n",
+            "Second Post (2026-02-03) by A Test\n",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_front_matter_2() {
+    let mut output = Vec::<u8>::new();
+    let mut context = Context::default();
+    context.add_variable("title", "./", "Open");
+    let mut parser = TemplateParser::new(
+        context,
+        PathBuf::from("./resources/parse_front_matter_2/test.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("---\ntitle: Never closed\nOpen\n", output_str);
+}
+
+#[test]
+fn parse_front_matter_3() {
+    let mut output = Vec::<u8>::new();
+    let input = "a\n---\nb: c\n---\n";
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(input, output_str);
+}
+
+#[test]
+fn parse_front_matter_4() {
+    for input in ["---\nb: c\n---\n{{ b }}", "---\n{{ b }}"] {
+        let mut output = Vec::<u8>::new();
+        let mut context = Context::default();
+        context.add_variable("b", "./", "{{ b }}");
+        let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+        parser.parse().unwrap();
+        drop(parser);
+        let output_str = String::from_utf8(output).unwrap();
+        assert_eq!(input, output_str);
+    }
+}
+
+#[test]
+fn parse_front_matter_5() {
+    for (name, expected) in [
+        ("list.arct", "---\n- first\n- second\n---\n\n"),
+        ("rule.md", "---\n\nA rule above some *Markdown*.\n\n---\nfooter\n"),
+    ] {
+        let mut output = Vec::<u8>::new();
+        let mut parser = TemplateParser::new(
+            Context::default(),
+            PathBuf::from(format!("./resources/parse_front_matter_5/{name}")),
+            &mut output,
+        ).unwrap();
+        parser.parse().unwrap();
+        drop(parser);
+        let output_str = String::from_utf8(output).unwrap();
+        assert_eq!(expected, output_str);
+    }
+}
+
+#[test]
+fn parse_stream_1() {
+    let mut ctx = Context::default();
+    ctx.set_streaming(true);
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(
+        ctx,
+        PathBuf::from("./resources/parse_stream_1/page.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("<body><main>Hello page\n</main>\n</body>\n", output_str);
+}
+
+#[test]
+fn parse_stream_2() {
+    let input = "streamed{% foreach x in xs %} {{ x }}{% /foreach %}{% assert \"0\" /%}";
+
+    let mut ctx = Context::default();
+    ctx.add_variable("xs", "", "a");
+    ctx.add_variable("xs", "", "b");
+    ctx.set_streaming(true);
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(ctx.clone(), input, &mut output).unwrap();
+    assert!(parser.parse().is_err());
+    drop(parser);
+    assert_eq!("streamed a b", String::from_utf8(output).unwrap());
+
+    ctx.set_streaming(false);
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(ctx, input, &mut output).unwrap();
+    assert!(parser.parse().is_err());
+    drop(parser);
+    assert!(output.is_empty());
+}
+
+#[test]
+fn parse_stream_3() {
+    let mut ctx = Context::default();
+    ctx.set_streaming(true);
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(
+        ctx,
+        PathBuf::from("./resources/parse_stream_1/late.arct"),
+        &mut output,
+    ).unwrap();
+    let e = parser.parse().unwrap_err();
+    assert_eq!(
+        "Cannot extend after output was streamed, 'extend' must come before any output",
+        e.message()
+    );
+}
+
+#[test]
+fn parse_utf8_1() {
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(
+        Context::default(),
+        PathBuf::from("./resources/parse_utf8_1/test.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "<main>Ünïcode “quotes” — 🎉\n",
+            "Grüße aus Köln\n",
+            "Zoë: 日本 Ελλάδα \n",
+            "café.txt=ä crème.txt=ö \n",
+            "naïve: ¿Ünïcode “quotes”?\n",
+            "</main>\n",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_utf8_2() {
+    let mut output = Vec::<u8>::new();
+    let input = "Zoë {# größe #}🎉 \\\n  ½{{ \"naïve\" }} {% set x %}Ελλάδα{% /set %}{{ x }} {{{ x }}}";
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("Zoë 🎉 ½naïve Ελλάδα Ελλάδα", output_str);
+}
+
+#[test]
+fn parse_utf8_3() {
+    let mut output = Vec::<u8>::new();
+    let input = concat!(
+        "{% set x %}ä{% /set %}{% set x %}日本語{% /set %}",
+        "{% length x /%} {% count x /%} {% nth x %}0{% /nth %} ",
+        "{% foreach v in x reversed %}{{ v }}·{% /foreach %} ",
+        "{% forsplit p in \"a→b→c\" on \"→\" %}[{{ p }}]{% /forsplit %} ",
+        "{% if x == \"日本語\" %}jä{% else %}nein{% /if %} ",
+        "{% fn greet(n) %}¡Hola {{ n }}!{% /fn %}{{ greet(\"Zoë\") }} ",
+        "{% set sum %}{% add \"1\" %}2{% /add %}€{% /set %}{{ sum }}",
+    );
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("3 2 ä 日本語·ä· [a][b][c] jä ¡Hola Zoë! 3€", output_str);
+}
+
+#[test]
+fn parse_utf8_4() {
+    let mut output = Vec::<u8>::new();
+    let input = concat!(
+        "{% set x %}<Zoë>{% /set %}",
+        "{{ x | upper }} {{ x | truncate(3) }} {{ x | urlencode }} ",
+        "{{ x | json }} {{ x | replace(\"ë\", \"e\") }} {{ x | escape }}",
+    );
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("<ZOË> <Zo %3CZo%C3%AB%3E \"<Zoë>\" <Zoe> &lt;Zoë&gt;", output_str);
+}
+
+#[test]
+fn parse_decimal_1() {
+    let input = "{% mul \"19.99\" %}3{% /mul %} {% add \"0.1\" %}0.2{% /add %} \
+        {% sub 1 %}1.25{% /sub %} {% mod \"7.5\" %}2{% /mod %} {% pow \"1.5\" %}2{% /pow %}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("59.97 0.3 -0.25 1.5 2.25", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_decimal_2() {
+    let input = "{% div 19 %}5{% /div %} {% div \"19.0\" %}5{% /div %} \
+        {% div 10 precision 2 %}3{% /div %} {% div 2 precision 0 %}3{% /div %} \
+        {% mul \"2.345\" precision 2 %}1{% /mul %} {% add 1 precision 2 %}1{% /add %} \
+        {% pow 2 %}-2{% /pow %}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("3 3.8 3.33 1 2.35 2.00 0.25", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_decimal_3() {
+    for (input, message) in [
+        ("{% div 1 %}0{% /div %}", "Division by zero"),
+        ("{% mod 1 %}0.0{% /mod %}", "Division by zero"),
+        ("{% div 1 precision 29 %}3{% /div %}", "Precision of 'div' tag must be from 0 to 28"),
+        ("{% pow 2 %}0.5{% /pow %}", "Exponent must be a whole number"),
+    ] {
+        let mut output = Vec::<u8>::new();
+        let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+        let e = parser.parse().unwrap_err();
+        assert_eq!(message, e.message(), "{input}");
+    }
+}
+
+#[test]
+fn parse_decimal_4() {
+    let input = "{% if \"2.5\" < \"10\" %}a{% /if %}\
+        {% if \"1.50\" <= \"1.5\" %}b{% /if %}\
+        {% if \"-0.5\" >= \"-1\" %}c{% /if %}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("abc", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_expression_1() {
+    let input = "{% set price %}19.99{% /set %}{% set qty %}3{% /set %}{% set shipping %}5{% /set %}\\
+        {% set first %}Ann{% /set %}{% set last %}Lee{% /set %}\\
+        {{ (price * qty) + shipping }} {{ first ~ \" \" ~ last }} {{ (first ~ last) | upper }}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("64.97 Ann Lee ANNLEE", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_expression_2() {
+    let mut context = Context::default();
+    context.add_variable("qty", "./", "3");
+    context.add_variable("names", "./", "a");
+    context.add_variable("names", "./", "b");
+    context.add_variable("names", "./", "c");
+
+    let input = "{% set total = qty * 2 + 1 /%}{{ total }} \\
+        {% foreach name in names from qty - 2 to qty %}{{ name }}{% /foreach %} \\
+        {% if qty * 2 > 5 && \"x\" ~ qty == \"x3\" %}yes{% /if %} \\
+        {% if qty == \"3\" || 6 / (qty - 3) > 1 %}short{% /if %}\\
+        {% if \"\" %}{% set skipped = 1 / 0 /%}{% /if %}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("7 bc yes short", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_expression_3() {
+    for (input, message) in [
+        ("{{ 1 / 0 }}", "Division by zero"),
+        ("{{ \"a\" * 2 }}", "Failed to coerce value into a number"),
+        ("{{ (1 + 2 }}", "Unexpected character in tag 'output'"),
+        ("{% set x = 1 %}", "Unexpected character in tag 'set'"),
+    ] {
+        let mut output = Vec::<u8>::new();
+        let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+        let e = parser.parse().unwrap_err();
+        assert_eq!(message, e.message(), "{input}");
+    }
+}
+
+#[test]
+fn parse_with_1() {
+    let mut context = Context::default();
+    context.add_variable("title", "./", "Site");
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(
+        context,
+        PathBuf::from("./resources/parse_with_1/page.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!("<h1>Home (3)</h1><h1>Site! ()</h1>Site\n", output_str);
+}
+
+#[test]
+fn parse_with_2() {
+    let input = "{% set x %}outer{% /set %}\
+        {% with x = \"a\", y = x %}{{ x }} {{ y }}{% set x %}inner{% /set %} {{ x }}{% /with %} \
+        {{ x }} [{{ y }}] \
+        {% with x = x ~ 2 %}{% with x = x ~ 3 %}{{ x }}{% /with %} {{ x }}{% /with %}\
+        {% if \"\" %}{% with x = 1 / 0 %}{{ x }}{% /with %}{% /if %}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("a outer inner outer [] outer23 outer2", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_with_3() {
+    for (input, message) in [
+        ("{% with x %}{% /with %}", "Unexpected character in tag 'with'"),
+        ("{% with x = 1 %}{% else %}{% /with %}", "Unexpected tag 'else' in tag 'with'"),
+        ("{% with x = 1 %}", "Unclosed tag 'with'"),
+    ] {
+        let mut output = Vec::<u8>::new();
+        let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+        let e = parser.parse().unwrap_err();
+        assert_eq!(message, e.message(), "{input}");
+    }
+}
+
+#[test]
+fn parse_unset_1() {
+    let input = "{% set x %}1{% /set %}{% set x %}2{% /set %}{% set x %}3{% /set %}\
+        {% pop x /%}{% count x /%}{{ x }} \
+        {% unset x /%}{% count x /%}[{{ x }}]\
+        {% pop x /%}{% unset x /%}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("22 0[]", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_replace_1() {
+    let mut context = Context::default();
+    for n in [ "1", "2", "3", "4", ] {
+        context.add_variable("ns", "./", n);
+    }
+
+    let input = "{% set total %}0{% /set %}{% set csv %}{% /set %}\
+        {% foreach n in ns %}\
+            {% replace total = total + n /%}\
+            {% replace csv %}{{ csv }}{{ n }},{% /replace %}\
+        {% /foreach %}\
+        {% count total /%} {{ total }} {% count csv /%} {{ csv }}\
+        {% if \"\" %}{% replace total = 1 / 0 /%}{% replace total %}x{% /replace %}{% /if %}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(context, input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!("1 10 1 1,2,3,4,", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn parse_block_1() {
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(
+        Context::default(),
+        PathBuf::from("./resources/parse_block_1/page.arct"),
+        &mut output,
+    ).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    let output_str = String::from_utf8(output).unwrap();
+    assert_eq!(
+        concat!(
+            "<title>Page - Section - Base</title>\n",
+            "<nav><a>Section</a></nav>\n",
+            "<main>Main</main>\n",
+            "<footer>Footer</footer>\n",
+            "<h1></h1>\n",
+            "<section>Page content\n</section>\n\n",
+        ),
+        output_str
+    );
+}
+
+#[test]
+fn parse_block_2() {
+    let input = "{% block a %}A{% /block %}\
+        {% if \"\" %}{% block b %}B{% /block %}{% super /%}{% /if %}\
+        {% compile \"./resources/parse_block_1/layouts/base.arct\" /%}";
+
+    let mut output = Vec::<u8>::new();
+    let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+    parser.parse().unwrap();
+    drop(parser);
+    assert_eq!(
+        "A<title>Base</title>\n<nav></nav>\n<main>Nothing</main>\n<footer>Footer</footer>\n<h1></h1>\n\n",
+        String::from_utf8(output).unwrap()
+    );
+}
+
+#[test]
+fn parse_block_3() {
+    for (input, message) in [
+        ("{% super /%}", "The 'super' tag must be used within a block"),
+        ("{% block a %}{% super /%}{% /block %}", "Block 'a' does not override another block"),
+        ("{% block a %}", "Unclosed tag 'block'"),
+        ("{% block a %}{% /block %}{% block a %}{% /block %}", "Duplicate block 'a'"),
+        ("{% block a %}{% if x %}{% block a %}{% /block %}{% /if %}{% /block %}", "Duplicate block 'a'"),
+    ] {
+        let mut output = Vec::<u8>::new();
+        let mut parser = TemplateParser::new(Context::default(), input, &mut output).unwrap();
+        let e = parser.parse().unwrap_err();
+        assert_eq!(message, e.message(), "{input}");
+    }
+}
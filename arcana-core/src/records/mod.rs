@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod test;
+
+use {
+    crate::error::{ InternalResult, IntoInternal, },
+    std::{
+        fs::File,
+        io::{ self, BufRead, BufReader, },
+        mem::take,
+        path::Path,
+    },
+};
+
+/// A line, or a row of comma separated values, with the text it was read from.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Record {
+    pub(crate) fields: Vec<String>,
+    pub(crate) text: String,
+}
+
+/// Reads the records of a file one at a time, a record being either a line or
+/// a row of comma separated values.
+pub(crate) struct Records<R> {
+    reader: R,
+    csv: bool,
+}
+
+impl Records<BufReader<File>> {
+    pub(crate) fn open(path: &Path, csv: bool) -> InternalResult<Self> {
+        let file = File::open(path)
+            .into_internal(format!("Failed to open file {path:?}"))?;
+
+        Ok(Self::new(BufReader::new(file), csv))
+    }
+}
+
+impl<R: BufRead> Records<R> {
+    pub(crate) fn new(reader: R, csv: bool) -> Self {
+        Self { reader, csv, }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        if line.ends_with('\n') {
+            line.pop();
+
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+
+        Ok(Some(line))
+    }
+
+    /// Read a row, where a field within double quotes can contain commas, line
+    /// breaks and doubled double quotes. Empty lines are skipped.
+    fn read_row(&mut self) -> io::Result<Option<Record>> {
+        let mut line = loop {
+            match self.read_line()? {
+                Some(line) if line.is_empty() => continue,
+                Some(line) => break line,
+                None => return Ok(None),
+            }
+        };
+
+        let mut text = line.clone();
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+
+        loop {
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match (quoted, c) {
+                    (true, '"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    (true, '"') => quoted = false,
+                    (false, '"') if field.is_empty() => quoted = true,
+                    (false, ',') => fields.push(take(&mut field)),
+                    (_, c) => field.push(c),
+                }
+            }
+
+            if !quoted {
+                break;
+            }
+
+            // the quoted field continues on the next line
+            line = self.read_line()?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unterminated quoted field"))?;
+            field.push('\n');
+            text.push('\n');
+            text.push_str(&line);
+        }
+
+        fields.push(field);
+
+        Ok(Some(Record { fields, text, }))
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = InternalResult<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = if self.csv {
+            self.read_row()
+        }
+        else {
+            self.read_line().map(|line| line.map(|line| Record { fields: vec![line.clone()], text: line, }))
+        };
+
+        record.into_internal("Failed to read record").transpose()
+    }
+}
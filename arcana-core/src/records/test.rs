@@ -0,0 +1,44 @@
+use super::Records;
+
+fn records(input: &str, csv: bool) -> Vec<Vec<String>> {
+    Records::new(input.as_bytes(), csv)
+        .map(|record| record.map(|record| record.fields))
+        .collect::<Result<Vec<Vec<String>>, _>>()
+        .unwrap()
+}
+
+#[test]
+fn lines_1() {
+    assert_eq!(
+        vec![vec!["one"], vec![""], vec!["three"]],
+        records("one\r\n\nthree\n", false),
+    );
+    assert!(records("", false).is_empty());
+}
+
+#[test]
+fn csv_1() {
+    assert_eq!(
+        vec![
+            vec!["name", "note"],
+            vec!["Mark", "said \"hi\", twice"],
+            vec!["Fred", "line\nbreak"],
+            vec!["", ""],
+        ],
+        records("name,note\n\nMark,\"said \"\"hi\"\", twice\"\nFred,\"line\nbreak\"\n,\n", true),
+    );
+}
+
+#[test]
+fn csv_2() {
+    assert!(Records::new("a,\"open\n".as_bytes(), true).next().unwrap().is_err());
+}
+
+#[test]
+fn csv_3() {
+    let texts = Records::new("a,\"b\r\nc\"\n\nd\n".as_bytes(), true)
+        .map(|record| record.unwrap().text)
+        .collect::<Vec<String>>();
+
+    assert_eq!(vec!["a,\"b\nc\"", "d"], texts);
+}
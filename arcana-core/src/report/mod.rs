@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    fs::canonicalize,
+    path::{ Path, PathBuf, },
+    rc::Rc,
+};
+
+/// The files and directories read while compiling a template, i.e. by the
+/// `extend`, `call`, `compile`, `include`, `load`, `fordir`, `forfile`,
+/// `path`, `basename` and `dirname` tags. Paths are canonical where they
+/// exist.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompileReport {
+    files: BTreeSet<PathBuf>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+/// A report shared between the parser of a template and every parser it
+/// spawns.
+pub(crate) type SharedReport = Rc<RefCell<CompileReport>>;
+
+fn canonical(path: &Path) -> PathBuf {
+    canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+impl CompileReport {
+    pub(crate) fn shared() -> SharedReport {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    pub(crate) fn add_file<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        if path.as_os_str().is_empty() {
+            return;
+        }
+
+        self.files.insert(canonical(path));
+    }
+
+    pub(crate) fn add_dir<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        if path.as_os_str().is_empty() {
+            return;
+        }
+
+        self.dirs.insert(canonical(path));
+    }
+
+    /// Add a path which may be either a file or a directory.
+    pub(crate) fn add_path<P: AsRef<Path>>(&mut self, path: P) {
+        if path.as_ref().is_dir() {
+            self.add_dir(path);
+        }
+        else {
+            self.add_file(path);
+        }
+    }
+
+    /// Every file read, including the compiled template.
+    pub fn files(&self) -> &BTreeSet<PathBuf> {
+        &self.files
+    }
+
+    /// Every directory read.
+    pub fn dirs(&self) -> &BTreeSet<PathBuf> {
+        &self.dirs
+    }
+
+    /// Whether any of the changed paths is a file that was read or lies within
+    /// a directory that was read.
+    pub fn is_affected_by(&self, changed: &BTreeSet<PathBuf>) -> bool {
+        changed.iter().any(|path| {
+            self.files.contains(path)
+                || self.dirs.contains(path)
+                || path.parent().is_some_and(|parent| self.dirs.contains(parent))
+        })
+    }
+
+    /// Write the report as a Makefile rule, as generated by `cc -MD -MP`, so
+    /// that `target` is rebuilt whenever anything read changes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::Arcana;
+    ///
+    /// let path = "./resources/parse_file_1/page.arct";
+    /// let report = Arcana::compile_file_report(path, Vec::<u8>::new()).unwrap();
+    /// let rule = report.to_makefile("page.txt");
+    /// assert!(rule.starts_with("page.txt: "));
+    /// ```
+    ///
+    pub fn to_makefile<S: AsRef<str>>(&self, target: S) -> String {
+        let paths = self.files.iter()
+            .chain(self.dirs.iter())
+            .map(|path| escape_make(&path.to_string_lossy()))
+            .collect::<Vec<String>>();
+
+        let mut rule = format!("{}:", escape_make(target.as_ref()));
+        for path in paths.iter() {
+            rule.push_str(" \\\n  ");
+            rule.push_str(path);
+        }
+        rule.push('\n');
+
+        // a target without prerequisites for every path, so that removing one
+        // does not fail the build
+        for path in paths.iter() {
+            rule.push('\n');
+            rule.push_str(path);
+            rule.push_str(":\n");
+        }
+
+        rule
+    }
+}
+
+fn escape_make(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' '|'#'|':' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '$' => escaped.push_str("$$"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
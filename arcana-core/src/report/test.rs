@@ -0,0 +1,28 @@
+use {
+    super::CompileReport,
+    std::{ collections::BTreeSet, path::PathBuf, },
+};
+
+#[test]
+fn to_makefile_1() {
+    let mut report = CompileReport::default();
+    report.files.insert(PathBuf::from("/site/my page.arct"));
+    report.dirs.insert(PathBuf::from("/site/$posts"));
+
+    assert_eq!(
+        "out/page\\:1.html: \\\n  /site/my\\ page.arct \\\n  /site/$$posts\n\n/site/my\\ page.arct:\n\n/site/$$posts:\n",
+        report.to_makefile("out/page:1.html")
+    );
+}
+
+#[test]
+fn is_affected_by_1() {
+    let mut report = CompileReport::default();
+    report.files.insert(PathBuf::from("/site/page.arct"));
+    report.dirs.insert(PathBuf::from("/site/posts"));
+
+    assert!(report.is_affected_by(&BTreeSet::from([ PathBuf::from("/site/page.arct") ])));
+    assert!(report.is_affected_by(&BTreeSet::from([ PathBuf::from("/site/posts/new.md") ])));
+    assert!(!report.is_affected_by(&BTreeSet::from([ PathBuf::from("/site/other.arct") ])));
+    assert!(!report.is_affected_by(&BTreeSet::from([ PathBuf::from("/site/posts/deep/new.md") ])));
+}
@@ -0,0 +1,656 @@
+#[cfg(test)]
+mod test;
+
+use {
+    crate::{
+        context::Context,
+        error::{ InternalError, InternalResult, IntoInternal, OrElseUpgrade, },
+        input::{ Input, TryIntoInput, },
+        parser::TemplateParser,
+        report::CompileReport,
+    },
+    serde_json::Value,
+    std::{
+        cell::RefCell,
+        collections::{ HashMap, HashSet, },
+        fmt::Debug,
+        io::{ Read, Write, },
+        path::{ Path, PathBuf, },
+        rc::Rc,
+        sync::Arc,
+    },
+};
+
+/// The name of a tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Add,
+    Assert,
+    Basename,
+    Block,
+    Break,
+    Call,
+    Callblock,
+    Compile,
+    Continue,
+    Count,
+    Dirname,
+    Div,
+    Extend,
+    Fn,
+    Forcsv,
+    Foreach,
+    Fordir,
+    Forfile,
+    Forlines,
+    Forrange,
+    Forsplit,
+    From,
+    If,
+    Import,
+    Include,
+    Length,
+    Load,
+    Mod,
+    Mul,
+    Nth,
+    Path,
+    Pop,
+    Pow,
+    Replace,
+    Set,
+    Sub,
+    Super,
+    Unset,
+    With,
+}
+
+impl Kind {
+    fn from_name(name: &str) -> Option<Self> {
+        let kind = match name {
+            "add" => Self::Add,
+            "assert" => Self::Assert,
+            "basename" => Self::Basename,
+            "block" => Self::Block,
+            "break" => Self::Break,
+            "call" => Self::Call,
+            "callblock" => Self::Callblock,
+            "compile" => Self::Compile,
+            "continue" => Self::Continue,
+            "count" => Self::Count,
+            "dirname" => Self::Dirname,
+            "div" => Self::Div,
+            "extend" => Self::Extend,
+            "fn" => Self::Fn,
+            "forcsv" => Self::Forcsv,
+            "foreach" => Self::Foreach,
+            "fordir" => Self::Fordir,
+            "forfile" => Self::Forfile,
+            "forlines" => Self::Forlines,
+            "forrange" => Self::Forrange,
+            "forsplit" => Self::Forsplit,
+            "from" => Self::From,
+            "if" => Self::If,
+            "import" => Self::Import,
+            "include" => Self::Include,
+            "length" => Self::Length,
+            "load" => Self::Load,
+            "mod" => Self::Mod,
+            "mul" => Self::Mul,
+            "nth" => Self::Nth,
+            "path" => Self::Path,
+            "pop" => Self::Pop,
+            "pow" => Self::Pow,
+            "replace" => Self::Replace,
+            "set" => Self::Set,
+            "sub" => Self::Sub,
+            "super" => Self::Super,
+            "unset" => Self::Unset,
+            "with" => Self::With,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+
+    /// Whether the tag has content up to its end-tag, a 'set' or 'replace'
+    /// only has when it is given no expression.
+    fn has_content(&self, source: &str) -> bool {
+        match self {
+            Self::Add|Self::Block|Self::Callblock|Self::Div|Self::Fn|Self::If
+            |Self::Mod|Self::Mul|Self::Nth|Self::Pow|Self::Sub|Self::With => true,
+            Self::Replace|Self::Set => !source.ends_with("/%}") && !source.contains('='),
+            kind => kind.is_loop(),
+        }
+    }
+
+    /// Whether the content of the tag is repeated, so that it may hold a
+    /// 'break' or 'continue' tag.
+    fn is_loop(&self) -> bool {
+        matches!(
+            self,
+            Self::Forcsv|Self::Foreach|Self::Fordir|Self::Forfile
+            |Self::Forlines|Self::Forrange|Self::Forsplit
+        )
+    }
+
+    /// Whether the content of the tag is rendered in place, so that a 'break'
+    /// or 'continue' within it ends an enclosing loop. The content of the
+    /// other tags is rendered by a parser of its own, i.e. into a variable, or
+    /// elsewhere, i.e. a function or a block.
+    fn renders_in_place(&self) -> bool {
+        matches!(self, Self::If|Self::With) || self.is_loop()
+    }
+
+    /// Whether the content of the tag may be split by an 'else' tag.
+    fn has_otherwise(&self) -> bool {
+        matches!(self, Self::If) || self.is_loop()
+    }
+}
+
+/// The source of a tag after its name, or of an output after its opening
+/// brackets, up to and including its closing brackets. It is read again each
+/// time the tag is rendered, starting at the position it has in the template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Source {
+    text: String,
+    line: usize,
+    index: usize,
+    /// The whole line of the template the source starts on, shown near errors.
+    hint: String,
+}
+
+impl Source {
+    /// An input reading the source as part of the template at path.
+    pub(crate) fn input<P>(&self, path: P) -> InternalResult<Input<&[u8]>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut input = self.text.as_bytes().try_into_input()?;
+        input.set_path(path);
+        input.set_position(self.line, self.index);
+        input.set_hint(&self.hint);
+
+        Ok(input)
+    }
+}
+
+/// A tag and its content, parsed once along with the rest of the template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Tag {
+    kind: Kind,
+    source: Source,
+    content: Arc<[Node]>,
+    otherwise: Option<Arc<[Node]>>,
+}
+
+impl Tag {
+    pub(crate) fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub(crate) fn source(&self) -> &Source {
+        &self.source
+    }
+
+    /// The content up to the end-tag, or up to the 'else' tag when there is
+    /// one, empty when the tag has no content.
+    pub(crate) fn content(&self) -> &Arc<[Node]> {
+        &self.content
+    }
+
+    /// The content after the 'else' tag.
+    pub(crate) fn otherwise(&self) -> Option<&[Node]> {
+        self.otherwise.as_deref()
+    }
+}
+
+/// A node of a parsed template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Node {
+    /// Text written as is, escapes are already applied.
+    Text(String),
+    /// An output, its value is read from the source when rendered.
+    Output(Source),
+    Tag(Tag),
+}
+
+/// What ended the nodes read by [`parse_nodes`].
+enum Ending {
+    Eof,
+    Else,
+    End(String),
+}
+
+/// A template parsed once and rendered any number of times.
+///
+/// Parsing reads the input, strips its front matter and parses the rest into
+/// nodes, each tag holding its content as nodes. Rendering walks the nodes and
+/// only reads the arguments of tags and outputs again.
+#[derive(Clone, Debug)]
+pub struct Template {
+    path: PathBuf,
+    front_matter: Option<Value>,
+    nodes: Vec<Node>,
+}
+
+/// Templates parsed while rendering others, by the path they were read from,
+/// i.e. the ones extended, called, compiled or imported. Handing them to the
+/// next render spares parsing them again.
+#[derive(Clone, Debug, Default)]
+pub struct Templates {
+    templates: HashMap<PathBuf, Arc<Template>>,
+}
+
+/// Templates shared between the parser of a template and every parser it
+/// spawns.
+pub(crate) type SharedTemplates = Rc<RefCell<Templates>>;
+
+impl Templates {
+    /// The template parsed from a path, if any.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<Arc<Template>> {
+        self.templates.get(path.as_ref()).cloned()
+    }
+
+    pub(crate) fn insert(&mut self, path: PathBuf, template: Arc<Template>) {
+        self.templates.insert(path, template);
+    }
+
+    /// The number of templates.
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Whether no template was parsed.
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+impl Template {
+    /// Parse a template.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The [readable](Read) template.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Context, Template, };
+    ///
+    /// let template = Template::parse("Hello, {{ name }}!").unwrap();
+    ///
+    /// for name in [ "Ann", "Bob", ] {
+    ///     let mut ctx = Context::default();
+    ///     ctx.add_variable("name", "./", name);
+    ///
+    ///     let mut output = Vec::<u8>::new();
+    ///     template.render(&ctx, &mut output).unwrap();
+    ///     assert_eq!(format!("Hello, {name}!"), String::from_utf8(output).unwrap());
+    /// }
+    /// ```
+    ///
+    pub fn parse<R, I>(input: I) -> InternalResult<Self>
+    where
+        R: Read + Debug,
+        I: TryIntoInput<R>,
+    {
+        let mut input = input.try_into_input()?;
+
+        let front_matter = match input.take_front_matter() {
+            Ok(front_matter) => front_matter,
+            Err(e) => return Err(e).or_else_upgrade_from_input(&input),
+        };
+
+        let nodes = match parse(&mut input) {
+            Ok(nodes) => nodes,
+            Err(e) => return Err(e).or_else_upgrade_from_input(&input),
+        };
+
+        Ok(Self {
+            path: input.path().to_owned(),
+            front_matter,
+            nodes,
+        })
+    }
+
+    /// Parse the template at a path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The [path](Path) to the template.
+    pub fn parse_file<P>(path: P) -> InternalResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::parse(path.as_ref())
+    }
+
+    /// The path the template was parsed from, empty when it was not read from
+    /// a file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Render the template to an output with a starting context.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The starting [context](Context), it is left untouched.
+    /// * `output` - The [writable](Write) output.
+    pub fn render<W>(&self, ctx: &Context, output: &mut W) -> InternalResult<()>
+    where
+        W: Write + Debug,
+    {
+        let mut parser = TemplateParser::new(ctx.clone(), self.empty_input()?, output)?;
+        parser.render(self)
+    }
+
+    /// Render the template to an output with a starting context, reusing the
+    /// templates it reads from `templates` and adding the ones it parses.
+    /// Returns every file and directory read along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The starting [context](Context), it is left untouched.
+    /// * `output` - The [writable](Write) output.
+    /// * `templates` - The [templates](Templates) parsed before.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arcana_core::{ Context, Template, Templates, };
+    ///
+    /// let template = Template::parse("{% call \"./resources/compile-other.arct\" /%}").unwrap();
+    /// let mut templates = Templates::default();
+    ///
+    /// let mut output = Vec::<u8>::new();
+    /// let report = template.render_with(&Context::default(), &mut output, &mut templates)
+    ///     .unwrap();
+    /// assert_eq!(1, report.files().len());
+    /// assert!(templates.get("./resources/compile-other.arct").is_some());
+    /// ```
+    ///
+    pub fn render_with<W>(
+        &self, ctx: &Context, output: &mut W, templates: &mut Templates,
+    ) -> InternalResult<CompileReport>
+    where
+        W: Write + Debug,
+    {
+        let report = CompileReport::shared();
+        let shared = Rc::new(RefCell::new(std::mem::take(templates)));
+
+        let mut parser = TemplateParser::new(ctx.clone(), self.empty_input()?, output)?;
+        parser.set_dependencies(report.clone())?;
+        parser.set_templates(shared.clone());
+        let result = parser.render(self);
+        drop(parser);
+
+        *templates = shared.take();
+        result?;
+
+        Ok(report.take())
+    }
+
+    pub(crate) fn front_matter(&self) -> Option<&Value> {
+        self.front_matter.as_ref()
+    }
+
+    pub(crate) fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// An input without content at the path of the template, for its nodes.
+    pub(crate) fn empty_input(&self) -> InternalResult<Input<&'static [u8]>> {
+        let mut input = "".try_into_input()?;
+        input.set_path(&self.path);
+
+        Ok(input)
+    }
+}
+
+/// Parse the input into nodes, the content of every tag is parsed along with
+/// the tag.
+pub(crate) fn parse<R>(input: &mut Input<R>) -> InternalResult<Vec<Node>>
+where
+    R: Read + Debug,
+{
+    match parse_nodes(input, false, &mut HashSet::new())? {
+        (nodes, Ending::Eof) => Ok(nodes),
+        (_, Ending::Else) => Err(InternalError::new("Unexpected tag 'else'")),
+        (_, Ending::End(name)) => Err(InternalError::new(format!("Unexpected end-tag '{name}'"))),
+    }
+}
+
+/// Parse nodes up to the end of the input, an 'else' tag or an end-tag.
+/// Whether the nodes are within a loop is known, so that a misplaced 'break'
+/// or 'continue' is found even when it is never rendered. The names of the
+/// blocks defined so far are kept, so that a block defined twice is found.
+fn parse_nodes<R>(
+    input: &mut Input<R>, looping: bool, blocks: &mut HashSet<String>,
+) -> InternalResult<(Vec<Node>, Ending)>
+where
+    R: Read + Debug,
+{
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+
+    let ending = loop {
+        let c = match input.current().copied() {
+            Some(c) => c,
+            None => break Ending::Eof,
+        };
+
+        match (c, input.peek()) {
+            // an escape removes the whitespace after it
+            ('\\', _) => {
+                input.step()?;
+                while input.current().is_some_and(|c| c.is_whitespace()) {
+                    input.step()?;
+                }
+            },
+            ('{', Some('#')) => parse_comment(input)?,
+            ('{', Some('{')) => {
+                input.step()?;
+                input.step()?;
+
+                // a third bracket writes the value without escaping
+                let end = if input.current() == Some(&'{') { "}}}" } else { "}}" };
+
+                push_text(&mut nodes, &mut text);
+                nodes.push(Node::Output(parse_source(input, "output", end)?));
+            },
+            ('{', Some('%')) => {
+                input.step()?;
+                input.step()?;
+                skip_whitespace(input)?;
+
+                if input.current() == Some(&'/') {
+                    input.step()?;
+                    skip_whitespace(input)?;
+
+                    let name = parse_name(input)?;
+                    expect_end_of_tag(input, "end-tag", &name)?;
+
+                    break Ending::End(name);
+                }
+
+                let name = parse_name(input)?;
+                if name == "else" {
+                    expect_end_of_tag(input, "tag", &name)?;
+
+                    break Ending::Else;
+                }
+
+                push_text(&mut nodes, &mut text);
+                nodes.push(Node::Tag(parse_tag(input, name, looping, blocks)?));
+            },
+            (c, _) => {
+                text.push(c);
+                input.step()?;
+            },
+        }
+    };
+
+    push_text(&mut nodes, &mut text);
+
+    Ok((nodes, ending))
+}
+
+/// Parse a tag after its name, along with its content.
+fn parse_tag<R>(input: &mut Input<R>, name: String, looping: bool, blocks: &mut HashSet<String>) -> InternalResult<Tag>
+where
+    R: Read + Debug,
+{
+    let kind = Kind::from_name(&name)
+        .into_internal(format!("Unknown tag '{name}'"))?;
+
+    if matches!(kind, Kind::Break|Kind::Continue) && !looping {
+        return Err(InternalError::new(format!("The '{name}' tag must be used within a loop")));
+    }
+
+    let source = parse_source(input, &name, "%}")?;
+
+    if kind == Kind::Block {
+        let block = source.text.trim_start()
+            .split(|c: char| c.is_whitespace() || c == '%')
+            .next()
+            .unwrap_or("");
+
+        if !blocks.insert(block.to_owned()) {
+            return Err(InternalError::new(format!("Duplicate block '{block}'")));
+        }
+    }
+    if !kind.has_content(&source.text) {
+        return Ok(Tag { kind, source, content: Arc::default(), otherwise: None, });
+    }
+
+    let content_looping = kind.is_loop() || (looping && kind.renders_in_place());
+
+    let (content, ending) = parse_nodes(input, content_looping, blocks)?;
+    let (otherwise, ending) = match ending {
+        // the content of an 'else' tag is only within a loop when the tag is
+        Ending::Else if kind.has_otherwise() => {
+            let (otherwise, ending) = parse_nodes(input, looping, blocks)?;
+            (Some(otherwise.into()), ending)
+        },
+        ending => (None, ending),
+    };
+
+    match ending {
+        Ending::End(end) if end == name => Ok(Tag { kind, source, content: content.into(), otherwise, }),
+        Ending::End(end) => Err(InternalError::new(format!("Unexpected end-tag '{end}' in tag '{name}'"))),
+        Ending::Else => Err(InternalError::new(format!("Unexpected tag 'else' in tag '{name}'"))),
+        Ending::Eof => Err(InternalError::new(format!("Unclosed tag '{name}'"))),
+    }
+}
+
+/// Read the source of a tag or output up to and including its closing
+/// brackets, which are not looked for within strings.
+fn parse_source<R>(input: &mut Input<R>, name: &str, end: &str) -> InternalResult<Source>
+where
+    R: Read + Debug,
+{
+    let line = input.line_no();
+    let index = input.index();
+    let hint = input.line();
+
+    let mut text = String::new();
+    let mut in_string = false;
+
+    loop {
+        let c = input.current().copied()
+            .into_internal(format!("Unexpected EOF in tag '{name}'"))?;
+
+        text.push(c);
+        input.step()?;
+
+        if in_string {
+            match c {
+                '\\' => if let Some(c) = input.current().copied() {
+                    text.push(c);
+                    input.step()?;
+                },
+                '"' => in_string = false,
+                _ => {},
+            }
+        }
+        else if c == '"' {
+            in_string = true;
+        }
+        else if text.ends_with(end) {
+            break;
+        }
+    }
+
+    Ok(Source { text, line, index, hint, })
+}
+
+fn parse_comment<R>(input: &mut Input<R>) -> InternalResult<()>
+where
+    R: Read + Debug,
+{
+    input.step()?;
+    input.step()?;
+
+    while !input.is_end() {
+        let closing = input.current() == Some(&'#');
+        input.step()?;
+
+        if closing && input.current() == Some(&'}') {
+            input.step()?;
+
+            return Ok(());
+        }
+    }
+
+    Err(InternalError::new("Unclosed comment"))
+}
+
+fn parse_name<R>(input: &mut Input<R>) -> InternalResult<String>
+where
+    R: Read + Debug,
+{
+    let mut name = String::new();
+    while let Some(c) = input.current().copied().filter(char::is_ascii_lowercase) {
+        name.push(c);
+        input.step()?;
+    }
+
+    Ok(name)
+}
+
+fn skip_whitespace<R>(input: &mut Input<R>) -> InternalResult<()>
+where
+    R: Read + Debug,
+{
+    while input.current().is_some_and(|c| c.is_whitespace()) {
+        input.step()?;
+    }
+
+    Ok(())
+}
+
+fn expect_end_of_tag<R>(input: &mut Input<R>, tag: &str, name: &str) -> InternalResult<()>
+where
+    R: Read + Debug,
+{
+    skip_whitespace(input)?;
+
+    for expected in [ '%', '}', ] {
+        match input.current().copied() {
+            Some(c) if c == expected => input.step()?,
+            Some(_) => return Err(InternalError::new(format!("Unexpected character in {tag} '{name}'"))),
+            None => return Err(InternalError::new(format!("Unexpected EOF in {tag} '{name}'"))),
+        }
+    }
+
+    Ok(())
+}
+
+fn push_text(nodes: &mut Vec<Node>, text: &mut String) {
+    if !text.is_empty() {
+        nodes.push(Node::Text(std::mem::take(text)));
+    }
+}
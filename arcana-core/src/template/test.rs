@@ -0,0 +1,322 @@
+use {
+    super::{
+        expect_end_of_tag, Kind, Node, parse_comment, parse_source, skip_whitespace, Source, Tag,
+        Template, Templates,
+    },
+    crate::{ Arcana, context::Context, input::TryIntoInput, },
+    std::sync::Arc,
+};
+
+fn source(text: &str, line: usize, index: usize, hint: &str) -> Source {
+    Source { text: text.to_owned(), line, index, hint: hint.to_owned(), }
+}
+
+fn tag(kind: Kind, source: Source, content: Vec<Node>, otherwise: Option<Vec<Node>>) -> Node {
+    Node::Tag(Tag { kind, source, content: content.into(), otherwise: otherwise.map(Into::into), })
+}
+
+#[test]
+fn parse_1() {
+    let input = "a {# note #}b \\\n  c{% set x %}{{ y }}{% /set %}{{ x }}\nd";
+    let template = Template::parse(input).unwrap();
+    let hint = "  c{% set x %}{{ y }}{% /set %}{{ x }}\n";
+
+    assert_eq!(
+        vec![
+            Node::Text("a b c".to_owned()),
+            tag(Kind::Set, source(" x %}", 2, 9, hint), vec![ Node::Output(source(" y }}", 2, 16, hint)), ], None),
+            Node::Output(source(" x }}", 2, 33, hint)),
+            Node::Text("\nd".to_owned()),
+        ],
+        template.nodes
+    );
+}
+
+#[test]
+fn parse_2() {
+    let input = "{% if x %}{% if y %}\"%}\"{% /if %}{% else %}z{% /if %} {% path \"a\" /%}";
+    let template = Template::parse(input).unwrap();
+    let hint = input;
+
+    assert_eq!(
+        vec![
+            tag(
+                Kind::If,
+                source(" x %}", 1, 5, hint),
+                vec![ tag(Kind::If, source(" y %}", 1, 15, hint), vec![ Node::Text("\"%}\"".to_owned()), ], None), ],
+                Some(vec![ Node::Text("z".to_owned()), ]),
+            ),
+            Node::Text(" ".to_owned()),
+            tag(Kind::Path, source(" \"a\" /%}", 1, 61, hint), vec![], None),
+        ],
+        template.nodes
+    );
+}
+
+#[test]
+fn parse_3() {
+    let cases = [
+        ("{% if x %}a", "Unclosed tag 'if'"),
+        ("{% if x %}a{% /with %}", "Unexpected end-tag 'with' in tag 'if'"),
+        ("{% with x = 1 %}{% else %}{% /with %}", "Unexpected tag 'else' in tag 'with'"),
+        ("a{% /if %}", "Unexpected end-tag 'if'"),
+        ("{% else %}", "Unexpected tag 'else'"),
+        ("{% nope %}", "Unknown tag 'nope'"),
+        ("{% if x %}{% break /%}{% /if %}", "The 'break' tag must be used within a loop"),
+        ("{# a", "Unclosed comment"),
+        ("{{ \"}}\"", "Unexpected EOF in tag 'output'"),
+    ];
+
+    for (input, expected) in cases {
+        let e = Template::parse(input).unwrap_err();
+        assert_eq!(expected, e.message(), "{input}");
+    }
+}
+
+#[test]
+fn parse_4() {
+    let input = "{% foreach i in items %}{% fn f() %}{% /fn %}{% set x = i /%}{{ x }}{% /foreach %}";
+    let template = Template::parse(input).unwrap();
+    let hint = input;
+
+    assert_eq!(
+        vec![
+            tag(
+                Kind::Foreach,
+                source(" i in items %}", 1, 10, hint),
+                vec![
+                    tag(Kind::Fn, source(" f() %}", 1, 29, hint), vec![], None),
+                    tag(Kind::Set, source(" x = i /%}", 1, 51, hint), vec![], None),
+                    Node::Output(source(" x }}", 1, 63, hint)),
+                ],
+                None,
+            ),
+        ],
+        template.nodes
+    );
+}
+
+#[test]
+fn parse_5() {
+    // whitespace is kept in the text as is
+    for input in [ "        \n x", "x", ] {
+        let template = Template::parse(input).unwrap();
+        assert_eq!(vec![ Node::Text(input.to_owned()), ], template.nodes);
+    }
+}
+
+#[test]
+fn parse_6() {
+    // content rendered by a parser of its own or elsewhere does not end the
+    // loop around it
+    let contents = [
+        "{% add 1 %}{{}}{% /add %}",
+        "{% sub 1 %}{{}}{% /sub %}",
+        "{% mul 1 %}{{}}{% /mul %}",
+        "{% div 1 %}{{}}{% /div %}",
+        "{% mod 1 %}{{}}{% /mod %}",
+        "{% pow 1 %}{{}}{% /pow %}",
+        "{% nth x %}{{}}{% /nth %}",
+        "{% set x %}{{}}{% /set %}",
+        "{% replace x %}{{}}{% /replace %}",
+        "{% block x %}{{}}{% /block %}",
+        "{% fn f() %}{{}}{% /fn %}",
+        "{% callblock f() %}{{}}{% /callblock %}",
+    ];
+
+    for content in contents {
+        for tag in [ "break", "continue", ] {
+            let input = format!(
+                "{{% forrange i to 2 %}}{}{{% /forrange %}}",
+                content.replace("{{}}", &format!("{{% {tag} /%}}")),
+            );
+            let e = Template::parse(input.as_str()).unwrap_err();
+            assert_eq!(format!("The '{tag}' tag must be used within a loop"), e.message(), "{input}");
+        }
+    }
+}
+
+#[test]
+fn parse_7() {
+    // the content of 'if' and 'with' is rendered in place
+    let template = Template::parse(
+        "{% forrange i to 4 %}{% with j = i %}{% if j == 2 %}{% break /%}{% /if %}{% /with %}{{ i }}{% /forrange %}"
+    ).unwrap();
+
+    let mut output = Vec::<u8>::new();
+    template.render(&Context::default(), &mut output).unwrap();
+    assert_eq!("01", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn skip_whitespace_1() {
+    let mut input = "        \n x".try_into_input().unwrap();
+    skip_whitespace(&mut input).unwrap();
+    assert_eq!(Some(&'x'), input.current());
+}
+
+#[test]
+fn parse_source_1() {
+    let mut input = "this \"that \\\" /%}\" the other /%}/%}".try_into_input().unwrap();
+    let source = parse_source(&mut input, "test", "/%}").unwrap();
+    // the sequence is not looked for within strings
+    assert_eq!("this \"that \\\" /%}\" the other /%}", source.text);
+    assert_eq!(Some(&'/'), input.current());
+}
+
+#[test]
+fn parse_source_2() {
+    let mut input = "this that the other /%}/%}".try_into_input().unwrap();
+    let source = parse_source(&mut input, "test", "/%}").unwrap();
+    assert_eq!("this that the other /%}", source.text);
+    assert_eq!(Some(&'/'), input.current());
+}
+
+#[test]
+fn parse_source_3() {
+    let mut input = "this that the other %".try_into_input().unwrap();
+    let e = parse_source(&mut input, "test", "%}").unwrap_err();
+    assert_eq!("Unexpected EOF in tag 'test'", e.message());
+    assert_eq!(None, input.current());
+}
+
+#[test]
+fn parse_comment_1() {
+    let mut input = "{# abcdef_x\n\t #}$".try_into_input().unwrap();
+    parse_comment(&mut input).unwrap();
+    assert_eq!(Some(&'$'), input.current());
+}
+
+#[test]
+fn expect_end_of_tag_1() {
+    let mut input = " %}".try_into_input().unwrap();
+    expect_end_of_tag(&mut input, "end-tag", "test").unwrap();
+    assert_eq!(None, input.current());
+}
+
+#[test]
+fn expect_end_of_tag_2() {
+    let mut input = "$".try_into_input().unwrap();
+    let e = expect_end_of_tag(&mut input, "end-tag", "test").unwrap_err();
+    assert_eq!("Unexpected character in end-tag 'test'", e.message());
+    assert_eq!(Some(&'$'), input.current());
+}
+
+#[test]
+fn expect_end_of_tag_3() {
+    let mut input = "".try_into_input().unwrap();
+    let e = expect_end_of_tag(&mut input, "end-tag", "test").unwrap_err();
+    assert_eq!("Unexpected EOF in end-tag 'test'", e.message());
+    assert_eq!(None, input.current());
+}
+
+#[test]
+fn render_1() {
+    let template = Template::parse("{% set x %}{{ name }}!{% /set %}{{ x }}").unwrap();
+
+    let mut ctx = Context::default();
+    for name in [ "a", "b", ] {
+        ctx.add_variable("name", "./", name);
+
+        let mut output = Vec::<u8>::new();
+        template.render(&ctx, &mut output).unwrap();
+        assert_eq!(format!("{name}!"), String::from_utf8(output).unwrap());
+    }
+
+    assert!(ctx.value("x").is_none());
+}
+
+#[test]
+fn render_2() {
+    let paths = [
+        "./resources/parse_complex_1/page.arct",
+        "./resources/parse_complex_6/allsiblings.arct",
+        "./resources/parse_foreach_2/test.arct",
+        "./resources/parse_forfile_1/loop.arct",
+        "./resources/parse_front_matter_1/test.arct",
+        "./resources/parse_function_1/page.arct",
+        "./resources/parse_block_1/page.arct",
+        "./resources/parse_if_1/test.arct",
+        "./resources/report_1/page.arct",
+    ];
+
+    for path in paths {
+        let mut expected = Vec::<u8>::new();
+        Arcana::compile_file_with_ctx(path, &mut expected, Context::default()).unwrap();
+
+        let template = Template::parse_file(path).unwrap();
+        for _ in 0..2 {
+            let mut output = Vec::<u8>::new();
+            template.render(&Context::default(), &mut output).unwrap();
+            assert_eq!(String::from_utf8_lossy(&expected), String::from_utf8_lossy(&output), "{path}");
+        }
+    }
+}
+
+#[test]
+fn render_3() {
+    let template = Template::parse("a\nb {% set 1 %}2{% /set %}").unwrap();
+
+    let mut output = Vec::<u8>::new();
+    let e = template.render(&Context::default(), &mut output).unwrap_err();
+    assert!(e.to_string().contains("Occured on line 2:9"), "{e}");
+}
+
+#[test]
+fn render_4() {
+    let template = Template::parse("Zoë — {% set x %}日本語{% /set %}{{ x }} 🎉").unwrap();
+
+    let mut output = Vec::<u8>::new();
+    template.render(&Context::default(), &mut output).unwrap();
+    assert_eq!("Zoë — 日本語 🎉", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn render_5() {
+    let mut template = Template::parse("{% forrange i from 0 to 3 %}{{ i }}-{% /forrange %}").unwrap();
+
+    // the content of the loop is parsed once, so that every iteration renders
+    // the same nodes
+    let Node::Tag(tag) = &mut template.nodes[0] else {
+        panic!("Expected a tag, found {:?}", template.nodes[0]);
+    };
+    let content = Arc::get_mut(&mut tag.content).unwrap();
+    assert_eq!(Node::Text("-".to_owned()), content[1]);
+    content[1] = Node::Text("+".to_owned());
+
+    let mut output = Vec::<u8>::new();
+    template.render(&Context::default(), &mut output).unwrap();
+    assert_eq!("0+1+2+", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn render_6() {
+    let path = "./resources/compile-other.arct";
+    let template = Template::parse(format!("{{% call \"{path}\" /%}}").as_str()).unwrap();
+    let mut templates = Templates::default();
+
+    let mut output = Vec::<u8>::new();
+    let report = template.render_with(&Context::default(), &mut output, &mut templates).unwrap();
+    assert_eq!(1, report.files().len());
+    assert_eq!(1, templates.len());
+
+    // a template read before is not parsed again
+    let mut cached = templates.get(path).unwrap().as_ref().clone();
+    cached.nodes = vec![ Node::Text("cached".to_owned()), ];
+    templates.insert(path.into(), Arc::new(cached));
+
+    let mut output = Vec::<u8>::new();
+    let report = template.render_with(&Context::default(), &mut output, &mut templates).unwrap();
+    assert_eq!("cached", String::from_utf8(output).unwrap());
+    assert_eq!(1, report.files().len());
+}
+
+#[test]
+fn render_7() {
+    // the whole line of the tag is shown, not only its source
+    let template = Template::parse("a\n{% set x = %}\n").unwrap();
+
+    let mut output = Vec::<u8>::new();
+    let e = template.render(&Context::default(), &mut output).unwrap_err();
+    assert!(e.to_string().contains("Occured on line 2:11\nNear '{% set x = %}\n'"), "{e}");
+}
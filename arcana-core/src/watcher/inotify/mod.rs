@@ -0,0 +1,123 @@
+use {
+    crate::error::{ InternalError, InternalResult, },
+    std::{
+        collections::{ BTreeSet, HashMap, },
+        ffi::{ CString, OsStr, },
+        mem::size_of,
+        os::unix::ffi::OsStrExt,
+        path::PathBuf,
+        ptr::read_unaligned,
+    },
+};
+
+const MASK: u32 = libc::IN_CLOSE_WRITE|libc::IN_CREATE|libc::IN_DELETE|libc::IN_MOVED_FROM|libc::IN_MOVED_TO;
+
+// how long to keep collecting events after the first, editors tend to write a
+// file in multiple steps
+const SETTLE_MS: i32 = 50;
+
+/// Watches the directories holding the watched files, as editors often
+/// replace a file rather than write to it.
+#[derive(Debug)]
+pub(crate) struct Backend {
+    fd: i32,
+    watches: HashMap<i32, PathBuf>,
+}
+
+impl Backend {
+    pub(crate) fn new() -> InternalResult<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(InternalError::new("Failed to initialize inotify"));
+        }
+
+        Ok(Self { fd, watches: HashMap::new(), })
+    }
+
+    fn watch(&mut self, dir: &PathBuf) {
+        if self.watches.values().any(|d| d == dir) {
+            return;
+        }
+
+        let c_dir = match CString::new(dir.as_os_str().as_bytes()) {
+            Ok(c_dir) => c_dir,
+            Err(_) => return,
+        };
+
+        // a missing directory is not an error, it may be created later on
+        let wd = unsafe { libc::inotify_add_watch(self.fd, c_dir.as_ptr(), MASK) };
+        if wd >= 0 {
+            self.watches.insert(wd, dir.to_owned());
+        }
+    }
+
+    fn read_events(&mut self, changed: &mut BTreeSet<PathBuf>) -> InternalResult<()> {
+        let mut buffer = [0_u8; 4096];
+        let read = unsafe { libc::read(self.fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if read < 0 {
+            return Err(InternalError::new("Failed to read inotify events"));
+        }
+
+        let read = read as usize;
+        let mut offset = 0;
+        while offset + size_of::<libc::inotify_event>() <= read {
+            let event = unsafe {
+                read_unaligned(buffer.as_ptr().add(offset).cast::<libc::inotify_event>())
+            };
+
+            let name_start = offset + size_of::<libc::inotify_event>();
+            let name_end = (name_start + event.len as usize).min(read);
+            let name = buffer[name_start..name_end].split(|b| *b == 0).next().unwrap_or_default();
+
+            if event.mask & libc::IN_IGNORED != 0 {
+                self.watches.remove(&event.wd);
+            }
+            else if let Some(dir) = self.watches.get(&event.wd) {
+                if name.is_empty() {
+                    changed.insert(dir.to_owned());
+                }
+                else {
+                    changed.insert(dir.join(OsStr::from_bytes(name)));
+                }
+            }
+
+            offset = name_start + event.len as usize;
+        }
+
+        Ok(())
+    }
+
+    fn ready(&self, timeout_ms: i32) -> bool {
+        let mut pollfd = libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0, };
+        unsafe { libc::poll(&mut pollfd, 1, timeout_ms) > 0 }
+    }
+
+    pub(crate) fn wait(&mut self, dirs: &BTreeSet<PathBuf>, files: &BTreeSet<PathBuf>) -> InternalResult<BTreeSet<PathBuf>> {
+        for dir in dirs.iter() {
+            self.watch(dir);
+        }
+
+        for file in files.iter() {
+            if let Some(dir) = file.parent() {
+                self.watch(&dir.to_owned());
+            }
+        }
+
+        let mut changed = BTreeSet::new();
+        while changed.is_empty() {
+            self.read_events(&mut changed)?;
+
+            while self.ready(SETTLE_MS) {
+                self.read_events(&mut changed)?;
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+impl Drop for Backend {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod test;
+
+#[cfg(target_os = "linux")]
+mod inotify;
+#[cfg(not(target_os = "linux"))]
+mod poll;
+
+#[cfg(target_os = "linux")]
+use inotify::Backend;
+#[cfg(not(target_os = "linux"))]
+use poll::Backend;
+
+use {
+    crate::{
+        context::Context,
+        error::InternalResult,
+        parser::TemplateParser,
+        report::CompileReport,
+    },
+    std::{
+        collections::{ BTreeMap, BTreeSet, },
+        fmt::Debug,
+        fs::{ canonicalize, read_dir, },
+        io::Write,
+        path::{ Path, PathBuf, },
+    },
+};
+
+/// Compiles templates while recording every file each of them reads, so that
+/// only the templates depending on a changed file need to be compiled again.
+///
+/// Changes are detected with inotify on Linux and by polling modification
+/// times elsewhere.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use arcana_core::{ Context, Watcher, };
+///
+/// let mut watcher = Watcher::new().unwrap();
+/// watcher.compile("./page.arct", std::io::stdout(), Context::default()).unwrap();
+///
+/// loop {
+///     let changed = watcher.wait().unwrap();
+///     for template in watcher.dependents(&changed) {
+///         watcher.compile(template, std::io::stdout(), Context::default()).unwrap();
+///     }
+/// }
+/// ```
+///
+#[derive(Debug)]
+pub struct Watcher {
+    reports: BTreeMap<PathBuf, CompileReport>,
+    dirs: BTreeSet<PathBuf>,
+    backend: Backend,
+}
+
+impl Watcher {
+    pub fn new() -> InternalResult<Self> {
+        Ok(Self {
+            reports: BTreeMap::new(),
+            dirs: BTreeSet::new(),
+            backend: Backend::new()?,
+        })
+    }
+
+    /// Compile a template file, recording the files it depends on. The
+    /// template is watched even when compiling it fails, so that fixing it is
+    /// noticed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The [path](Path) to the template.
+    /// * `output` - The [writable](Write) output.
+    /// * `ctx` - The [context](Context).
+    ///
+    pub fn compile<P, W>(&mut self, path: P, output: W, ctx: Context) -> InternalResult<()>
+    where
+        P: AsRef<Path>,
+        W: Write + Debug,
+    {
+        let path = path.as_ref();
+        let report = CompileReport::shared();
+        report.borrow_mut().add_file(path);
+
+        let result = TemplateParser::new(ctx, path, output)
+            .and_then(|mut parser| {
+                parser.set_dependencies(report.clone())?;
+                parser.parse()
+            });
+
+        let key = canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        self.reports.insert(key, report.take());
+
+        result
+    }
+
+    /// Also report changes to any file within a directory and its
+    /// subdirectories, i.e. for new files.
+    pub fn watch_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        let dir = dir.as_ref();
+        self.dirs.insert(canonicalize(dir).unwrap_or_else(|_| dir.to_owned()));
+    }
+
+    /// Stop watching a template, i.e. once its file is removed.
+    pub fn forget<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        self.reports.remove(&canonicalize(path).unwrap_or_else(|_| path.to_owned()));
+    }
+
+    /// The compiled templates which read any of the changed files, or a
+    /// directory they are in.
+    pub fn dependents(&self, changed: &BTreeSet<PathBuf>) -> Vec<PathBuf> {
+        self.reports.iter()
+            .filter(|(_, report)| report.is_affected_by(changed))
+            .map(|(template, _)| template.to_owned())
+            .collect()
+    }
+
+    /// Block until watched files change, returning their paths.
+    pub fn wait(&mut self) -> InternalResult<BTreeSet<PathBuf>> {
+        let files = self.reports.values()
+            .flat_map(|report| report.files().iter())
+            .cloned()
+            .collect::<BTreeSet<PathBuf>>();
+
+        let mut dirs = BTreeSet::new();
+        for dir in self.dirs.iter() {
+            collect_dirs(dir, &mut dirs);
+        }
+        dirs.extend(self.reports.values().flat_map(|report| report.dirs().iter()).cloned());
+
+        self.backend.wait(&dirs, &files)
+    }
+}
+
+fn collect_dirs(dir: &Path, dirs: &mut BTreeSet<PathBuf>) {
+    if !dirs.insert(dir.to_owned()) {
+        return;
+    }
+
+    if let Ok(entries) = read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                collect_dirs(&path, dirs);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use {
+    crate::error::InternalResult,
+    std::{
+        collections::{ BTreeMap, BTreeSet, },
+        fs::{ metadata, read_dir, },
+        path::PathBuf,
+        thread::sleep,
+        time::{ Duration, SystemTime, },
+    },
+};
+
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Compares the modification times of the watched files at an interval.
+#[derive(Debug)]
+pub(crate) struct Backend {
+    mtimes: Option<BTreeMap<PathBuf, SystemTime>>,
+}
+
+impl Backend {
+    pub(crate) fn new() -> InternalResult<Self> {
+        Ok(Self { mtimes: None, })
+    }
+
+    fn scan(dirs: &BTreeSet<PathBuf>, files: &BTreeSet<PathBuf>) -> BTreeMap<PathBuf, SystemTime> {
+        let mut paths = files.clone();
+        for dir in dirs.iter() {
+            if let Ok(entries) = read_dir(dir) {
+                paths.extend(entries.flatten().map(|e| e.path()));
+            }
+        }
+
+        paths.into_iter()
+            .filter_map(|path| {
+                let mtime = metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((path, mtime))
+            })
+            .collect()
+    }
+
+    pub(crate) fn wait(&mut self, dirs: &BTreeSet<PathBuf>, files: &BTreeSet<PathBuf>) -> InternalResult<BTreeSet<PathBuf>> {
+        let mut mtimes = self.mtimes.take().unwrap_or_else(|| Self::scan(dirs, files));
+
+        loop {
+            sleep(INTERVAL);
+
+            let current = Self::scan(dirs, files);
+            let changed = current.iter()
+                .filter(|(path, mtime)| mtimes.get(*path) != Some(*mtime))
+                .map(|(path, _)| path.to_owned())
+                .chain(mtimes.keys().filter(|path| !current.contains_key(*path)).cloned())
+                .collect::<BTreeSet<PathBuf>>();
+
+            mtimes = current;
+
+            if !changed.is_empty() {
+                self.mtimes = Some(mtimes);
+                return Ok(changed);
+            }
+        }
+    }
+}
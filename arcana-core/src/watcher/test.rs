@@ -0,0 +1,72 @@
+use {
+    crate::{ context::Context, watcher::Watcher, },
+    std::{
+        collections::BTreeSet,
+        fs::{ canonicalize, create_dir_all, write, },
+        path::PathBuf,
+        sync::mpsc::{ channel, RecvTimeoutError, },
+        thread::spawn,
+        time::{ Duration, Instant, },
+    },
+};
+
+fn canonical(path: &str) -> PathBuf {
+    canonicalize(path).unwrap()
+}
+
+#[test]
+fn dependents_1() {
+    let mut watcher = Watcher::new().unwrap();
+    let mut output = Vec::<u8>::new();
+    watcher.compile("./resources/watch_1/page.arct", &mut output, Context::default()).unwrap();
+    watcher.compile("./resources/watch_1/other.arct", Vec::<u8>::new(), Context::default()).unwrap();
+    assert_eq!("<main>Page</main>footer", String::from_utf8(output).unwrap());
+
+    let page = vec![canonical("./resources/watch_1/page.arct")];
+    for dependency in ["page.arct", "_vars.arct", "layout.arct", "footer.txt"] {
+        let changed = BTreeSet::from([canonical(&format!("./resources/watch_1/{dependency}"))]);
+        assert_eq!(page, watcher.dependents(&changed));
+    }
+
+    let changed = BTreeSet::from([canonical("./resources/watch_1/other.arct")]);
+    assert_eq!(vec![canonical("./resources/watch_1/other.arct")], watcher.dependents(&changed));
+
+    watcher.forget("./resources/watch_1/other.arct");
+    assert!(watcher.dependents(&changed).is_empty());
+}
+
+#[test]
+fn wait_1() {
+    let dir = std::env::temp_dir().join(format!("arcana-watch-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    let template = dir.join("page.arct");
+    write(&template, "first").unwrap();
+
+    let mut watcher = Watcher::new().unwrap();
+    watcher.compile(&template, Vec::<u8>::new(), Context::default()).unwrap();
+
+    let (sender, receiver) = channel();
+    spawn(move || {
+        let changed = watcher.wait();
+        sender.send((watcher, changed)).unwrap();
+    });
+
+    // keep writing, as the watcher may only start watching after a write
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let (watcher, changed) = loop {
+        write(&template, "second").unwrap();
+
+        match receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(result) => break result,
+            Err(RecvTimeoutError::Timeout) if Instant::now() < deadline => continue,
+            Err(error) => panic!("The change was never noticed, {error}"),
+        }
+    };
+    let changed = changed.unwrap();
+
+    let template = canonicalize(&template).unwrap();
+    assert!(changed.contains(&template));
+    assert_eq!(vec![template], watcher.dependents(&changed));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,228 @@
+#[cfg(test)]
+mod test;
+
+use {
+    arcana_core::{ Arcana, Context, InternalResult, Watcher, },
+    std::{
+        collections::BTreeSet,
+        ffi::OsStr,
+        fs::{ canonicalize, copy, create_dir_all, read_dir, write, },
+        path::{ Path, PathBuf, },
+    },
+};
+
+/// Compiles every template of a source tree into an output directory.
+pub struct Build {
+    src: PathBuf,
+    out: PathBuf,
+    extensions: Vec<String>,
+    out_extension: Option<String>,
+    watcher: Option<Watcher>,
+}
+
+/// The outcome of a build, failures do not stop the rest of the build.
+#[derive(Default)]
+pub struct Summary {
+    pub compiled: usize,
+    pub copied: usize,
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl Summary {
+    fn is_empty(&self) -> bool {
+        self.compiled == 0 && self.copied == 0 && self.failures.is_empty()
+    }
+}
+
+impl Build {
+    pub fn new(src: PathBuf, out: PathBuf) -> Self {
+        Self { src, out, extensions: Vec::new(), out_extension: None, watcher: None, }
+    }
+
+    /// Treat files with the extension `ext` as templates. Defaults to `arct`
+    /// when no extension is added.
+    pub fn add_extension<S: AsRef<str>>(&mut self, ext: S) {
+        self.extensions.push(ext.as_ref().trim_start_matches('.').to_owned());
+    }
+
+    /// Replace the template extension of compiled files with `ext` instead of
+    /// removing it.
+    pub fn set_out_extension<S: AsRef<str>>(&mut self, ext: S) {
+        self.out_extension = Some(ext.as_ref().trim_start_matches('.').to_owned());
+    }
+
+    pub fn run(&mut self, ctx: &Context) -> Summary {
+        if self.extensions.is_empty() {
+            self.add_extension("arct");
+        }
+
+        let mut summary = Summary::default();
+
+        if !self.src.is_dir() {
+            summary.failures.push((self.src.clone(), "Not a directory".to_owned()));
+            return summary;
+        }
+
+        // both are canonical so that paths reported by the watcher and the
+        // output directory, which may live within the source tree, can be
+        // compared against them
+        match create_dir_all(&self.out)
+            .and_then(|_| canonicalize(&self.out))
+            .and_then(|out| Ok((canonicalize(&self.src)?, out)))
+        {
+            Ok((src, out)) => {
+                self.src = src;
+                self.out = out;
+            },
+            Err(e) => {
+                summary.failures.push((self.out.clone(), e.to_string()));
+                return summary;
+            },
+        };
+
+        let src = self.src.clone();
+        self.build_dir(ctx, &src, &mut summary);
+
+        summary
+    }
+
+    /// Build, then keep building the templates depending on changed files
+    /// until an error occurs while watching.
+    pub fn watch<F>(&mut self, ctx: &Context, mut report: F) -> InternalResult<()>
+    where
+        F: FnMut(&Summary),
+    {
+        self.watcher = Some(Watcher::new()?);
+
+        report(&self.run(ctx));
+
+        let src = self.src.clone();
+        let watcher = self.watcher.as_mut().unwrap();
+        watcher.watch_dir(src);
+
+        loop {
+            let watcher = self.watcher.as_mut().unwrap();
+            let changed = watcher.wait()?;
+
+            let mut templates = watcher.dependents(&changed)
+                .into_iter()
+                .collect::<BTreeSet<PathBuf>>();
+
+            let mut summary = Summary::default();
+            for path in changed.iter() {
+                if !path.is_file() || !self.is_output(path) {
+                    continue;
+                }
+
+                if self.is_template(path) {
+                    templates.insert(path.to_owned());
+                }
+                else {
+                    self.build_file(ctx, path, &mut summary);
+                }
+            }
+
+            for template in templates.iter() {
+                if template.is_file() && self.is_output(template) {
+                    self.build_file(ctx, template, &mut summary);
+                }
+                else {
+                    self.watcher.as_mut().unwrap().forget(template);
+                }
+            }
+
+            if !summary.is_empty() {
+                report(&summary);
+            }
+        }
+    }
+
+    fn is_template(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| self.extensions.iter().any(|e| e == ext))
+    }
+
+    /// Whether a file within the source tree is written to the output
+    /// directory, partials are only used by other templates.
+    fn is_output(&self, path: &Path) -> bool {
+        if path.starts_with(&self.out) {
+            return false;
+        }
+
+        match path.strip_prefix(&self.src) {
+            Ok(relative) => !relative.components()
+                .any(|c| c.as_os_str().to_str().is_some_and(|c| c.starts_with('_'))),
+            Err(_) => false,
+        }
+    }
+
+    fn out_path(&self, path: &Path) -> PathBuf {
+        let relative = path.strip_prefix(&self.src).unwrap_or(path);
+        let mut out_path = self.out.join(relative);
+
+        if self.is_template(path) {
+            out_path.set_extension(self.out_extension.as_deref().unwrap_or(""));
+        }
+
+        out_path
+    }
+
+    fn build_dir(&mut self, ctx: &Context, dir: &Path, summary: &mut Summary) {
+        let mut entries = match read_dir(dir).and_then(|entries| entries.collect::<Result<Vec<_>, _>>()) {
+            Ok(entries) => entries.into_iter().map(|e| e.path()).collect::<Vec<PathBuf>>(),
+            Err(e) => {
+                summary.failures.push((dir.to_owned(), e.to_string()));
+                return;
+            },
+        };
+        entries.sort();
+
+        for path in entries {
+            if !self.is_output(&path) {
+                continue;
+            }
+
+            if path.is_dir() {
+                self.build_dir(ctx, &path, summary);
+            }
+            else {
+                self.build_file(ctx, &path, summary);
+            }
+        }
+    }
+
+    fn build_file(&mut self, ctx: &Context, path: &Path, summary: &mut Summary) {
+        let out_path = self.out_path(path);
+
+        if let Some(out_dir) = out_path.parent()
+            && let Err(e) = create_dir_all(out_dir)
+        {
+            summary.failures.push((out_dir.to_owned(), e.to_string()));
+            return;
+        }
+
+        if self.is_template(path) {
+            let mut output = Vec::<u8>::new();
+            let result = match self.watcher.as_mut() {
+                Some(watcher) => watcher.compile(path, &mut output, ctx.clone()),
+                None => Arcana::compile_file_with_ctx(path, &mut output, ctx.clone()),
+            };
+
+            let result = result
+                .map_err(|e| e.to_string())
+                .and_then(|_| write(out_path, output).map_err(|e| e.to_string()));
+
+            match result {
+                Ok(_) => summary.compiled += 1,
+                Err(e) => summary.failures.push((path.to_owned(), e)),
+            }
+        }
+        else {
+            match copy(path, out_path) {
+                Ok(_) => summary.copied += 1,
+                Err(e) => summary.failures.push((path.to_owned(), e.to_string())),
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use {
+    super::Build,
+    arcana_core::Context,
+    std::{
+        env::temp_dir,
+        fs::{ canonicalize, read_to_string, remove_dir_all, },
+        path::PathBuf,
+    },
+};
+
+fn out_dir(name: &str) -> PathBuf {
+    let out = temp_dir().join(format!("arcc-{name}-{}", std::process::id()));
+    let _ = remove_dir_all(&out);
+    out
+}
+
+#[test]
+fn run_1() {
+    let out = out_dir("run-1");
+    let mut build = Build::new(PathBuf::from("./resources/build_1/src"), out.clone());
+    let summary = build.run(&Context::default());
+
+    assert_eq!(2, summary.compiled);
+    assert_eq!(2, summary.copied);
+
+    // a failing template is reported without stopping the others
+    let failures = summary.failures.iter().map(|(path, _)| path.to_owned()).collect::<Vec<_>>();
+    assert_eq!(vec![canonicalize("./resources/build_1/src/broken.arct").unwrap()], failures);
+    assert!(summary.failures[0].1.contains("Function 'missing' never defined"));
+    assert!(!out.join("broken").exists());
+
+    assert_eq!("<h1>Site</h1>\n<p>Home</p>\n", read_to_string(out.join("index.html")).unwrap());
+    assert_eq!("<p>First</p>\n", read_to_string(out.join("posts/first")).unwrap());
+
+    // other files are copied as is
+    assert_eq!("body { margin: 0; }\n", read_to_string(out.join("style.css")).unwrap());
+    assert_eq!("<svg></svg>\n", read_to_string(out.join("img/logo.svg")).unwrap());
+
+    // partials are skipped
+    assert!(!out.join("_partials").exists());
+    assert!(!out.join("_drafts").exists());
+
+    remove_dir_all(out).unwrap();
+}
+
+#[test]
+fn run_2() {
+    let out = out_dir("run-2");
+    let mut build = Build::new(PathBuf::from("./resources/build_1/src"), out.clone());
+    build.add_extension(".css");
+    build.set_out_extension("html");
+    let summary = build.run(&Context::default());
+
+    // only the added extension is compiled once one is added
+    assert_eq!(1, summary.compiled);
+    assert_eq!(4, summary.copied);
+    assert!(summary.failures.is_empty());
+
+    assert_eq!("body { margin: 0; }\n", read_to_string(out.join("style.html")).unwrap());
+    assert!(out.join("index.html.arct").exists());
+    assert!(!out.join("_partials").exists());
+
+    remove_dir_all(out).unwrap();
+}
+
+#[test]
+fn run_3() {
+    let out = out_dir("run-3");
+    let mut build = Build::new(PathBuf::from("./resources/build_1/missing"), out.clone());
+    let summary = build.run(&Context::default());
+
+    assert_eq!(0, summary.compiled);
+    assert_eq!(0, summary.copied);
+    assert_eq!(
+        vec![(PathBuf::from("./resources/build_1/missing"), "Not a directory".to_owned())],
+        summary.failures,
+    );
+}
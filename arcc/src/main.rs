@@ -14,12 +14,20 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+mod build;
+
 use {
+    crate::build::{ Build, Summary, },
     arcana_core::{
         Context,
         Arcana,
+        CompileReport,
+        Escape,
+        InternalError,
+        InternalResult,
+        Watcher,
     },
-    std::{ io, path::PathBuf, },
+    std::{ io::{ self, Write, }, path::PathBuf, },
 };
 
 fn help() -> ! {
@@ -69,6 +77,45 @@ fn set<P: AsRef<std::path::Path>>(ctx: &mut Context, pwd: P, dkv: String) -> boo
     true
 }
 
+fn report(summary: &Summary) {
+    for (path, e) in summary.failures.iter() {
+        eprintln!("arcc: failed to build {path:?}\n{e}");
+    }
+
+    eprintln!(
+        "arcc: compiled {} template(s), copied {} file(s), {} failure(s)",
+        summary.compiled,
+        summary.copied,
+        summary.failures.len(),
+    );
+}
+
+fn watch_file(path: PathBuf, ctx: Context) -> InternalResult<()> {
+    let mut watcher = Watcher::new()?;
+
+    loop {
+        if let Err(e) = watcher.compile(&path, io::stdout(), ctx.clone()) {
+            eprintln!("{e}");
+        }
+
+        // stdout is line buffered and the output may not end with a newline
+        let _ = io::stdout().flush();
+
+        loop {
+            let changed = watcher.wait()?;
+            if !watcher.dependents(&changed).is_empty() {
+                break;
+            }
+        }
+    }
+}
+
+/// Print the files read by a compile as a Makefile rule.
+fn print_deps(target: String, report: &CompileReport) -> InternalResult<()> {
+    io::stdout().write_all(report.to_makefile(target).as_bytes())
+        .map_err(|e| InternalError::new(format!("Failed to print dependencies: {e}")))
+}
+
 fn version() -> ! {
     println!("arcc: v{}", get_short_version());
 	std::process::exit(0)
@@ -80,15 +127,82 @@ fn main() {
     let mut path: Option<PathBuf> = None;
     let mut read_stdin = false;
 
+    let mut build = false;
+    let mut build_dirs: Vec<PathBuf> = Vec::new();
+    let mut extensions: Vec<String> = Vec::new();
+    let mut out_extension: Option<String> = None;
+    let mut watch = false;
+    let mut deps = false;
+    let mut target: Option<String> = None;
+    // after '--' every argument is a path, i.e. a template named 'build'
+    let mut positional = false;
+
     let mut ctx = Context::default();
 
     let mut args = std::env::args();
     args.next(); // burn program name
 
     while let Some(full_arg) = args.next() {
-        if let Some(long_arg) = full_arg.strip_prefix("--") {
+        if !positional && full_arg == "--" {
+            positional = true;
+        }
+        else if !positional && let Some(long_arg) = full_arg.strip_prefix("--") {
             match long_arg {
+                "config" => {
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: --config requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    if let Err(e) = ctx.add_config_file(arg) {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                },
+                "deps" => deps = true,
+                "escape" => {
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: --escape requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    match arg.parse::<Escape>() {
+                        Ok(escape) => ctx.set_escape(escape),
+                        Err(_) => {
+                            eprintln!("arcc: invalid <MODE> passed to --escape");
+                            std::process::exit(1);
+                        },
+                    }
+                },
+                "extension" => {
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: --extension requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    extensions.push(arg);
+                },
                 "help" => help(),
+                "out-extension" => {
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: --out-extension requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    out_extension = Some(arg);
+                },
                 "set" => {
                     let arg = match args.next() {
                         Some(arg) => arg,
@@ -103,17 +217,95 @@ fn main() {
                         std::process::exit(1);
                     }
                 },
+                "stream" => ctx.set_streaming(true),
+                "target" => {
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: --target requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    target = Some(arg);
+                },
                 "version" => version(),
+                "watch" => watch = true,
                 long_arg => {
                     eprintln!("arcc: unknown argument '--{long_arg}'");
                     std::process::exit(1);
                 },
             }
         }
-        else if full_arg.starts_with('-') && full_arg.len() > 1 {
+        else if !positional && full_arg.starts_with('-') && full_arg.len() > 1 {
             let mut short_args = full_arg[1..].chars();
             match short_args.next() {
+                Some('c') => {
+                    if short_args.next().is_some() {
+                        eprintln!("arcc: -c requires a value");
+                        std::process::exit(1);
+                    }
+
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: -c requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    if let Err(e) = ctx.add_config_file(arg) {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                },
+                Some('d') => {
+                    if short_args.next().is_some() {
+                        eprintln!("arcc: unknown arguemnt '{full_arg}'");
+                        std::process::exit(1);
+                    }
+
+                    deps = true;
+                },
+                Some('e') => {
+                    if short_args.next().is_some() {
+                        eprintln!("arcc: -e requires a value");
+                        std::process::exit(1);
+                    }
+
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: -e requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    match arg.parse::<Escape>() {
+                        Ok(escape) => ctx.set_escape(escape),
+                        Err(_) => {
+                            eprintln!("arcc: invalid <MODE> passed to -e");
+                            std::process::exit(1);
+                        },
+                    }
+                },
                 Some('h') => help(),
+                Some('o') => {
+                    if short_args.next().is_some() {
+                        eprintln!("arcc: -o requires a value");
+                        std::process::exit(1);
+                    }
+
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: -o requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    out_extension = Some(arg);
+                },
                 Some('s') => {
                     if short_args.next().is_some() {
                         eprintln!("arcc: -s requires a value");
@@ -133,7 +325,47 @@ fn main() {
                         std::process::exit(1);
                     }
                 },
+                Some('t') => {
+                    if short_args.next().is_some() {
+                        eprintln!("arcc: -t requires a value");
+                        std::process::exit(1);
+                    }
+
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: -t requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    target = Some(arg);
+                },
                 Some('v') => short_version(),
+                Some('w') => {
+                    if short_args.next().is_some() {
+                        eprintln!("arcc: unknown arguemnt '{full_arg}'");
+                        std::process::exit(1);
+                    }
+
+                    watch = true;
+                },
+                Some('x') => {
+                    if short_args.next().is_some() {
+                        eprintln!("arcc: -x requires a value");
+                        std::process::exit(1);
+                    }
+
+                    let arg = match args.next() {
+                        Some(arg) => arg,
+                        None => {
+                            eprintln!("arcc: -x requires a value");
+                            std::process::exit(1);
+                        },
+                    };
+
+                    extensions.push(arg);
+                },
                 Some(short_arg) => {
                     eprintln!("arcc: unknown arguemnt '-{short_arg}'");
                     std::process::exit(1);
@@ -142,11 +374,27 @@ fn main() {
             }
         }
         // just a hyphen, signals read from stdin
-        else if full_arg.starts_with('-') {
+        else if !positional && full_arg.starts_with('-') {
+            if build {
+                eprintln!("arcc: build cannot read from stdin");
+                std::process::exit(1);
+            }
+
             read_stdin = true;
 
             break
         }
+        else if build {
+            if build_dirs.len() == 2 {
+                eprintln!("arcc: trailing arguments: {full_arg}");
+                std::process::exit(1);
+            }
+
+            build_dirs.push(PathBuf::from(full_arg));
+        }
+        else if !positional && full_arg == "build" {
+            build = true;
+        }
         else {
             if path.is_some() {
                 eprintln!("arcc: cannot include more than one path");
@@ -159,7 +407,48 @@ fn main() {
         }
     }
 
-    if path.is_none() && !read_stdin {
+    if build && deps {
+        eprintln!("arcc: dependencies cannot be printed by build");
+        std::process::exit(1);
+    }
+    else if build {
+        if build_dirs.len() != 2 {
+            eprintln!("arcc: build requires a <SRC_DIR> and an <OUT_DIR>");
+            std::process::exit(1);
+        }
+
+        let out = build_dirs.pop().unwrap();
+        let src = build_dirs.pop().unwrap();
+
+        let mut build = Build::new(src, out);
+        for ext in extensions.iter() {
+            build.add_extension(ext);
+        }
+
+        if let Some(ext) = out_extension {
+            build.set_out_extension(ext);
+        }
+
+        if watch {
+            if let Err(e) = build.watch(&ctx, report) {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        else {
+            let summary = build.run(&ctx);
+            report(&summary);
+
+            if !summary.failures.is_empty() {
+                std::process::exit(1);
+            }
+        }
+    }
+    else if !extensions.is_empty() || out_extension.is_some() {
+        eprintln!("arcc: extensions are only used by build");
+        std::process::exit(1);
+    }
+    else if path.is_none() && !read_stdin {
         eprintln!("arcc: path must be defined");
         std::process::exit(1);
     }
@@ -174,6 +463,45 @@ fn main() {
         eprintln!("arcc: trailing arguments: {}", trailing.join(" "));
         std::process::exit(1);
     }
+    else if watch && read_stdin {
+        eprintln!("arcc: cannot watch stdin");
+        std::process::exit(1);
+    }
+    else if watch && deps {
+        eprintln!("arcc: dependencies cannot be printed while watching");
+        std::process::exit(1);
+    }
+    else if watch {
+        if let Err(e) = watch_file(path.unwrap(), ctx) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+    else if !deps && target.is_some() {
+        eprintln!("arcc: a target is only used by deps");
+        std::process::exit(1);
+    }
+    else if deps {
+        let (result, target) = if read_stdin {
+            let Some(target) = target else {
+                eprintln!("arcc: deps requires a target when reading from stdin");
+                std::process::exit(1);
+            };
+
+            (Arcana::compile_with_ctx_report(io::stdin(), io::sink(), ctx), target)
+        }
+        else {
+            let path = path.unwrap();
+            let target = target.unwrap_or_else(|| path.with_extension("").to_string_lossy().into_owned());
+
+            (Arcana::compile_file_with_ctx_report(path, io::sink(), ctx), target)
+        };
+
+        if let Err(e) = result.and_then(|report| print_deps(target, &report)) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
     else if read_stdin {
         if let Err(e) = Arcana::compile_to_stdout_with_ctx(io::stdin(), ctx) {
             eprintln!("{e}");
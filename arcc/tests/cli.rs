@@ -0,0 +1,74 @@
+use std::{
+    env::temp_dir,
+    fs::{ read_to_string, remove_dir_all, },
+    io::Write,
+    process::{ Command, Stdio, },
+};
+
+fn arcc() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_arcc"))
+}
+
+#[test]
+fn build_1() {
+    let out = temp_dir().join(format!("arcc-build-1-{}", std::process::id()));
+    let _ = remove_dir_all(&out);
+
+    let output = arcc()
+        .args(["build", "./resources/build_1/src"])
+        .arg(&out)
+        .output()
+        .unwrap();
+
+    // the rest of the build is written, but the failure is the exit code
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("arcc: compiled 2 template(s), copied 2 file(s), 1 failure(s)\n"));
+    assert_eq!("<p>First</p>\n", read_to_string(out.join("posts/first")).unwrap());
+
+    remove_dir_all(out).unwrap();
+}
+
+#[test]
+fn path_1() {
+    // a template named 'build' follows '--'
+    let output = arcc()
+        .current_dir("./resources/build_2")
+        .args(["--", "build"])
+        .output()
+        .unwrap();
+
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("<p>Build</p>\n", String::from_utf8(output.stdout).unwrap());
+
+    let output = arcc()
+        .current_dir("./resources/build_2")
+        .arg("build")
+        .output()
+        .unwrap();
+
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "arcc: build requires a <SRC_DIR> and an <OUT_DIR>\n",
+        String::from_utf8(output.stderr).unwrap(),
+    );
+}
+
+#[test]
+fn escape_1() {
+    // values are escaped as html unless another mode is chosen
+    for (args, expected) in [ (vec![], "&lt;b&gt;|<b>"), (vec![ "-e", "none", ], "<b>|<b>"), ] {
+        let mut child = arcc()
+            .args(args)
+            .args([ "-s", ":name:<b>", "-", ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"{{ name }}|{{{ name }}}").unwrap();
+
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(Some(0), output.status.code());
+        assert_eq!(expected, String::from_utf8(output.stdout).unwrap());
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod test;
+
+use {
+    arcana_core::{ Context, InternalResult, Template, Templates, },
+    std::{
+        collections::{ BTreeMap, HashMap, },
+        fmt::Debug,
+        fs::metadata,
+        io::Write,
+        path::{ Path, PathBuf, },
+        sync::{ Arc, Mutex, },
+        time::SystemTime,
+    },
+};
+
+/// A parsed GET template along with the templates it read while rendering and
+/// the modification time of every file and directory it read.
+#[derive(Clone)]
+struct Entry {
+    template: Arc<Template>,
+    templates: Templates,
+    modified: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+/// Parsed GET templates shared by every worker. A template is parsed again,
+/// along with everything it reads, once the modification time of any file or
+/// directory it read changes.
+#[derive(Default)]
+pub struct TemplateCache {
+    disabled: bool,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+/// The modification time of a file or directory, none when it does not exist.
+fn modified(path: &Path) -> Option<SystemTime> {
+    metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Entry {
+    fn parse(path: &Path) -> InternalResult<Self> {
+        // taken before parsing so that a change made meanwhile is not missed
+        let mut times = BTreeMap::new();
+        times.insert(path.to_owned(), modified(path));
+
+        Ok(Self {
+            template: Arc::new(Template::parse_file(path)?),
+            templates: Templates::default(),
+            modified: times,
+        })
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.modified.iter().all(|(path, time)| modified(path) == *time)
+    }
+}
+
+impl TemplateCache {
+    /// A cache which parses every template on every render.
+    pub fn disabled() -> Self {
+        Self { disabled: true, ..Self::default() }
+    }
+
+    pub fn render<P, W>(&self, path: P, ctx: &Context, output: &mut W) -> InternalResult<()>
+    where
+        P: AsRef<Path>,
+        W: Write + Debug,
+    {
+        let path = path.as_ref();
+        if self.disabled {
+            return Template::parse_file(path)?.render(ctx, output);
+        }
+
+        let mut entry = self.get(path)?;
+        let started = SystemTime::now();
+        let report = entry.template.render_with(ctx, output, &mut entry.templates)?;
+
+        // what a template reads may depend on the context, so the files and
+        // directories of every render are kept
+        for read in report.files().iter().chain(report.dirs()) {
+            entry.modified.entry(read.to_owned()).or_insert_with(|| modified(read));
+        }
+
+        // a file or directory changed during the render may have been read
+        // before the change, so the entry is parsed again next time rather
+        // than kept with a time which claims it is fresh
+        let finished = SystemTime::now();
+        let changed = entry.modified.values().flatten()
+            .any(|time| (started..=finished).contains(time));
+        if !changed {
+            self.entries.lock().unwrap().insert(path.to_owned(), entry);
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, path: &Path) -> InternalResult<Entry> {
+        // checked and parsed without holding the lock so other routes are not
+        // blocked
+        let cached = self.entries.lock().unwrap().get(path).cloned();
+        match cached.filter(Entry::is_fresh) {
+            Some(entry) => Ok(entry),
+            None => Entry::parse(path),
+        }
+    }
+}
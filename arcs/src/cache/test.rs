@@ -0,0 +1,160 @@
+use {
+    super::TemplateCache,
+    arcana_core::Context,
+    std::{
+        fs::{ File, write, },
+        io::{ self, Write, },
+        path::Path,
+        sync::Arc,
+        time::{ Duration, SystemTime, },
+    },
+    tempfile::tempdir,
+};
+
+fn render(cache: &TemplateCache, path: &Path) -> String {
+    let mut output = Vec::<u8>::new();
+    cache.render(path, &Context::default(), &mut output).unwrap();
+
+    String::from_utf8(output).unwrap()
+}
+
+/// Write a file and move its modification time forward, so that the change is
+/// seen however coarse the clock of the file system is.
+fn rewrite(path: &Path, content: &str) {
+    write(path, content).unwrap();
+    File::options().write(true).open(path).unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+}
+
+#[test]
+fn miss_1() {
+    let dir = tempdir().unwrap();
+    let page = dir.path().join("page.arct");
+    write(&page, "Hello").unwrap();
+
+    let cache = TemplateCache::default();
+    assert!(cache.entries.lock().unwrap().is_empty());
+
+    assert_eq!("Hello", render(&cache, &page));
+    assert!(cache.entries.lock().unwrap().contains_key(&page));
+}
+
+#[test]
+fn hit_1() {
+    let dir = tempdir().unwrap();
+    let page = dir.path().join("page.arct");
+    write(&page, "{% call \"./part.arct\" /%}").unwrap();
+    write(dir.path().join("part.arct"), "part").unwrap();
+
+    let cache = TemplateCache::default();
+    assert_eq!("part", render(&cache, &page));
+    let first = cache.entries.lock().unwrap()[&page].clone();
+    assert_eq!(1, first.templates.len());
+
+    assert_eq!("part", render(&cache, &page));
+    let second = cache.entries.lock().unwrap()[&page].clone();
+
+    // neither the template nor the one it calls is parsed again
+    assert!(Arc::ptr_eq(&first.template, &second.template));
+    let part = dir.path().join("./part.arct");
+    assert!(Arc::ptr_eq(
+        &first.templates.get(&part).unwrap(),
+        &second.templates.get(&part).unwrap(),
+    ));
+}
+
+#[test]
+fn invalidate_1() {
+    let dir = tempdir().unwrap();
+    let page = dir.path().join("page.arct");
+    write(&page, "Hello").unwrap();
+
+    let cache = TemplateCache::default();
+    assert_eq!("Hello", render(&cache, &page));
+
+    rewrite(&page, "Bye");
+    assert_eq!("Bye", render(&cache, &page));
+}
+
+#[test]
+fn invalidate_2() {
+    let dir = tempdir().unwrap();
+    let page = dir.path().join("page.arct");
+    let layout = dir.path().join("layout.arct");
+    write(&page, "{% extend \"./layout.arct\" /%}page").unwrap();
+    write(&layout, "<main>{{ CONTENT }}</main>").unwrap();
+
+    let cache = TemplateCache::default();
+    assert_eq!("<main>page</main>", render(&cache, &page));
+    let first = cache.entries.lock().unwrap()[&page].clone();
+
+    rewrite(&layout, "<body>{{ CONTENT }}</body>");
+    assert_eq!("<body>page</body>", render(&cache, &page));
+    let second = cache.entries.lock().unwrap()[&page].clone();
+
+    assert!(!Arc::ptr_eq(&first.template, &second.template));
+}
+
+#[test]
+fn invalidate_3() {
+    let dir = tempdir().unwrap();
+    let page = dir.path().join("page.arct");
+    let entries = dir.path().join("entries");
+    std::fs::create_dir(&entries).unwrap();
+    write(entries.join("a.txt"), "").unwrap();
+    write(&page, "{% forfile file in \"./entries\" %}+{% /forfile %}").unwrap();
+
+    let cache = TemplateCache::default();
+    assert_eq!("+", render(&cache, &page));
+
+    write(entries.join("b.txt"), "").unwrap();
+    File::open(&entries).unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+    assert_eq!("++", render(&cache, &page));
+}
+
+/// An output which touches a file whenever it is written to, as if the file
+/// changed during the render.
+#[derive(Debug)]
+struct Touching<'a>(&'a Path, Vec<u8>);
+
+impl Write for Touching<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        File::options().write(true).open(self.0)?.set_modified(SystemTime::now())?;
+        self.1.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn changed_1() {
+    let dir = tempdir().unwrap();
+    let page = dir.path().join("page.arct");
+    let part = dir.path().join("part.txt");
+    write(&page, "{% include \"./part.txt\" /%}").unwrap();
+    write(&part, "part").unwrap();
+
+    let cache = TemplateCache::default();
+    let mut output = Touching(&part, Vec::new());
+    cache.render(&page, &Context::default(), &mut output).unwrap();
+    assert_eq!(b"part", &output.1[..]);
+
+    // the file changed during the render, so the entry is not kept
+    assert!(!cache.entries.lock().unwrap().contains_key(&page));
+}
+
+#[test]
+fn disabled_1() {
+    let dir = tempdir().unwrap();
+    let page = dir.path().join("page.arct");
+    write(&page, "Hello").unwrap();
+
+    let cache = TemplateCache::disabled();
+    assert_eq!("Hello", render(&cache, &page));
+    assert!(cache.entries.lock().unwrap().is_empty());
+}
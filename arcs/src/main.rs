@@ -1,4 +1,7 @@
+mod cache;
+
 use {
+    crate::cache::TemplateCache,
     axum::{
         extract::{ DefaultBodyLimit, Form, Multipart, Query, },
         http::{ header, HeaderMap, StatusCode, },
@@ -16,7 +19,7 @@ use {
         sync::OnceLock,
     },
     tempfile::TempDir,
-    arcana_core::{ Context, Arcana },
+    arcana_core::{ Context, Escape, },
     tokio::net::TcpListener,
 };
 
@@ -90,6 +93,9 @@ fn base26(mut dec: usize) -> String {
     out
 }
 
+static CACHE: OnceLock<TemplateCache> = OnceLock::new();
+static CONTEXT: OnceLock<Context> = OnceLock::new();
+static ESCAPE: OnceLock<Escape> = OnceLock::new();
 static VARS: OnceLock<Vec<(String, String)>> = OnceLock::new();
 static NAME: OnceLock<String> = OnceLock::new();
 
@@ -108,11 +114,17 @@ fn main() {
     let mut urlencodeds = Vec::new();
     let mut multiparts = Vec::new();
     let mut variables = Vec::new();
+    let mut context = Context::default();
     let mut workers = 0_usize;
+    let mut no_cache = false;
 
     while let Some(long_arg) = long_args.next() {
         if let Some(long_arg) = long_arg.strip_prefix("--") {
             match long_arg {
+                "config" => context.add_config_file(long_args.next().unwrap()).unwrap(),
+                "escape" => {
+                    ESCAPE.get_or_init(|| long_args.next().unwrap().parse::<Escape>().unwrap());
+                },
                 "get" => gets.push(from_dkv(long_args.next().unwrap())),
                 "help" => {
                     println!("{HELP}");
@@ -121,6 +133,7 @@ fn main() {
                 "ip" => ip = long_args.next().unwrap(),
                 "max-multipart-size" => max_multipart_size = long_args.next().unwrap().parse::<usize>().unwrap(),
                 "multipart" => multiparts.push(from_dkv(long_args.next().unwrap())),
+                "no-cache" => no_cache = true,
                 "name" => {
                     NAME.get_or_init(|| long_args.next().unwrap());
                 },
@@ -138,6 +151,14 @@ fn main() {
 
             while let Some(short_arg) = short_args.next() {
                 match short_arg {
+                    'c' => {
+                        assert!(short_args.next().is_none());
+                        context.add_config_file(long_args.next().unwrap()).unwrap();
+                    },
+                    'e' => {
+                        assert!(short_args.next().is_none());
+                        ESCAPE.get_or_init(|| long_args.next().unwrap().parse::<Escape>().unwrap());
+                    },
                     'g' => {
                         assert!(short_args.next().is_none());
                         gets.push(from_dkv(long_args.next().unwrap()));
@@ -192,6 +213,8 @@ fn main() {
         }
     }
 
+    CACHE.get_or_init(|| if no_cache { TemplateCache::disabled() } else { TemplateCache::default() });
+    CONTEXT.get_or_init(move || context);
     VARS.get_or_init(move || variables);
 
     let mut app  = Router::new();
@@ -202,7 +225,9 @@ fn main() {
         app = app.route(route, get(async |query: Query<BTreeMap<String, String>>| -> (StatusCode, HeaderMap, Vec<u8>) {
             let mut output = Vec::<u8>::new();
 
-            let mut context = Context::default();
+            let mut context = CONTEXT.get_or_init(Context::default).clone();
+            context.set_escape(*ESCAPE.get_or_init(Escape::default));
+
             for (key, value) in query.iter() {
                 context.add_variable(format!("params.{key}"), "", value);
             }
@@ -211,7 +236,7 @@ fn main() {
                 context.add_variable(key, "", value);
             }
 
-            match Arcana::compile_file_with_ctx(path, &mut output, context) {
+            match CACHE.get_or_init(TemplateCache::default).render(path, &context, &mut output) {
                 Ok(_) => {
                     let mut headers = HeaderMap::new();
                     headers.insert(header::CONTENT_TYPE, "text/html".parse().unwrap());